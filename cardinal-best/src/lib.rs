@@ -1,13 +1,26 @@
 pub use coord_2d::{Coord, Size};
 pub use direction::CardinalDirection;
-pub use grid_search_cardinal_common::{coord::UnitCoord, path::Path, step::Step};
+pub use grid_search_cardinal_common::storage::{Dense, Sparse, Storage};
 use grid_search_cardinal_common::{
+    can_enter::CanEnter,
     coord::UNIT_COORDS,
+    min_heap::MinHeapNode,
     seen_set::{SeenSet, Visit},
 };
+pub use grid_search_cardinal_common::{
+    can_enter::{Portal, Portals},
+    clearance::{AgentClearance, ClearanceMap},
+    coord::UnitCoord,
+    error::SearchError,
+    observer::{Observer, SearchStats},
+    path::{Move, Path, PortalPath, PortalPathNode},
+    step::Step,
+    trace::{Trace, TraceEvent},
+    wrap::Wrap,
+};
 #[cfg(feature = "serialize")]
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{BinaryHeap, VecDeque};
 
 pub type Depth = u32;
 
@@ -16,22 +29,30 @@ struct Node {
     depth: Depth,
 }
 
-pub struct Context {
-    seen_set: SeenSet,
+// shallowest cell first
+type PortalNode = MinHeapNode<Depth, Coord>;
+
+pub struct Context<S: Storage = Dense> {
+    seen_set: SeenSet<S>,
     queue: VecDeque<Node>,
+    portal_queue: BinaryHeap<PortalNode>,
 }
 
 #[cfg(feature = "serialize")]
 impl Serialize for Context {
     fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
-        self.seen_set.size().serialize(s)
+        (self.seen_set.size(), self.seen_set.wrap()).serialize(s)
     }
 }
 
 #[cfg(feature = "serialize")]
 impl<'a> Deserialize<'a> for Context {
     fn deserialize<D: serde::Deserializer<'a>>(d: D) -> Result<Self, D::Error> {
-        Deserialize::deserialize(d).map(Self::new)
+        Deserialize::deserialize(d).map(|(size, wrap)| {
+            let mut context = Self::new(size);
+            context.set_wrap(wrap);
+            context
+        })
     }
 }
 
@@ -44,25 +65,95 @@ pub trait BestSearch {
     fn best_coord(&self) -> Option<Coord>;
 }
 
-impl Context {
+/// Restricts a `BestSearch` to the cells where a large agent fits. Cells without enough
+/// clearance are skipped before `best_search` sees them.
+pub struct WithClearance<'a, B: BestSearch> {
+    best_search: B,
+    clearance: AgentClearance<'a>,
+}
+
+impl<'a, B: BestSearch> WithClearance<'a, B> {
+    pub fn new(best_search: B, clearance: AgentClearance<'a>) -> Self {
+        Self { best_search, clearance }
+    }
+}
+
+impl<'a, B: BestSearch> BestSearch for WithClearance<'a, B> {
+    fn is_at_max_depth(&self, depth: Depth) -> bool {
+        self.best_search.is_at_max_depth(depth)
+    }
+    fn can_enter_initial_updating_best(&mut self, start_coord: Coord) -> bool {
+        self.clearance.can_enter(start_coord) && self.best_search.can_enter_initial_updating_best(start_coord)
+    }
+    fn can_step_updating_best(&mut self, step: Step) -> bool {
+        self.clearance.can_step(step) && self.best_search.can_step_updating_best(step)
+    }
+    fn best_coord(&self) -> Option<Coord> {
+        self.best_search.best_coord()
+    }
+}
+
+impl Context<Dense> {
     pub fn new(size: Size) -> Self {
         Self {
             seen_set: SeenSet::new(size),
             queue: VecDeque::new(),
+            portal_queue: BinaryHeap::new(),
         }
     }
 
-    fn consider<B: BestSearch>(&mut self, best_search: &mut B, step: Step, depth: Depth) {
+    /// Makes searches treat the grid as wrapping around along some axes. Paths may then cross
+    /// the seams, and every coordinate passed to the `BestSearch` or in a `Path` is normalized
+    /// to lie within the grid.
+    pub fn set_wrap(&mut self, wrap: Wrap) {
+        self.seen_set.set_wrap(wrap);
+    }
+}
+
+impl Context<Sparse> {
+    /// A context for searching worlds with no bounds, where coordinates can be any `i32`. The
+    /// `BestSearch` must limit the depth if infinitely many cells are reachable.
+    pub fn new_unbounded() -> Self {
+        Self {
+            seen_set: SeenSet::new_unbounded(),
+            queue: VecDeque::new(),
+            portal_queue: BinaryHeap::new(),
+        }
+    }
+}
+
+impl<S: Storage> Context<S> {
+    /// The size of the grid, or `None` if the context is unbounded
+    pub fn bounds(&self) -> Option<Size> {
+        self.seen_set.bounds()
+    }
+
+    pub fn wrap(&self) -> Wrap {
+        self.seen_set.wrap()
+    }
+
+    fn consider<B: BestSearch, O: Observer>(
+        &mut self,
+        best_search: &mut B,
+        step: Step,
+        depth: Depth,
+        observer: &mut O,
+    ) {
+        let step = Step {
+            to_coord: self.seen_set.normalize(step.to_coord),
+            in_direction: step.in_direction,
+        };
         if let Some(Visit) = self.seen_set.try_visit_step(step, depth) {
-            if best_search.can_step_updating_best(step) {
-                if !best_search.is_at_max_depth(depth) {
-                    self.queue.push_back(Node { step, depth });
-                }
+            observer.visit(step.to_coord);
+            if best_search.can_step_updating_best(step) && !best_search.is_at_max_depth(depth) {
+                observer.push(step.to_coord);
+                self.queue.push_back(Node { step, depth });
+                observer.queue_len(self.queue.len());
             }
         }
     }
 
-    fn best_search_core<B: BestSearch>(&mut self, best_search: &mut B, start: Coord) {
+    fn best_search_core<B: BestSearch, O: Observer>(&mut self, best_search: &mut B, start: Coord, observer: &mut O) {
         self.seen_set.init(start);
         self.queue.clear();
         if !best_search.can_enter_initial_updating_best(start) {
@@ -76,29 +167,197 @@ impl Context {
                 to_coord: start + in_direction.to_coord(),
                 in_direction,
             };
-            self.consider(best_search, step, 1);
+            self.consider(best_search, step, 1, observer);
         }
         if best_search.is_at_max_depth(1) {
             return;
         }
         while let Some(Node { step, depth }) = self.queue.pop_front() {
+            observer.expand(step.to_coord);
             let next_depth = depth + 1;
-            self.consider(best_search, step.forward(), next_depth);
-            self.consider(best_search, step.left(), next_depth);
-            self.consider(best_search, step.right(), next_depth);
+            self.consider(best_search, step.forward(), next_depth, observer);
+            self.consider(best_search, step.left(), next_depth, observer);
+            self.consider(best_search, step.right(), next_depth, observer);
         }
     }
 
-    pub fn best_search_path<B: BestSearch>(&mut self, mut best_search: B, start: Coord, path: &mut Path) {
-        self.best_search_core(&mut best_search, start);
+    pub fn best_search_path<B: BestSearch>(
+        &mut self,
+        best_search: B,
+        start: Coord,
+        path: &mut Path,
+    ) -> Result<(), SearchError> {
+        self.best_search_path_with_observer(best_search, start, path, &mut ())
+    }
+
+    pub fn best_search_path_with_observer<B: BestSearch, O: Observer>(
+        &mut self,
+        mut best_search: B,
+        start: Coord,
+        path: &mut Path,
+        observer: &mut O,
+    ) -> Result<(), SearchError> {
+        self.seen_set.check_bounds(&[start], &[])?;
+        self.best_search_core(&mut best_search, start, observer);
         let end = best_search.best_coord().unwrap_or(start);
         self.seen_set.build_path_to(end, path);
+        observer.path_cost(self.seen_set.cost(end).unwrap_or(0));
+        Ok(())
+    }
+
+    pub fn best_search_first<B: BestSearch>(
+        &mut self,
+        best_search: B,
+        start: Coord,
+    ) -> Result<Option<CardinalDirection>, SearchError> {
+        self.best_search_first_with_observer(best_search, start, &mut ())
+    }
+
+    pub fn best_search_first_with_observer<B: BestSearch, O: Observer>(
+        &mut self,
+        mut best_search: B,
+        start: Coord,
+        observer: &mut O,
+    ) -> Result<Option<CardinalDirection>, SearchError> {
+        self.seen_set.check_bounds(&[start], &[])?;
+        self.best_search_core(&mut best_search, start, observer);
+        let end = best_search.best_coord().unwrap_or(start);
+        observer.path_cost(self.seen_set.cost(end).unwrap_or(0));
+        Ok(self.seen_set.first_direction_towards(end))
+    }
+    fn consider_with_portals<B: BestSearch, O: Observer>(
+        &mut self,
+        best_search: &mut B,
+        from_coord: Coord,
+        node: PortalPathNode,
+        depth: Depth,
+        observer: &mut O,
+    ) {
+        // a portal may skip past the max depth
+        if best_search.is_at_max_depth(depth - 1) {
+            return;
+        }
+        let to_coord = self.seen_set.normalize(node.to_coord);
+        let step = match node.via {
+            Move::Step(direction) => Some(Step {
+                to_coord,
+                in_direction: UnitCoord::from_cardinal_direction(direction),
+            }),
+            Move::Portal => None,
+        };
+        let visit = match step {
+            Some(step) => self.seen_set.try_visit_step(step, depth),
+            None => self.seen_set.try_visit_portal(from_coord, to_coord, depth),
+        };
+        if let Some(Visit) = visit {
+            observer.visit(to_coord);
+            let can_enter = match step {
+                Some(step) => best_search.can_step_updating_best(step),
+                None => best_search.can_enter_initial_updating_best(to_coord),
+            };
+            if can_enter && !best_search.is_at_max_depth(depth) {
+                observer.push(to_coord);
+                self.portal_queue.push(PortalNode {
+                    key: depth,
+                    value: to_coord,
+                });
+                observer.queue_len(self.portal_queue.len());
+            }
+        }
+    }
+
+    // Taking a portal adds its cost to the depth (at least 1), so cells are expanded in order of
+    // depth rather than breadth-first
+    fn best_search_portal_core<B: BestSearch, P: Portals, O: Observer>(
+        &mut self,
+        best_search: &mut B,
+        portals: &P,
+        start: Coord,
+        observer: &mut O,
+    ) {
+        self.seen_set.init(start);
+        self.portal_queue.clear();
+        if !best_search.can_enter_initial_updating_best(start) || best_search.is_at_max_depth(0) {
+            return;
+        }
+        self.portal_queue.push(PortalNode { key: 0, value: start });
+        while let Some(PortalNode {
+            key: depth,
+            value: coord,
+        }) = self.portal_queue.pop()
+        {
+            if self.seen_set.cost(coord) != Some(depth) {
+                // a shallower route to this cell was found after this node was queued
+                continue;
+            }
+            observer.expand(coord);
+            for &in_direction in &UNIT_COORDS {
+                let node = PortalPathNode {
+                    to_coord: coord + in_direction.to_coord(),
+                    via: Move::Step(in_direction.to_cardinal_direction()),
+                };
+                self.consider_with_portals(best_search, coord, node, depth + 1, observer);
+            }
+            portals.for_each_portal(coord, |portal| {
+                let node = PortalPathNode {
+                    to_coord: portal.to_coord,
+                    via: Move::Portal,
+                };
+                let depth = depth.saturating_add(portal.cost.max(1));
+                self.consider_with_portals(best_search, coord, node, depth, observer);
+            });
+        }
+    }
+
+    /// Best search through a world with portals. Cells reached through a portal are checked with
+    /// `can_enter_initial_updating_best`.
+    pub fn best_search_portal_path<B: BestSearch, P: Portals>(
+        &mut self,
+        best_search: B,
+        portals: &P,
+        start: Coord,
+        path: &mut PortalPath,
+    ) -> Result<(), SearchError> {
+        self.best_search_portal_path_with_observer(best_search, portals, start, path, &mut ())
+    }
+
+    pub fn best_search_portal_path_with_observer<B: BestSearch, P: Portals, O: Observer>(
+        &mut self,
+        mut best_search: B,
+        portals: &P,
+        start: Coord,
+        path: &mut PortalPath,
+        observer: &mut O,
+    ) -> Result<(), SearchError> {
+        self.seen_set.check_bounds(&[start], &[])?;
+        self.best_search_portal_core(&mut best_search, portals, start, observer);
+        let end = best_search.best_coord().unwrap_or(start);
+        self.seen_set.build_portal_path_to(end, path);
+        observer.path_cost(self.seen_set.cost(end).unwrap_or(0));
+        Ok(())
+    }
+
+    pub fn best_search_portal_first<B: BestSearch, P: Portals>(
+        &mut self,
+        best_search: B,
+        portals: &P,
+        start: Coord,
+    ) -> Result<Option<PortalPathNode>, SearchError> {
+        self.best_search_portal_first_with_observer(best_search, portals, start, &mut ())
     }
 
-    pub fn best_search_first<B: BestSearch>(&mut self, mut best_search: B, start: Coord) -> Option<CardinalDirection> {
-        self.best_search_core(&mut best_search, start);
+    pub fn best_search_portal_first_with_observer<B: BestSearch, P: Portals, O: Observer>(
+        &mut self,
+        mut best_search: B,
+        portals: &P,
+        start: Coord,
+        observer: &mut O,
+    ) -> Result<Option<PortalPathNode>, SearchError> {
+        self.seen_set.check_bounds(&[start], &[])?;
+        self.best_search_portal_core(&mut best_search, portals, start, observer);
         let end = best_search.best_coord().unwrap_or(start);
-        self.seen_set.first_direction_towards(end)
+        observer.path_cost(self.seen_set.cost(end).unwrap_or(0));
+        Ok(self.seen_set.first_portal_path_node_towards(end))
     }
 }
 
@@ -223,14 +482,49 @@ mod test {
         let Test { grid, start } = str_slice_to_test(GRID_A);
         let mut ctx = Context::new(grid.size());
         let mut path = Path::default();
-        ctx.best_search_path(ConstrainedSearch::new(100, &grid), start, &mut path);
+        ctx.best_search_path(ConstrainedSearch::new(100, &grid), start, &mut path)
+            .unwrap();
         assert_eq!(path.len(), 13);
-        ctx.best_search_path(ConstrainedSearch::new(10, &grid), start, &mut path);
+        ctx.best_search_path(ConstrainedSearch::new(10, &grid), start, &mut path)
+            .unwrap();
         assert_eq!(path.len(), 4);
-        ctx.best_search_path(ConstrainedSearch::new(3, &grid), start, &mut path);
+        ctx.best_search_path(ConstrainedSearch::new(3, &grid), start, &mut path)
+            .unwrap();
         assert_eq!(path.len(), 0);
     }
 
+    #[test]
+    fn wrap() {
+        let Test { grid, start } = str_slice_to_test(GRID_A);
+        let mut ctx = Context::new(grid.size());
+        ctx.set_wrap(Wrap::Both);
+        let mut path = Path::default();
+        ctx.best_search_path(ConstrainedSearch::new(100, &grid), start, &mut path)
+            .unwrap();
+        assert_eq!(path.len(), 7);
+        assert!(path.iter().all(|node| grid.size().is_valid(node.to_coord)));
+        assert_eq!(path.iter().last().map(|node| node.to_coord), Some(Coord::new(7, 1)));
+    }
+
+    #[test]
+    fn observer() {
+        let Test { grid, start } = str_slice_to_test(GRID_A);
+        let mut ctx = Context::new(grid.size());
+        let mut path = Path::default();
+        let mut stats = SearchStats::default();
+        ctx.best_search_path_with_observer(ConstrainedSearch::new(100, &grid), start, &mut path, &mut stats)
+            .unwrap();
+        assert_eq!(path.len(), 13);
+        assert_eq!(stats.path_cost, Some(13));
+        assert_eq!(stats.cells_visited, grid.size().count() as u64 - 1);
+        assert_eq!(stats.nodes_pushed, stats.cells_visited);
+        assert_eq!(stats.nodes_expanded, stats.nodes_pushed);
+        let mut first_stats = SearchStats::default();
+        ctx.best_search_first_with_observer(ConstrainedSearch::new(100, &grid), start, &mut first_stats)
+            .unwrap();
+        assert_eq!(first_stats, stats);
+    }
+
     const GRID_B: &[&str] = &[
         "....#.....",
         ".@........",
@@ -249,11 +543,14 @@ mod test {
         let Test { grid, start } = str_slice_to_test(GRID_B);
         let mut ctx = Context::new(grid.size());
         let mut path = Path::default();
-        ctx.best_search_path(ConstrainedSearch::new(100, &grid), start, &mut path);
+        ctx.best_search_path(ConstrainedSearch::new(100, &grid), start, &mut path)
+            .unwrap();
         assert_eq!(path.len(), 33);
-        ctx.best_search_path(ConstrainedSearch::new(30, &grid), start, &mut path);
+        ctx.best_search_path(ConstrainedSearch::new(30, &grid), start, &mut path)
+            .unwrap();
         assert_eq!(path.len(), 20);
-        ctx.best_search_path(ConstrainedSearch::new(3, &grid), start, &mut path);
+        ctx.best_search_path(ConstrainedSearch::new(3, &grid), start, &mut path)
+            .unwrap();
         assert_eq!(path.len(), 0);
     }
 
@@ -275,11 +572,14 @@ mod test {
         let Test { grid, start } = str_slice_to_test(GRID_C);
         let mut ctx = Context::new(grid.size());
         let mut path = Path::default();
-        ctx.best_search_path(ConstrainedSearch::new(100, &grid), start, &mut path);
+        ctx.best_search_path(ConstrainedSearch::new(100, &grid), start, &mut path)
+            .unwrap();
         assert_eq!(path.len(), 1);
-        ctx.best_search_path(ConstrainedSearch::new(2, &grid), start, &mut path);
+        ctx.best_search_path(ConstrainedSearch::new(2, &grid), start, &mut path)
+            .unwrap();
         assert_eq!(path.len(), 1);
-        ctx.best_search_path(ConstrainedSearch::new(0, &grid), start, &mut path);
+        ctx.best_search_path(ConstrainedSearch::new(0, &grid), start, &mut path)
+            .unwrap();
         assert_eq!(path.len(), 0);
     }
 
@@ -301,11 +601,14 @@ mod test {
         let Test { grid, start } = str_slice_to_test_start_score(GRID_D, 10);
         let mut ctx = Context::new(grid.size());
         let mut path = Path::default();
-        ctx.best_search_path(ConstrainedSearch::new(100, &grid), start, &mut path);
+        ctx.best_search_path(ConstrainedSearch::new(100, &grid), start, &mut path)
+            .unwrap();
         assert_eq!(path.len(), 0);
-        ctx.best_search_path(ConstrainedSearch::new(2, &grid), start, &mut path);
+        ctx.best_search_path(ConstrainedSearch::new(2, &grid), start, &mut path)
+            .unwrap();
         assert_eq!(path.len(), 0);
-        ctx.best_search_path(ConstrainedSearch::new(0, &grid), start, &mut path);
+        ctx.best_search_path(ConstrainedSearch::new(0, &grid), start, &mut path)
+            .unwrap();
         assert_eq!(path.len(), 0);
     }
 
@@ -318,7 +621,98 @@ mod test {
         let mut path = Path::default();
         for _ in 0..num_tests {
             let Test { grid, start } = random_test(size, &mut rng);
-            ctx.best_search_path(ConstrainedSearch::new(100, &grid), start, &mut path);
+            ctx.best_search_path(ConstrainedSearch::new(100, &grid), start, &mut path)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn clearance() {
+        let Test { grid, start } = str_slice_to_test(&["#.....2...", "..1...#...", "@.........", ".........."]);
+        let world = |coord| matches!(grid.get(coord), Some(Cell::Traversable(_)));
+        let mut clearance_map = ClearanceMap::new(grid.size());
+        clearance_map.compute(&Search(world));
+        let mut ctx = Context::new(grid.size());
+        let mut path = Path::default();
+        ctx.best_search_path(ConstrainedSearch::new(100, &grid), start, &mut path)
+            .unwrap();
+        assert_eq!(path.iter().last().map(|node| node.to_coord), Some(Coord::new(6, 0)));
+        // a 2x2 agent can't fit between the top-right wall and the edge of the grid
+        let best_search = WithClearance::new(ConstrainedSearch::new(100, &grid), clearance_map.for_agent(2));
+        ctx.best_search_path(best_search, start, &mut path).unwrap();
+        assert_eq!(path.iter().last().map(|node| node.to_coord), Some(Coord::new(2, 1)));
+        let best_search = WithClearance::new(ConstrainedSearch::new(100, &grid), clearance_map.for_agent(3));
+        ctx.best_search_path(best_search, start, &mut path).unwrap();
+        assert_eq!(path.len(), 0);
+    }
+
+    struct Search<F: Fn(Coord) -> bool>(F);
+
+    impl<F: Fn(Coord) -> bool> CanEnter for Search<F> {
+        fn can_enter(&self, coord: Coord) -> bool {
+            (self.0)(coord)
+        }
+    }
+
+    struct Teleporter<'a> {
+        grid: &'a Grid<Cell>,
+        from_coord: Coord,
+        portal: Portal,
+    }
+
+    impl<'a> CanEnter for Teleporter<'a> {
+        fn can_enter(&self, coord: Coord) -> bool {
+            matches!(self.grid.get(coord), Some(Cell::Traversable(_)))
         }
     }
+
+    impl<'a> Portals for Teleporter<'a> {
+        fn for_each_portal<F: FnMut(Portal)>(&self, from_coord: Coord, mut f: F) {
+            if from_coord == self.from_coord {
+                f(self.portal);
+            }
+        }
+        fn for_each_portal_in_world<F: FnMut(Coord, Portal)>(&self, mut f: F) {
+            f(self.from_coord, self.portal);
+        }
+    }
+
+    #[test]
+    fn portals() {
+        let Test { grid, start } = str_slice_to_test(&["@.1.#...2", "....#....", "....#...."]);
+        let teleporter = Teleporter {
+            grid: &grid,
+            from_coord: Coord::new(3, 2),
+            portal: Portal {
+                to_coord: Coord::new(5, 2),
+                cost: 4,
+            },
+        };
+        let mut ctx = Context::new(grid.size());
+        let mut path = PortalPath::default();
+        ctx.best_search_portal_path(ConstrainedSearch::new(100, &grid), &teleporter, start, &mut path)
+            .unwrap();
+        assert_eq!(path.iter().last().map(|node| node.to_coord), Some(Coord::new(8, 0)));
+        assert_eq!(path.iter().filter(|node| node.via == Move::Portal).count(), 1);
+        assert_eq!(path.len(), 5 + 1 + 5);
+        // 5 steps, the portal and 5 more steps reach the best cell at depth 14
+        ctx.best_search_portal_path(ConstrainedSearch::new(14, &grid), &teleporter, start, &mut path)
+            .unwrap();
+        assert_eq!(path.iter().last().map(|node| node.to_coord), Some(Coord::new(8, 0)));
+        ctx.best_search_portal_path(ConstrainedSearch::new(13, &grid), &teleporter, start, &mut path)
+            .unwrap();
+        assert_eq!(path.iter().last().map(|node| node.to_coord), Some(Coord::new(2, 0)));
+        assert_eq!(
+            ctx.best_search_portal_first(ConstrainedSearch::new(100, &grid), &teleporter, Coord::new(3, 2)),
+            Ok(Some(PortalPathNode {
+                to_coord: Coord::new(5, 2),
+                via: Move::Portal
+            }))
+        );
+        // the same context searches without portals
+        let mut steps = Path::default();
+        ctx.best_search_path(ConstrainedSearch::new(100, &grid), start, &mut steps)
+            .unwrap();
+        assert_eq!(steps.last().map(|node| node.to_coord), Some(Coord::new(2, 0)));
+    }
 }
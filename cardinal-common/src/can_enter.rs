@@ -7,3 +7,43 @@ pub trait CanEnter {
         self.can_enter(step.to_coord)
     }
 }
+
+/// A world which can report when it changes, so that results computed from it can be reused
+/// until it does
+pub trait WorldVersion: CanEnter {
+    /// Must change whenever the result of `can_enter` or `can_step` changes for any cell
+    fn world_version(&self) -> u64;
+}
+
+pub trait StepCost: CanEnter {
+    /// Cost of taking `step`. Must be at least `min_step_cost`.
+    fn step_cost(&self, step: Step) -> u32;
+    /// Lower bound on the cost of any step, used to scale the heuristic.
+    fn min_step_cost(&self) -> u32;
+}
+
+/// A world whose obstacles move on a known schedule
+pub trait CanEnterAt {
+    /// Whether `coord` may be occupied at tick `time`
+    fn can_enter_at(&self, coord: Coord, time: u32) -> bool;
+    /// Whether `step` may be taken so as to arrive at tick `time`
+    fn can_step_at(&self, step: Step, time: u32) -> bool {
+        self.can_enter_at(step.to_coord, time)
+    }
+}
+
+/// An edge between cells which needn't be adjacent, such as a teleporter, stairs or a ladder
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Portal {
+    pub to_coord: Coord,
+    pub cost: u32,
+}
+
+/// A world with portals as well as steps between adjacent cells, each step costing 1
+pub trait Portals: CanEnter {
+    /// Calls `f` with each portal which can be taken from `from_coord`
+    fn for_each_portal<F: FnMut(Portal)>(&self, from_coord: Coord, f: F);
+    /// Calls `f` with every portal in the world and the coordinate it's taken from. Used to keep
+    /// heuristics admissible and to follow portals backwards.
+    fn for_each_portal_in_world<F: FnMut(Coord, Portal)>(&self, f: F);
+}
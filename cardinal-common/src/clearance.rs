@@ -0,0 +1,120 @@
+use crate::can_enter::CanEnter;
+use crate::step::Step;
+use grid_2d::{Coord, Grid, Size};
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// For each cell, the width of the largest square of enterable cells with that cell as its
+/// top-left corner. An agent occupying an `n` by `n` square, positioned by its top-left cell,
+/// fits wherever the clearance is at least `n`.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct ClearanceMap {
+    grid: Grid<u32>,
+}
+
+impl ClearanceMap {
+    pub fn new(size: Size) -> Self {
+        Self {
+            grid: Grid::new_copy(size, 0),
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.grid.size()
+    }
+
+    /// Cells outside the grid have no clearance
+    pub fn clearance(&self, coord: Coord) -> u32 {
+        self.grid.get(coord).cloned().unwrap_or(0)
+    }
+
+    fn compute_cell<C: CanEnter>(&self, can_enter: &C, coord: Coord) -> u32 {
+        if !can_enter.can_enter(coord) {
+            return 0;
+        }
+        let right = self.clearance(coord + Coord::new(1, 0));
+        let down = self.clearance(coord + Coord::new(0, 1));
+        let diagonal = self.clearance(coord + Coord::new(1, 1));
+        1 + right.min(down).min(diagonal)
+    }
+
+    pub fn compute<C: CanEnter>(&mut self, can_enter: &C) {
+        let size = self.grid.size();
+        for y in (0..size.height() as i32).rev() {
+            for x in (0..size.width() as i32).rev() {
+                let coord = Coord::new(x, y);
+                *self.grid.get_checked_mut(coord) = self.compute_cell(can_enter, coord);
+            }
+        }
+    }
+
+    /// Call after `can_enter` changes for `coord`. Only the cells above and to the left of
+    /// `coord` whose clearance changes are recomputed.
+    pub fn update<C: CanEnter>(&mut self, can_enter: &C, coord: Coord) {
+        let mut queue = VecDeque::new();
+        queue.push_back(coord);
+        while let Some(coord) = queue.pop_front() {
+            let clearance = self.compute_cell(can_enter, coord);
+            match self.grid.get_mut(coord) {
+                Some(cell) if *cell != clearance => *cell = clearance,
+                _ => continue,
+            }
+            queue.push_back(coord - Coord::new(1, 0));
+            queue.push_back(coord - Coord::new(0, 1));
+            queue.push_back(coord - Coord::new(1, 1));
+        }
+    }
+
+    /// A world in which an `agent_size` by `agent_size` agent can enter the cells where its
+    /// top-left corner fits
+    pub fn for_agent(&self, agent_size: u32) -> AgentClearance<'_> {
+        AgentClearance {
+            clearance_map: self,
+            agent_size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AgentClearance<'a> {
+    clearance_map: &'a ClearanceMap,
+    agent_size: u32,
+}
+
+impl<'a> AgentClearance<'a> {
+    pub fn agent_size(&self) -> u32 {
+        self.agent_size
+    }
+}
+
+impl<'a> CanEnter for AgentClearance<'a> {
+    fn can_enter(&self, coord: Coord) -> bool {
+        self.clearance_map.clearance(coord) >= self.agent_size
+    }
+}
+
+/// Restricts a world to the cells where a large agent fits. Unlike `AgentClearance` alone, the
+/// world's own `can_step` is still consulted, for worlds whose steps depend on more than which
+/// cells are solid.
+#[derive(Debug, Clone, Copy)]
+pub struct WithClearance<'a, C: CanEnter> {
+    can_enter: C,
+    clearance: AgentClearance<'a>,
+}
+
+impl<'a, C: CanEnter> WithClearance<'a, C> {
+    pub fn new(can_enter: C, clearance: AgentClearance<'a>) -> Self {
+        Self { can_enter, clearance }
+    }
+}
+
+impl<'a, C: CanEnter> CanEnter for WithClearance<'a, C> {
+    fn can_enter(&self, coord: Coord) -> bool {
+        self.clearance.can_enter(coord) && self.can_enter.can_enter(coord)
+    }
+    fn can_step(&self, step: Step) -> bool {
+        self.clearance.can_step(step) && self.can_enter.can_step(step)
+    }
+}
@@ -4,14 +4,37 @@ use grid_2d::Coord;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
+/// A non-zero offset along a single row or column
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(try_from = "Coord"))]
 #[derive(Clone, Copy, Debug)]
 pub struct CardinalCoord(Coord);
 
+#[cfg(feature = "serialize")]
+impl TryFrom<Coord> for CardinalCoord {
+    type Error = &'static str;
+    fn try_from(coord: Coord) -> Result<Self, Self::Error> {
+        Self::from_coord(coord).ok_or("offset must be non-zero and along a row or column")
+    }
+}
+
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(try_from = "CardinalCoord"))]
 #[derive(Clone, Copy, Debug)]
 pub struct UnitCoord(CardinalCoord);
 
+#[cfg(feature = "serialize")]
+impl TryFrom<CardinalCoord> for UnitCoord {
+    type Error = &'static str;
+    fn try_from(cardinal_coord: CardinalCoord) -> Result<Self, Self::Error> {
+        if cardinal_coord.magnitude() == 1 {
+            Ok(Self(cardinal_coord))
+        } else {
+            Err("offset must have a magnitude of 1")
+        }
+    }
+}
+
 pub const UNIT_COORDS: [UnitCoord; 4] = [
     UnitCoord(CardinalCoord(Coord::new(1, 0))),
     UnitCoord(CardinalCoord(Coord::new(0, -1))),
@@ -46,6 +69,9 @@ impl CardinalCoord {
     pub const fn right135(self) -> Coord {
         self.0.cardinal_right135()
     }
+    pub const fn opposite(self) -> Self {
+        Self(Coord::new(-self.0.x, -self.0.y))
+    }
     pub fn to_cardinal_direction(self) -> CardinalDirection {
         match self.0.x.cmp(&0) {
             Ordering::Equal => match self.0.y.cmp(&0) {
@@ -58,7 +84,7 @@ impl CardinalCoord {
         }
     }
     pub const fn magnitude(self) -> u32 {
-        (self.0.x + self.0.y).abs() as u32
+        (self.0.x + self.0.y).unsigned_abs()
     }
     pub fn to_unit_coord(self) -> UnitCoord {
         match self.0.x.cmp(&0) {
@@ -86,6 +112,9 @@ impl UnitCoord {
     pub const fn right135(self) -> Coord {
         self.0.right135()
     }
+    pub const fn opposite(self) -> Self {
+        Self(self.0.opposite())
+    }
     pub const fn to_coord(self) -> Coord {
         self.0.to_coord()
     }
@@ -103,3 +132,19 @@ impl UnitCoord {
         CardinalCoord(self.to_coord() * by as i32)
     }
 }
+
+#[cfg(all(test, feature = "serialize"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deserialize_checks_magnitude() {
+        let unit: UnitCoord = serde_json::from_str(r#"{"x":0,"y":-1}"#).unwrap();
+        assert_eq!(unit.to_coord(), Coord::new(0, -1));
+        assert!(serde_json::from_str::<UnitCoord>(r#"{"x":0,"y":-2}"#).is_err());
+        assert!(serde_json::from_str::<UnitCoord>(r#"{"x":1,"y":1}"#).is_err());
+        let cardinal: CardinalCoord = serde_json::from_str(r#"{"x":0,"y":-2}"#).unwrap();
+        assert_eq!(cardinal.magnitude(), 2);
+        assert!(serde_json::from_str::<CardinalCoord>(r#"{"x":0,"y":0}"#).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use grid_2d::{Coord, Size};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchError {
+    StartOutOfBounds(Coord),
+    GoalOutOfBounds(Coord),
+    /// Two grids used together in a search have different sizes
+    SizeMismatch {
+        expected: Size,
+        actual: Size,
+    },
+    /// The search visited every cell it could without reaching the goal
+    GoalUnreachable {
+        /// The cell visited by the search which is closest to the goal. Searches which run
+        /// backwards from the goal report the cell closest to the start instead.
+        closest: Coord,
+        /// The number of distinct cells visited by the search, including the start itself
+        num_reachable: u64,
+    },
+    /// A search bounded in time ran out of time before reaching the goal
+    HorizonExceeded {
+        /// The cell visited by the search which is closest to the goal
+        closest: Coord,
+    },
+    /// The distance map guiding the search doesn't reach the start, so no search was run
+    StartNotInDistanceMap(Coord),
+    /// The search can't cross the edges of a grid which wraps around
+    WrapUnsupported,
+}
+
+impl SearchError {
+    pub fn check_bounds(size: Size, starts: &[Coord], goals: &[Coord]) -> Result<(), Self> {
+        if let Some(&start) = starts.iter().find(|&&start| !size.is_valid(start)) {
+            return Err(Self::StartOutOfBounds(start));
+        }
+        if let Some(&goal) = goals.iter().find(|&&goal| !size.is_valid(goal)) {
+            return Err(Self::GoalOutOfBounds(goal));
+        }
+        Ok(())
+    }
+
+    pub fn check_size(expected: Size, actual: Size) -> Result<(), Self> {
+        if expected == actual {
+            Ok(())
+        } else {
+            Err(Self::SizeMismatch { expected, actual })
+        }
+    }
+
+    /// Describes a search which ran out of cells to visit, from the cells it visited. `visited`
+    /// should include `start`. Ties for the closest cell are broken in favour of `start`, then
+    /// by row and column.
+    pub fn goal_unreachable<I, D>(start: Coord, visited: I, distance_to_goal: D) -> Self
+    where
+        I: IntoIterator<Item = Coord>,
+        D: Fn(Coord) -> u32,
+    {
+        let mut closest = start;
+        let mut closest_key = (distance_to_goal(start), i32::MIN, i32::MIN);
+        let mut num_reachable = 0;
+        for coord in visited {
+            num_reachable += 1;
+            let key = (distance_to_goal(coord), coord.y, coord.x);
+            if key < closest_key {
+                closest = coord;
+                closest_key = key;
+            }
+        }
+        Self::GoalUnreachable { closest, num_reachable }
+    }
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::StartOutOfBounds(coord) => write!(f, "start ({}, {}) is out of bounds", coord.x, coord.y),
+            Self::GoalOutOfBounds(coord) => write!(f, "goal ({}, {}) is out of bounds", coord.x, coord.y),
+            Self::SizeMismatch { expected, actual } => write!(
+                f,
+                "expected a grid of size {}x{} but got {}x{}",
+                expected.width(),
+                expected.height(),
+                actual.width(),
+                actual.height()
+            ),
+            Self::GoalUnreachable { closest, num_reachable } => write!(
+                f,
+                "goal is unreachable after visiting {} cells, the closest being ({}, {})",
+                num_reachable, closest.x, closest.y
+            ),
+            Self::HorizonExceeded { closest } => write!(
+                f,
+                "goal wasn't reached within the time horizon, the closest cell being ({}, {})",
+                closest.x, closest.y
+            ),
+            Self::StartNotInDistanceMap(coord) => {
+                write!(f, "start ({}, {}) isn't reached by the distance map", coord.x, coord.y)
+            }
+            Self::WrapUnsupported => write!(f, "the search doesn't support grids which wrap around"),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
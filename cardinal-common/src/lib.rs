@@ -1,5 +1,13 @@
 pub mod can_enter;
+pub mod clearance;
 pub mod coord;
+pub mod error;
+pub mod line_of_sight;
+pub mod min_heap;
+pub mod observer;
 pub mod path;
 pub mod seen_set;
 pub mod step;
+pub mod storage;
+pub mod trace;
+pub mod wrap;
@@ -0,0 +1,169 @@
+use crate::{can_enter::CanEnter, path::Path};
+use grid_2d::Coord;
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineOfSight {
+    /// The cells visited by Bresenham's line algorithm
+    Bresenham,
+    /// Every cell the line between the centres of the two cells passes through. Where the line
+    /// passes exactly through a corner, both cells beside the corner must be enterable.
+    Supercover,
+}
+
+impl LineOfSight {
+    /// Returns true if all the cells on the line between `from` and `to` can be entered. The
+    /// cells at either end aren't checked.
+    pub fn is_visible<C: CanEnter>(self, can_enter: &C, from: Coord, to: Coord) -> bool {
+        match self {
+            Self::Bresenham => bresenham(can_enter, from, to),
+            Self::Supercover => supercover(can_enter, from, to),
+        }
+    }
+}
+
+fn bresenham<C: CanEnter>(can_enter: &C, from: Coord, to: Coord) -> bool {
+    let delta = to - from;
+    let step = Coord::new(delta.x.signum(), delta.y.signum());
+    let abs_delta = Coord::new(delta.x.abs(), -delta.y.abs());
+    let mut error = abs_delta.x + abs_delta.y;
+    let mut coord = from;
+    loop {
+        let double_error = 2 * error;
+        if double_error >= abs_delta.y {
+            error += abs_delta.y;
+            coord.x += step.x;
+        }
+        if double_error <= abs_delta.x {
+            error += abs_delta.x;
+            coord.y += step.y;
+        }
+        if coord == to {
+            return true;
+        }
+        if !can_enter.can_enter(coord) {
+            return false;
+        }
+    }
+}
+
+fn supercover<C: CanEnter>(can_enter: &C, from: Coord, to: Coord) -> bool {
+    let delta = to - from;
+    let step = Coord::new(delta.x.signum(), delta.y.signum());
+    let (num_x, num_y) = (delta.x.abs(), delta.y.abs());
+    let (mut x, mut y) = (0, 0);
+    let mut coord = from;
+    let is_blocked = |coord: Coord| coord != to && !can_enter.can_enter(coord);
+    while x < num_x || y < num_y {
+        // compare where the line next crosses a vertical and a horizontal cell boundary
+        let next_x = (1 + 2 * x) * num_y;
+        let next_y = (1 + 2 * y) * num_x;
+        if next_x == next_y {
+            if is_blocked(coord + Coord::new(step.x, 0)) || is_blocked(coord + Coord::new(0, step.y)) {
+                return false;
+            }
+            coord += step;
+            x += 1;
+            y += 1;
+        } else if next_x < next_y {
+            coord.x += step.x;
+            x += 1;
+        } else {
+            coord.y += step.y;
+            y += 1;
+        }
+        if is_blocked(coord) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Reduces a path to a list of cells on it, each visible from the one before (and the first
+/// from the start of the path), ending at the end of the path. Each waypoint is the last cell
+/// before the first one along the path that isn't visible from the previous waypoint.
+pub fn waypoints<C: CanEnter>(can_enter: &C, path: &Path, line_of_sight: LineOfSight, waypoints: &mut Vec<Coord>) {
+    waypoints.clear();
+    let mut from = match path.iter().next() {
+        Some(first) => first.to_coord - first.in_direction.coord(),
+        None => return,
+    };
+    let mut previous = from;
+    for node in path.iter() {
+        if previous != from && !line_of_sight.is_visible(can_enter, from, node.to_coord) {
+            waypoints.push(previous);
+            from = previous;
+        }
+        previous = node.to_coord;
+    }
+    waypoints.push(previous);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use direction::CardinalDirection;
+    use grid_2d::{Grid, Size};
+
+    struct Walls(Grid<bool>);
+
+    impl CanEnter for Walls {
+        fn can_enter(&self, coord: Coord) -> bool {
+            self.0.get(coord) == Some(&false)
+        }
+    }
+
+    fn walls(size: Size, walls: &[Coord]) -> Walls {
+        let mut grid = Grid::new_copy(size, false);
+        for &coord in walls {
+            *grid.get_checked_mut(coord) = true;
+        }
+        Walls(grid)
+    }
+
+    #[test]
+    fn open() {
+        let world = walls(Size::new(8, 8), &[]);
+        for line_of_sight in [LineOfSight::Bresenham, LineOfSight::Supercover] {
+            assert!(line_of_sight.is_visible(&world, Coord::new(0, 0), Coord::new(7, 3)));
+            assert!(line_of_sight.is_visible(&world, Coord::new(7, 3), Coord::new(0, 0)));
+            assert!(line_of_sight.is_visible(&world, Coord::new(2, 2), Coord::new(2, 2)));
+        }
+    }
+
+    #[test]
+    fn ends_not_checked() {
+        let world = walls(Size::new(8, 8), &[Coord::new(0, 0), Coord::new(4, 0)]);
+        for line_of_sight in [LineOfSight::Bresenham, LineOfSight::Supercover] {
+            assert!(line_of_sight.is_visible(&world, Coord::new(0, 0), Coord::new(4, 0)));
+            assert!(!line_of_sight.is_visible(&world, Coord::new(0, 0), Coord::new(5, 0)));
+        }
+    }
+
+    #[test]
+    fn corner() {
+        // the diagonal passes exactly through the corner between the start and the wall
+        let world = walls(Size::new(4, 4), &[Coord::new(1, 0)]);
+        assert!(LineOfSight::Bresenham.is_visible(&world, Coord::new(0, 0), Coord::new(2, 2)));
+        assert!(!LineOfSight::Supercover.is_visible(&world, Coord::new(0, 0), Coord::new(2, 2)));
+    }
+
+    #[test]
+    fn waypoints_around_wall() {
+        let world = walls(Size::new(4, 4), &[Coord::new(1, 1)]);
+        let directions = [
+            CardinalDirection::East,
+            CardinalDirection::East,
+            CardinalDirection::South,
+            CardinalDirection::South,
+        ];
+        let path = Path::from_directions(&world, Coord::new(0, 0), &directions).unwrap();
+        let mut coords = Vec::new();
+        waypoints(&world, &path, LineOfSight::Supercover, &mut coords);
+        assert_eq!(coords, vec![Coord::new(2, 0), Coord::new(2, 2)]);
+        waypoints(&world, &Path::default(), LineOfSight::Supercover, &mut coords);
+        assert!(coords.is_empty());
+    }
+}
@@ -0,0 +1,29 @@
+use std::cmp::Ordering;
+
+/// Entry of a `BinaryHeap` which pops the entry with the smallest key first. Entries are
+/// compared by key alone.
+#[derive(Clone, Copy, Debug)]
+pub struct MinHeapNode<K, T> {
+    pub key: K,
+    pub value: T,
+}
+
+impl<K: Ord, T> PartialEq for MinHeapNode<K, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key.eq(&other.key)
+    }
+}
+
+impl<K: Ord, T> PartialOrd for MinHeapNode<K, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, T> Eq for MinHeapNode<K, T> {}
+
+impl<K: Ord, T> Ord for MinHeapNode<K, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key.cmp(&self.key)
+    }
+}
@@ -0,0 +1,82 @@
+use grid_2d::Coord;
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+
+/// Notified of the progress of a search. All methods do nothing by default.
+pub trait Observer {
+    /// A node was added to the queue
+    fn push(&mut self, _coord: Coord) {}
+    /// A node was taken from the queue and its neighbours considered
+    fn expand(&mut self, _coord: Coord) {}
+    /// The cost of reaching a cell was recorded
+    fn visit(&mut self, _coord: Coord) {}
+    /// A cell was scanned while jumping. Scanned cells aren't recorded, so a cell may be
+    /// rescanned by later jumps.
+    fn rescan(&mut self, _coord: Coord) {}
+    /// A straight jump from `from` to `to` was recorded, skipping the cells in between. When the
+    /// jump crosses the edge of a grid which wraps around, `to` is off the grid, so that the
+    /// jump's direction and length are still known.
+    fn jump(&mut self, _from: Coord, _to: Coord) {}
+    /// The length of the queue after a node was added
+    fn queue_len(&mut self, _len: usize) {}
+    /// The cost of the path found by the search
+    fn path_cost(&mut self, _cost: u32) {}
+}
+
+impl Observer for () {}
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    pub nodes_pushed: u64,
+    pub nodes_expanded: u64,
+    pub cells_visited: u64,
+    pub cells_rescanned: u64,
+    pub peak_queue_len: usize,
+    pub path_cost: Option<u32>,
+}
+
+impl Observer for SearchStats {
+    fn push(&mut self, _coord: Coord) {
+        self.nodes_pushed += 1;
+    }
+    fn expand(&mut self, _coord: Coord) {
+        self.nodes_expanded += 1;
+    }
+    fn visit(&mut self, _coord: Coord) {
+        self.cells_visited += 1;
+    }
+    fn rescan(&mut self, _coord: Coord) {
+        self.cells_rescanned += 1;
+    }
+    fn queue_len(&mut self, len: usize) {
+        self.peak_queue_len = self.peak_queue_len.max(len);
+    }
+    fn path_cost(&mut self, cost: u32) {
+        self.path_cost = Some(cost);
+    }
+}
+
+impl<O: Observer> Observer for &mut O {
+    fn push(&mut self, coord: Coord) {
+        (**self).push(coord);
+    }
+    fn expand(&mut self, coord: Coord) {
+        (**self).expand(coord);
+    }
+    fn visit(&mut self, coord: Coord) {
+        (**self).visit(coord);
+    }
+    fn rescan(&mut self, coord: Coord) {
+        (**self).rescan(coord);
+    }
+    fn jump(&mut self, from: Coord, to: Coord) {
+        (**self).jump(from, to);
+    }
+    fn queue_len(&mut self, len: usize) {
+        (**self).queue_len(len);
+    }
+    fn path_cost(&mut self, cost: u32) {
+        (**self).path_cost(cost);
+    }
+}
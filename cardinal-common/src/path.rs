@@ -1,6 +1,9 @@
-use crate::step::Step;
+use crate::can_enter::CanEnter;
+use crate::coord::UnitCoord;
+use crate::step::{Jump, Step};
+use crate::wrap::Wrap;
 use direction::CardinalDirection;
-use grid_2d::Coord;
+use grid_2d::{Coord, Size};
 use std::collections::{vec_deque, VecDeque};
 
 #[cfg(feature = "serialize")]
@@ -36,14 +39,35 @@ impl<'a> Iterator for PathIter<'a> {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathError {
+    /// A path was appended which doesn't start where the path it was appended to ends
+    Discontinuous { end: Coord, next_start: Coord },
+    /// The step at `index` enters a cell which can't be entered
+    Blocked { index: usize, coord: Coord },
+    /// A path was appended which was found on a grid that wraps differently
+    WrapMismatch,
+}
+
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct Path {
     steps: VecDeque<Step>,
+    // the grid the path was built on, if it can cross the grid's edges
+    wrap: Option<(Wrap, Size)>,
 }
 
 impl Path {
-    pub fn iter(&self) -> PathIter {
+    pub(crate) fn set_wrap(&mut self, wrap: Wrap, size: Option<Size>) {
+        self.wrap = size.filter(|_| wrap != Wrap::None).map(|size| (wrap, size));
+    }
+    fn normalize(&self, coord: Coord) -> Coord {
+        match self.wrap {
+            Some((wrap, size)) => wrap.normalize(coord, size),
+            None => coord,
+        }
+    }
+    pub fn iter(&self) -> PathIter<'_> {
         PathIter {
             iter: self.steps.iter(),
         }
@@ -63,4 +87,476 @@ impl Path {
     pub(crate) fn prepend(&mut self, step: Step) {
         self.steps.push_front(step);
     }
+    pub(crate) fn append(&mut self, step: Step) {
+        self.steps.push_back(step);
+    }
+    /// Adds `step` to the end of the path. Fails without changing the path unless `step` starts
+    /// where the path ends.
+    pub fn push_back(&mut self, step: Step) -> Result<(), PathError> {
+        if let Some(end) = self.last().map(|node| node.to_coord) {
+            let next_start = self.normalize(step.from_coord());
+            if end != next_start {
+                return Err(PathError::Discontinuous { end, next_start });
+            }
+        }
+        self.append(step);
+        Ok(())
+    }
+    /// Adds `step` to the start of the path. Fails without changing the path unless `step`
+    /// enters the cell the path starts from.
+    pub fn push_front(&mut self, step: Step) -> Result<(), PathError> {
+        if let Some(next_start) = self.start() {
+            let end = self.normalize(step.to_coord);
+            if end != next_start {
+                return Err(PathError::Discontinuous { end, next_start });
+            }
+        }
+        self.prepend(step);
+        Ok(())
+    }
+    pub fn first(&self) -> Option<PathNode> {
+        self.steps.front().map(PathNode::from_step)
+    }
+    pub fn last(&self) -> Option<PathNode> {
+        self.steps.back().map(PathNode::from_step)
+    }
+    /// The coordinate the path starts from, if it has any nodes
+    pub fn start(&self) -> Option<Coord> {
+        self.steps.front().map(|step| self.normalize(step.from_coord()))
+    }
+    /// Appends the nodes of `path`, which must start where this path ends and have been found on
+    /// a grid that wraps the same way, if both paths know their grid. Fails without changing this
+    /// path otherwise. Either path may be empty.
+    pub fn append_path(&mut self, path: &Path) -> Result<(), PathError> {
+        if let (Some(wrap), Some(other_wrap)) = (self.wrap, path.wrap) {
+            if wrap != other_wrap {
+                return Err(PathError::WrapMismatch);
+            }
+        }
+        if let (Some(end), Some(next_start)) = (self.last().map(|node| node.to_coord), path.start()) {
+            if end != next_start {
+                return Err(PathError::Discontinuous { end, next_start });
+            }
+        }
+        self.steps.extend(path.steps.iter().cloned());
+        self.wrap = self.wrap.or(path.wrap);
+        Ok(())
+    }
+    /// Reverses the path in place, so that it leads back to where it started
+    pub fn reverse(&mut self) {
+        self.steps = self
+            .steps
+            .iter()
+            .rev()
+            .map(|step| Step {
+                to_coord: self.normalize(step.from_coord()),
+                in_direction: step.in_direction.opposite(),
+            })
+            .collect();
+    }
+    /// Keeps the first `len` nodes
+    pub fn truncate(&mut self, len: usize) {
+        self.steps.truncate(len);
+    }
+    /// Splits the path into the first `index` nodes and the rest, which start where the first
+    /// part ends. Panics if `index` is greater than the length.
+    pub fn split_at(&self, index: usize) -> (Self, Self) {
+        let mut head = self.clone();
+        let tail = Self {
+            steps: head.steps.split_off(index),
+            wrap: self.wrap,
+        };
+        (head, tail)
+    }
+    pub fn directions(&self) -> impl Iterator<Item = CardinalDirection> + '_ {
+        self.steps.iter().map(|step| step.in_direction.to_cardinal_direction())
+    }
+    /// Builds the path taking `directions` in turn from `start`, checking that each step can
+    /// be taken
+    pub fn from_directions<C: CanEnter>(
+        can_enter: &C,
+        start: Coord,
+        directions: &[CardinalDirection],
+    ) -> Result<Self, PathError> {
+        let mut path = Self::default();
+        let mut coord = start;
+        for (index, &direction) in directions.iter().enumerate() {
+            let step = Step {
+                to_coord: coord + direction.coord(),
+                in_direction: UnitCoord::from_cardinal_direction(direction),
+            };
+            if !can_enter.can_step(step) {
+                return Err(PathError::Blocked {
+                    index,
+                    coord: step.to_coord,
+                });
+            }
+            path.append(step);
+            coord = step.to_coord;
+        }
+        Ok(path)
+    }
+}
+
+/// A path stored as the jumps made by the search which found it, rather than one node per step.
+/// Iterating yields the same unit steps as the equivalent `Path`. Every jump is at least one
+/// step long, since `CardinalCoord` can't be zero.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(from = "CompactPathFields"))]
+#[derive(Default, Debug, Clone)]
+pub struct CompactPath {
+    jumps: VecDeque<Jump>,
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    len: usize,
+    // the grid the path was built on, if steps within a jump need to wrap around its edges
+    wrap: Option<(Wrap, Size)>,
+}
+
+// the serialized form of `CompactPath`, whose length is recomputed from its jumps
+#[cfg(feature = "serialize")]
+#[derive(Deserialize)]
+struct CompactPathFields {
+    jumps: VecDeque<Jump>,
+    wrap: Option<(Wrap, Size)>,
+}
+
+#[cfg(feature = "serialize")]
+impl From<CompactPathFields> for CompactPath {
+    fn from(CompactPathFields { jumps, wrap }: CompactPathFields) -> Self {
+        let len = jumps.iter().map(|jump| jump.in_direction.magnitude() as usize).sum();
+        Self { jumps, len, wrap }
+    }
+}
+
+fn nth_from_end(jump: &Jump, n: u32, wrap: Option<(Wrap, Size)>) -> Step {
+    let step = jump.last_step();
+    let to_coord = step.to_coord - step.in_direction.to_coord() * n as i32;
+    Step {
+        to_coord: match wrap {
+            Some((wrap, size)) => wrap.normalize(to_coord, size),
+            None => to_coord,
+        },
+        in_direction: step.in_direction,
+    }
+}
+
+pub struct CompactPathIter<'a> {
+    jumps: vec_deque::Iter<'a, Jump>,
+    current: Option<(&'a Jump, u32)>,
+    remaining: usize,
+    wrap: Option<(Wrap, Size)>,
+}
+
+impl<'a> Iterator for CompactPathIter<'a> {
+    type Item = PathNode;
+    fn next(&mut self) -> Option<Self::Item> {
+        let (jump, steps_left) = match self.current {
+            Some((jump, steps_left)) if steps_left > 0 => (jump, steps_left),
+            _ => {
+                let jump = self.jumps.next()?;
+                (jump, jump.in_direction.magnitude())
+            }
+        };
+        self.current = Some((jump, steps_left - 1));
+        self.remaining -= 1;
+        Some(PathNode::from_step(&nth_from_end(jump, steps_left - 1, self.wrap)))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for CompactPathIter<'a> {}
+
+impl CompactPath {
+    pub fn iter(&self) -> CompactPathIter<'_> {
+        CompactPathIter {
+            jumps: self.jumps.iter(),
+            current: None,
+            remaining: self.len,
+            wrap: self.wrap,
+        }
+    }
+    /// The number of unit steps in the path
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// The number of jumps the path is stored as
+    pub fn num_jumps(&self) -> usize {
+        self.jumps.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn clear(&mut self) {
+        self.jumps.clear();
+        self.len = 0;
+    }
+    pub fn pop(&mut self) -> Option<PathNode> {
+        let jump = self.jumps.front_mut()?;
+        let magnitude = jump.in_direction.magnitude();
+        let step = nth_from_end(jump, magnitude - 1, self.wrap);
+        if magnitude == 1 {
+            self.jumps.pop_front();
+        } else {
+            jump.in_direction = step.in_direction.scale(magnitude - 1);
+        }
+        self.len -= 1;
+        Some(PathNode::from_step(&step))
+    }
+    pub fn first(&self) -> Option<PathNode> {
+        self.jumps
+            .front()
+            .map(|jump| PathNode::from_step(&nth_from_end(jump, jump.in_direction.magnitude() - 1, self.wrap)))
+    }
+    pub fn last(&self) -> Option<PathNode> {
+        self.jumps.back().map(|jump| PathNode::from_step(&jump.last_step()))
+    }
+    pub(crate) fn set_wrap(&mut self, wrap: Wrap, size: Option<Size>) {
+        self.wrap = size.filter(|_| wrap != Wrap::None).map(|size| (wrap, size));
+    }
+    pub fn prepend(&mut self, jump: Jump) {
+        self.len += jump.in_direction.magnitude() as usize;
+        self.jumps.push_front(jump);
+    }
+    pub fn append(&mut self, jump: Jump) {
+        self.len += jump.in_direction.magnitude() as usize;
+        self.jumps.push_back(jump);
+    }
+    /// Expands every jump into unit steps, replacing the contents of `path`
+    pub fn to_path(&self, path: &mut Path) {
+        path.clear();
+        path.wrap = self.wrap;
+        for jump in &self.jumps {
+            for n in (0..jump.in_direction.magnitude()).rev() {
+                path.append(nth_from_end(jump, n, self.wrap));
+            }
+        }
+    }
+}
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedPathNode {
+    pub node: PathNode,
+    /// The tick at which `node.to_coord` is entered
+    pub time: u32,
+}
+
+/// A path through a world whose obstacles move over time. Waiting doesn't appear as a node, so
+/// a wait shows up as a gap between the arrival ticks of consecutive nodes.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Default, Debug, Clone)]
+pub struct TimedPath {
+    nodes: VecDeque<TimedPathNode>,
+}
+
+impl TimedPath {
+    pub fn iter(&self) -> vec_deque::Iter<'_, TimedPathNode> {
+        self.nodes.iter()
+    }
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    pub fn pop(&mut self) -> Option<TimedPathNode> {
+        self.nodes.pop_front()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+    }
+    /// The tick at which the last node is entered
+    pub fn arrival_time(&self) -> Option<u32> {
+        self.nodes.back().map(|node| node.time)
+    }
+    pub fn prepend(&mut self, node: TimedPathNode) {
+        self.nodes.push_front(node);
+    }
+    pub fn append(&mut self, node: TimedPathNode) {
+        self.nodes.push_back(node);
+    }
+}
+
+/// How a node of a `PortalPath` is entered from the node before it
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Move {
+    Step(CardinalDirection),
+    Portal,
+}
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortalPathNode {
+    pub to_coord: Coord,
+    pub via: Move,
+}
+
+/// A path which may take portals between cells which aren't adjacent
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Default, Debug, Clone)]
+pub struct PortalPath {
+    nodes: VecDeque<PortalPathNode>,
+}
+
+impl PortalPath {
+    pub fn iter(&self) -> vec_deque::Iter<'_, PortalPathNode> {
+        self.nodes.iter()
+    }
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    pub fn pop(&mut self) -> Option<PortalPathNode> {
+        self.nodes.pop_front()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+    }
+    pub fn prepend(&mut self, node: PortalPathNode) {
+        self.nodes.push_front(node);
+    }
+    pub fn append(&mut self, node: PortalPathNode) {
+        self.nodes.push_back(node);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use direction::CardinalDirection::{East, North, South};
+
+    fn step(x: i32, y: i32, direction: CardinalDirection) -> Step {
+        Step {
+            to_coord: Coord::new(x, y),
+            in_direction: UnitCoord::from_cardinal_direction(direction),
+        }
+    }
+
+    fn coords(path: &Path) -> Vec<Coord> {
+        path.iter().map(|node| node.to_coord).collect()
+    }
+
+    #[test]
+    fn push_back_and_front() {
+        let mut path = Path::default();
+        path.push_back(step(1, 0, East)).unwrap();
+        path.push_back(step(1, 1, South)).unwrap();
+        path.push_front(step(0, 0, East)).unwrap();
+        assert_eq!(path.start(), Some(Coord::new(-1, 0)));
+        assert_eq!(
+            coords(&path),
+            vec![Coord::new(0, 0), Coord::new(1, 0), Coord::new(1, 1)]
+        );
+        assert_eq!(
+            path.push_back(step(3, 1, East)),
+            Err(PathError::Discontinuous {
+                end: Coord::new(1, 1),
+                next_start: Coord::new(2, 1),
+            })
+        );
+        assert_eq!(
+            path.push_front(step(5, 5, North)),
+            Err(PathError::Discontinuous {
+                end: Coord::new(5, 5),
+                next_start: Coord::new(-1, 0),
+            })
+        );
+        assert_eq!(path.len(), 3);
+    }
+
+    #[test]
+    fn push_across_wrapped_edge() {
+        let mut path = Path::default();
+        path.set_wrap(Wrap::X, Some(Size::new(4, 4)));
+        path.push_back(step(3, 0, East)).unwrap();
+        path.push_back(step(0, 0, East)).unwrap();
+        assert_eq!(path.start(), Some(Coord::new(2, 0)));
+        path.push_front(step(2, 0, East)).unwrap();
+        assert_eq!(
+            coords(&path),
+            vec![Coord::new(2, 0), Coord::new(3, 0), Coord::new(0, 0)]
+        );
+    }
+
+    #[test]
+    fn append_path() {
+        let mut head = Path::default();
+        head.push_back(step(1, 0, East)).unwrap();
+        let mut tail = Path::default();
+        tail.push_back(step(1, 1, South)).unwrap();
+        let mut disjoint = Path::default();
+        disjoint.push_back(step(5, 5, South)).unwrap();
+        assert_eq!(
+            head.append_path(&disjoint),
+            Err(PathError::Discontinuous {
+                end: Coord::new(1, 0),
+                next_start: Coord::new(5, 4),
+            })
+        );
+        head.append_path(&Path::default()).unwrap();
+        head.append_path(&tail).unwrap();
+        assert_eq!(coords(&head), vec![Coord::new(1, 0), Coord::new(1, 1)]);
+        let mut wrapped = Path::default();
+        wrapped.set_wrap(Wrap::Both, Some(Size::new(4, 4)));
+        wrapped.push_back(step(1, 2, South)).unwrap();
+        head.set_wrap(Wrap::X, Some(Size::new(4, 4)));
+        assert_eq!(head.append_path(&wrapped), Err(PathError::WrapMismatch));
+        assert_eq!(head.len(), 2);
+    }
+
+    #[test]
+    fn reverse_and_split() {
+        let mut path = Path::default();
+        path.push_back(step(1, 0, East)).unwrap();
+        path.push_back(step(2, 0, East)).unwrap();
+        path.push_back(step(2, 1, South)).unwrap();
+        let (head, tail) = path.split_at(1);
+        assert_eq!(coords(&head), vec![Coord::new(1, 0)]);
+        assert_eq!(tail.start(), Some(Coord::new(1, 0)));
+        path.reverse();
+        assert_eq!(path.start(), Some(Coord::new(2, 1)));
+        assert_eq!(
+            coords(&path),
+            vec![Coord::new(2, 0), Coord::new(1, 0), Coord::new(0, 0)]
+        );
+    }
+
+    #[test]
+    fn compact_path_to_path() {
+        let mut compact = CompactPath::default();
+        compact.append(step(3, 0, East).scale_back(3));
+        compact.append(step(3, 2, South).scale_back(2));
+        assert_eq!(compact.len(), 5);
+        assert_eq!(compact.num_jumps(), 2);
+        assert_eq!(compact.iter().len(), 5);
+        let mut path = Path::default();
+        compact.to_path(&mut path);
+        assert_eq!(compact.iter().collect::<Vec<_>>(), path.iter().collect::<Vec<_>>());
+        assert_eq!(compact.pop(), path.pop());
+        assert_eq!(compact.len(), 4);
+        assert_eq!(compact.first(), path.first());
+        assert_eq!(compact.last(), path.last());
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn compact_path_round_trip() {
+        let mut compact = CompactPath::default();
+        compact.set_wrap(Wrap::X, Some(Size::new(4, 4)));
+        compact.append(step(1, 0, East).scale_back(3));
+        compact.append(step(1, 2, South).scale_back(2));
+        let json = serde_json::to_string(&compact).unwrap();
+        let deserialized: CompactPath = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.len(), compact.len());
+        assert_eq!(
+            deserialized.iter().collect::<Vec<_>>(),
+            compact.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(deserialized.first().map(|node| node.to_coord), Some(Coord::new(3, 0)));
+    }
 }
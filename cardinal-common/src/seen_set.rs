@@ -1,45 +1,125 @@
 use crate::coord::CardinalCoord;
-use crate::path::Path;
+use crate::error::SearchError;
+use crate::path::{CompactPath, Move, Path, PortalPath, PortalPathNode};
 use crate::step::{Jump, Step};
+use crate::storage::{Dense, DenseStore, Sparse, SparseStore, Storage, Store};
+use crate::wrap::Wrap;
 use direction::CardinalDirection;
-use grid_2d::{Coord, Grid, Size};
+use grid_2d::{Coord, Size};
 
 #[derive(Debug, Clone)]
 struct SeenCell {
     count: u64,
     cost: u32,
     in_direction: Option<CardinalCoord>,
+    // set instead of `in_direction` when the cell was entered through a portal
+    portal_from: Option<Coord>,
+    // false if the cell was visited but turned out not to be enterable
+    entered: bool,
 }
 
+const UNSEEN: SeenCell = SeenCell {
+    count: 0,
+    cost: 0,
+    in_direction: None,
+    portal_from: None,
+    entered: false,
+};
+
 #[derive(Debug, Clone)]
-pub struct SeenSet {
+pub struct SeenSet<B: Storage = Dense> {
     count: u64,
-    grid: Grid<SeenCell>,
+    grid: B::Store<SeenCell>,
+    wrap: Wrap,
 }
 
 pub struct Visit;
 
-impl SeenSet {
+impl SeenSet<Dense> {
     pub fn new(size: Size) -> Self {
         Self {
             count: 1,
-            grid: Grid::new_fn(size, |_| SeenCell {
-                count: 0,
-                cost: 0,
-                in_direction: None,
-            }),
+            grid: DenseStore::new(size, UNSEEN),
+            wrap: Wrap::None,
         }
     }
 
     pub fn size(&self) -> Size {
+        self.bounds().expect("dense storage is bounded")
+    }
+}
+
+impl SeenSet<Sparse> {
+    pub fn new_unbounded() -> Self {
+        Self {
+            count: 1,
+            grid: SparseStore::new(UNSEEN),
+            wrap: Wrap::None,
+        }
+    }
+}
+
+impl<B: Storage> SeenSet<B> {
+    /// An empty seen set covering the same grid with the same wrap
+    pub fn new_like(&self) -> Self {
+        Self {
+            count: 1,
+            grid: self.grid.new_like(UNSEEN),
+            wrap: self.wrap,
+        }
+    }
+
+    /// The size of the grid, or `None` if the seen set is unbounded
+    pub fn bounds(&self) -> Option<Size> {
         self.grid.size()
     }
 
+    pub fn wrap(&self) -> Wrap {
+        self.wrap
+    }
+
+    pub fn set_wrap(&mut self, wrap: Wrap) {
+        self.wrap = wrap;
+    }
+
+    pub fn check_bounds(&self, starts: &[Coord], goals: &[Coord]) -> Result<(), SearchError> {
+        match self.grid.size() {
+            Some(size) => SearchError::check_bounds(size, starts, goals),
+            None => Ok(()),
+        }
+    }
+
+    /// Describes why the latest search failed, from the cells it visited. Closeness is measured
+    /// by manhattan distance to the nearest of `goals`.
+    pub fn goal_unreachable(&self, starts: &[Coord], goals: &[Coord]) -> SearchError {
+        let distance = |a: Coord, b: Coord| match self.grid.size() {
+            Some(size) => self.wrap.manhattan_distance(a, b, size),
+            None => a.manhattan_distance(b),
+        };
+        let visited = self
+            .grid
+            .iter()
+            .filter(|(_, cell)| cell.count == self.count && cell.entered)
+            .map(|(coord, _)| coord);
+        SearchError::goal_unreachable(starts.first().cloned().unwrap_or(Coord::new(0, 0)), visited, |coord| {
+            goals.iter().map(|&goal| distance(coord, goal)).min().unwrap_or(0)
+        })
+    }
+
+    /// Maps `coord` onto the grid if it wraps around
+    pub fn normalize(&self, coord: Coord) -> Coord {
+        match self.grid.size() {
+            Some(size) => self.wrap.normalize(coord, size),
+            None => coord,
+        }
+    }
+
     pub fn build_path_to(&self, end: Coord, path: &mut Path) {
         let mut cell = self.grid.get(end).expect("path end out of bounds");
         debug_assert_eq!(cell.count, self.count, "path end not visited in latest search");
         let mut coord = end;
         path.clear();
+        path.set_wrap(self.wrap, self.grid.size());
         while let Some(in_direction) = cell.in_direction {
             let mut step = Step {
                 to_coord: coord,
@@ -47,10 +127,73 @@ impl SeenSet {
             };
             for _ in 0..in_direction.magnitude() {
                 path.prepend(step);
-                step.to_coord -= step.in_direction.to_coord();
+                step.to_coord = self.normalize(step.to_coord - step.in_direction.to_coord());
             }
             coord = step.to_coord;
-            cell = self.grid.get_checked(coord);
+            cell = self.grid.get(coord).expect("path includes cell out of bounds");
+            debug_assert_eq!(
+                cell.count, self.count,
+                "path includes cell not visited in latest search"
+            );
+        }
+    }
+
+    /// Like `build_path_to`, but keeps each jump made by the search as a single entry
+    pub fn build_compact_path_to(&self, end: Coord, path: &mut CompactPath) {
+        let mut cell = self.grid.get(end).expect("path end out of bounds");
+        debug_assert_eq!(cell.count, self.count, "path end not visited in latest search");
+        let mut coord = end;
+        path.clear();
+        path.set_wrap(self.wrap, self.grid.size());
+        while let Some(in_direction) = cell.in_direction {
+            path.prepend(Jump {
+                to_coord: coord,
+                in_direction,
+            });
+            coord = self.normalize(coord - in_direction.to_coord());
+            cell = self.grid.get(coord).expect("path includes cell out of bounds");
+            debug_assert_eq!(
+                cell.count, self.count,
+                "path includes cell not visited in latest search"
+            );
+        }
+    }
+
+    /// Like `build_path_to`, for searches which may have taken portals
+    pub fn build_portal_path_to(&self, end: Coord, path: &mut PortalPath) {
+        path.clear();
+        self.for_each_portal_path_node_towards(end, |node| path.prepend(node));
+    }
+
+    /// The first node of the path to `end`, which may be a portal rather than a step
+    pub fn first_portal_path_node_towards(&self, end: Coord) -> Option<PortalPathNode> {
+        let mut ret = None;
+        self.for_each_portal_path_node_towards(end, |node| ret = Some(node));
+        ret
+    }
+
+    // calls `f` with each node of the path to `end`, starting with the last
+    fn for_each_portal_path_node_towards<F: FnMut(PortalPathNode)>(&self, end: Coord, mut f: F) {
+        let mut cell = self.grid.get(end).expect("path end out of bounds");
+        debug_assert_eq!(cell.count, self.count, "path end not visited in latest search");
+        let mut coord = end;
+        loop {
+            if let Some(in_direction) = cell.in_direction {
+                let via = Move::Step(in_direction.to_cardinal_direction());
+                for _ in 0..in_direction.magnitude() {
+                    f(PortalPathNode { to_coord: coord, via });
+                    coord = self.normalize(coord - in_direction.to_unit_coord().to_coord());
+                }
+            } else if let Some(from_coord) = cell.portal_from {
+                f(PortalPathNode {
+                    to_coord: coord,
+                    via: Move::Portal,
+                });
+                coord = from_coord;
+            } else {
+                break;
+            }
+            cell = self.grid.get(coord).expect("path includes cell out of bounds");
             debug_assert_eq!(
                 cell.count, self.count,
                 "path includes cell not visited in latest search"
@@ -64,8 +207,8 @@ impl SeenSet {
         let mut coord = end;
         let mut ret = None;
         while let Some(in_direction) = cell.in_direction {
-            coord = coord - in_direction.to_coord();
-            cell = self.grid.get_checked(coord);
+            coord = self.normalize(coord - in_direction.to_coord());
+            cell = self.grid.get(coord).expect("path includes cell out of bounds");
             debug_assert_eq!(
                 cell.count, self.count,
                 "path includes cell not visited in latest search"
@@ -75,11 +218,81 @@ impl SeenSet {
         ret.map(|in_direction| in_direction.to_cardinal_direction())
     }
 
+    // For a seen set populated by searching backwards from a goal, extend the path from `start`
+    // to that goal.
+    pub fn extend_path_in_reverse_from(&self, start: Coord, path: &mut Path) {
+        let mut cell = self.grid.get(start).expect("path start out of bounds");
+        debug_assert_eq!(cell.count, self.count, "path start not visited in latest search");
+        let mut coord = start;
+        while let Some(in_direction) = cell.in_direction {
+            let in_direction = in_direction.opposite();
+            let unit_coord = in_direction.to_unit_coord();
+            for _ in 0..in_direction.magnitude() {
+                coord = self.normalize(coord + unit_coord.to_coord());
+                path.append(Step {
+                    to_coord: coord,
+                    in_direction: unit_coord,
+                });
+            }
+            cell = self.grid.get(coord).expect("path includes cell out of bounds");
+            debug_assert_eq!(
+                cell.count, self.count,
+                "path includes cell not visited in latest search"
+            );
+        }
+    }
+
+    pub fn extend_compact_path_in_reverse_from(&self, start: Coord, path: &mut CompactPath) {
+        let mut cell = self.grid.get(start).expect("path start out of bounds");
+        debug_assert_eq!(cell.count, self.count, "path start not visited in latest search");
+        let mut coord = start;
+        while let Some(in_direction) = cell.in_direction {
+            let in_direction = in_direction.opposite();
+            coord = self.normalize(coord + in_direction.to_coord());
+            path.append(Jump {
+                to_coord: coord,
+                in_direction,
+            });
+            cell = self.grid.get(coord).expect("path includes cell out of bounds");
+            debug_assert_eq!(
+                cell.count, self.count,
+                "path includes cell not visited in latest search"
+            );
+        }
+    }
+
+    pub fn first_direction_in_reverse_from(&self, start: Coord) -> Option<CardinalDirection> {
+        let cell = self.grid.get(start).expect("path start out of bounds");
+        debug_assert_eq!(cell.count, self.count, "path start not visited in latest search");
+        cell.in_direction
+            .map(|in_direction| in_direction.opposite().to_cardinal_direction())
+    }
+
     pub fn init(&mut self, start: Coord) {
+        self.clear();
+        self.add_start(start);
+    }
+
+    pub fn clear(&mut self) {
         self.count += 1;
-        let cell = self.grid.get_checked_mut(start);
+        let count = self.count;
+        self.grid.prune(|cell| cell.count == count);
+    }
+
+    pub fn add_start(&mut self, start: Coord) {
+        let cell = self.grid.get_mut(start).expect("start out of bounds");
         cell.count = self.count;
+        cell.cost = 0;
         cell.in_direction = None;
+        cell.portal_from = None;
+        cell.entered = true;
+    }
+
+    pub fn cost(&self, coord: Coord) -> Option<u32> {
+        self.grid
+            .get(coord)
+            .filter(|cell| cell.count == self.count)
+            .map(|cell| cell.cost)
     }
 
     fn try_visit(&mut self, to_coord: Coord, in_direction: CardinalCoord, cost: u32) -> Option<Visit> {
@@ -88,12 +301,22 @@ impl SeenSet {
                 cell.count = self.count;
                 cell.cost = cost;
                 cell.in_direction = Some(in_direction);
+                cell.portal_from = None;
+                cell.entered = true;
                 return Some(Visit);
             }
         }
         None
     }
 
+    /// Records that the cell most recently visited at `coord` can't be entered, so it isn't
+    /// counted as reached when describing a failed search
+    pub fn mark_blocked(&mut self, coord: Coord) {
+        if let Some(cell) = self.grid.get_mut(coord) {
+            cell.entered = false;
+        }
+    }
+
     pub fn try_visit_step(&mut self, step: Step, cost: u32) -> Option<Visit> {
         self.try_visit(step.to_coord, step.in_direction.to_cardinal_coord(), cost)
     }
@@ -101,4 +324,18 @@ impl SeenSet {
     pub fn try_visit_jump(&mut self, jump: Jump, cost: u32) -> Option<Visit> {
         self.try_visit(jump.to_coord, jump.in_direction, cost)
     }
+
+    pub fn try_visit_portal(&mut self, from_coord: Coord, to_coord: Coord, cost: u32) -> Option<Visit> {
+        if let Some(cell) = self.grid.get_mut(to_coord) {
+            if cell.count != self.count || cost < cell.cost {
+                cell.count = self.count;
+                cell.cost = cost;
+                cell.in_direction = None;
+                cell.portal_from = Some(from_coord);
+                cell.entered = true;
+                return Some(Visit);
+            }
+        }
+        None
+    }
 }
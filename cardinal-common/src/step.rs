@@ -46,6 +46,7 @@ impl Step {
     }
 }
 
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 #[derive(Clone, Copy, Debug)]
 pub struct Jump {
     pub to_coord: Coord,
@@ -0,0 +1,166 @@
+use grid_2d::{Coord, Grid, GridEnumerate, Size};
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+use std::collections::{hash_map, HashMap};
+use std::fmt::Debug;
+use std::iter;
+
+/// Per-cell state kept by a search
+pub trait Store<T>: Clone + Debug {
+    type Iter<'a>: Iterator<Item = (Coord, &'a T)>
+    where
+        Self: 'a,
+        T: 'a;
+    /// The size of the grid, or `None` if any coordinate can be stored
+    fn size(&self) -> Option<Size>;
+    fn get(&self, coord: Coord) -> Option<&T>;
+    /// Cells which haven't been stored yet start with a default value. Returns `None` if
+    /// `coord` is out of bounds.
+    fn get_mut(&mut self, coord: Coord) -> Option<&mut T>;
+    /// Frees the cells for which `keep` returns false, if the store allocates cells on demand
+    fn prune<F: FnMut(&T) -> bool>(&mut self, keep: F);
+    /// Every cell, or only the cells which have been stored if the store allocates cells on
+    /// demand
+    fn iter(&self) -> Self::Iter<'_>;
+    /// An empty store covering the same grid, whose cells start as `default`
+    fn new_like(&self, default: T) -> Self;
+}
+
+/// Chooses how searches store per-cell state
+pub trait Storage {
+    type Store<T: Clone + Debug>: Store<T>;
+}
+
+/// Stores every cell of a fixed size grid. Coordinates outside the grid are out of bounds.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Dense;
+
+/// Stores only the cells a search reaches in a hash map, so coordinates can be any `i32`
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sparse;
+
+impl Storage for Dense {
+    type Store<T: Clone + Debug> = DenseStore<T>;
+}
+
+impl Storage for Sparse {
+    type Store<T: Clone + Debug> = SparseStore<T>;
+}
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize), serde(transparent))]
+#[derive(Clone, Debug)]
+pub struct DenseStore<T> {
+    grid: Grid<T>,
+}
+
+impl<T: Clone> DenseStore<T> {
+    pub fn new(size: Size, default: T) -> Self {
+        Self {
+            grid: Grid::new_clone(size, default),
+        }
+    }
+}
+
+impl<T: Clone + Debug> Store<T> for DenseStore<T> {
+    type Iter<'a>
+        = GridEnumerate<'a, T>
+    where
+        T: 'a;
+    fn size(&self) -> Option<Size> {
+        Some(self.grid.size())
+    }
+    fn get(&self, coord: Coord) -> Option<&T> {
+        self.grid.get(coord)
+    }
+    fn get_mut(&mut self, coord: Coord) -> Option<&mut T> {
+        self.grid.get_mut(coord)
+    }
+    fn prune<F: FnMut(&T) -> bool>(&mut self, _keep: F) {}
+    fn iter(&self) -> Self::Iter<'_> {
+        self.grid.enumerate()
+    }
+    fn new_like(&self, default: T) -> Self {
+        Self::new(self.grid.size(), default)
+    }
+}
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug)]
+pub struct SparseStore<T> {
+    cells: HashMap<Coord, T>,
+    default: T,
+}
+
+impl<T> SparseStore<T> {
+    pub fn new(default: T) -> Self {
+        Self {
+            cells: HashMap::new(),
+            default,
+        }
+    }
+}
+
+type SparseIter<'a, T> = iter::Map<hash_map::Iter<'a, Coord, T>, fn((&'a Coord, &'a T)) -> (Coord, &'a T)>;
+
+impl<T: Clone + Debug> Store<T> for SparseStore<T> {
+    type Iter<'a>
+        = SparseIter<'a, T>
+    where
+        T: 'a;
+    fn size(&self) -> Option<Size> {
+        None
+    }
+    fn get(&self, coord: Coord) -> Option<&T> {
+        self.cells.get(&coord)
+    }
+    fn get_mut(&mut self, coord: Coord) -> Option<&mut T> {
+        let default = &self.default;
+        Some(self.cells.entry(coord).or_insert_with(|| default.clone()))
+    }
+    fn prune<F: FnMut(&T) -> bool>(&mut self, mut keep: F) {
+        self.cells.retain(|_, cell| keep(cell));
+    }
+    fn iter(&self) -> Self::Iter<'_> {
+        self.cells.iter().map(|(&coord, cell)| (coord, cell))
+    }
+    fn new_like(&self, default: T) -> Self {
+        Self::new(default)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dense() {
+        let mut store = DenseStore::new(Size::new(3, 2), 0u32);
+        assert_eq!(store.size(), Some(Size::new(3, 2)));
+        *store.get_mut(Coord::new(2, 1)).unwrap() = 5;
+        assert!(store.get_mut(Coord::new(3, 0)).is_none());
+        assert!(store.get(Coord::new(-1, 0)).is_none());
+        store.prune(|&cell| cell != 0);
+        assert_eq!(store.iter().count(), 6);
+        assert_eq!(store.get(Coord::new(2, 1)), Some(&5));
+        let like = store.new_like(1);
+        assert_eq!(like.size(), Some(Size::new(3, 2)));
+        assert!(like.iter().all(|(_, &cell)| cell == 1));
+    }
+
+    #[test]
+    fn sparse() {
+        let mut store = SparseStore::new(0u32);
+        assert_eq!(store.size(), None);
+        assert!(store.get(Coord::new(-100, 100)).is_none());
+        *store.get_mut(Coord::new(-100, 100)).unwrap() = 5;
+        assert_eq!(store.get_mut(Coord::new(i32::MAX, 0)), Some(&mut 0));
+        assert_eq!(store.iter().count(), 2);
+        store.prune(|&cell| cell != 0);
+        assert_eq!(store.iter().collect::<Vec<_>>(), vec![(Coord::new(-100, 100), &5)]);
+        let like = store.new_like(1);
+        assert_eq!(like.iter().count(), 0);
+        assert_eq!(like.clone().get_mut(Coord::new(0, 0)), Some(&mut 1));
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{can_enter::CanEnter, observer::Observer, path::Path};
+use grid_2d::{Coord, Grid, Size};
+use std::fmt::Write as _;
+#[cfg(feature = "trace-json")]
+use std::io::{self, BufRead, Write};
+
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(rename_all = "snake_case"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceEvent {
+    Visit(Coord),
+    Push(Coord),
+    Pop(Coord),
+    Jump { from: Coord, to: Coord },
+    PathCost(u32),
+}
+
+/// Records the events of a search so they can be saved or drawn. Pass a `Trace` to any
+/// `*_with_observer` search, then call `set_path` with the resulting path.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Trace {
+    events: Vec<TraceEvent>,
+    // includes the start of the path
+    path: Vec<Coord>,
+}
+
+impl Observer for Trace {
+    fn push(&mut self, coord: Coord) {
+        self.events.push(TraceEvent::Push(coord));
+    }
+    fn expand(&mut self, coord: Coord) {
+        self.events.push(TraceEvent::Pop(coord));
+    }
+    fn visit(&mut self, coord: Coord) {
+        self.events.push(TraceEvent::Visit(coord));
+    }
+    fn jump(&mut self, from: Coord, to: Coord) {
+        self.events.push(TraceEvent::Jump { from, to });
+    }
+    fn path_cost(&mut self, cost: u32) {
+        self.events.push(TraceEvent::PathCost(cost));
+    }
+}
+
+// one line of the JSON lines format
+#[cfg(feature = "trace-json")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Line {
+    Event(TraceEvent),
+    Path(Vec<Coord>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Mark {
+    None,
+    Visited,
+    Frontier,
+    Expanded,
+}
+
+struct Drawing {
+    marks: Grid<Mark>,
+    on_path: Grid<bool>,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.path.clear();
+    }
+
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    pub fn path(&self) -> &[Coord] {
+        &self.path
+    }
+
+    pub fn set_path(&mut self, start: Coord, path: &Path) {
+        self.path.clear();
+        self.path.push(start);
+        self.path.extend(path.iter().map(|node| node.to_coord));
+    }
+
+    /// Writes one JSON object per line: one for each event in order, followed by the path
+    #[cfg(feature = "trace-json")]
+    pub fn write_json_lines<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for event in &self.events {
+            serde_json::to_writer(&mut writer, &Line::Event(*event))?;
+            writeln!(writer)?;
+        }
+        serde_json::to_writer(&mut writer, &Line::Path(self.path.clone()))?;
+        writeln!(writer)
+    }
+
+    /// Reads a trace in the format written by `write_json_lines`. Jumps must be along a single
+    /// row or column.
+    #[cfg(feature = "trace-json")]
+    pub fn read_json_lines<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut trace = Self::default();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line)? {
+                Line::Event(TraceEvent::Jump { from, to }) if from.x != to.x && from.y != to.y => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("jump is not along a row or column: {}", line),
+                    ));
+                }
+                Line::Event(event) => trace.events.push(event),
+                Line::Path(path) => trace.path = path,
+            }
+        }
+        Ok(trace)
+    }
+
+    fn drawing(&self, size: Size) -> Drawing {
+        let mut marks = Grid::new_copy(size, Mark::None);
+        let mut mark = |coord: Coord, mark: Mark| {
+            if let Some(cell) = marks.get_mut(coord) {
+                *cell = (*cell).max(mark);
+            }
+        };
+        for event in &self.events {
+            match *event {
+                TraceEvent::Visit(coord) => mark(coord, Mark::Visited),
+                TraceEvent::Push(coord) => mark(coord, Mark::Frontier),
+                TraceEvent::Pop(coord) => mark(coord, Mark::Expanded),
+                TraceEvent::Jump { .. } | TraceEvent::PathCost(_) => (),
+            }
+        }
+        let mut on_path = Grid::new_copy(size, false);
+        for &coord in &self.path {
+            if let Some(cell) = on_path.get_mut(coord) {
+                *cell = true;
+            }
+        }
+        Drawing { marks, on_path }
+    }
+
+    /// Draws the grid with cells coloured by how the search treated them. Cells which were
+    /// pushed but never popped make up the frontier. Jumps and the path are drawn as lines.
+    pub fn render_svg<C: CanEnter>(&self, can_enter: &C, size: Size) -> String {
+        const CELL_SIZE: u32 = 16;
+        let Drawing { marks, .. } = self.drawing(size);
+        let centre = |coord: Coord| {
+            (
+                coord.x * CELL_SIZE as i32 + CELL_SIZE as i32 / 2,
+                coord.y * CELL_SIZE as i32 + CELL_SIZE as i32 / 2,
+            )
+        };
+        let mut svg = String::new();
+        let (width, height) = (size.width() * CELL_SIZE, size.height() * CELL_SIZE);
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+        );
+        for (coord, &mark) in marks.enumerate() {
+            let fill = if !can_enter.can_enter(coord) {
+                "#333333"
+            } else {
+                match mark {
+                    Mark::None => "#ffffff",
+                    Mark::Visited => "#e9c46a",
+                    Mark::Frontier => "#2a9d8f",
+                    Mark::Expanded => "#f4a261",
+                }
+            };
+            let _ = writeln!(
+                svg,
+                r##"<rect x="{}" y="{}" width="{CELL_SIZE}" height="{CELL_SIZE}" fill="{fill}" stroke="#cccccc"/>"##,
+                coord.x * CELL_SIZE as i32,
+                coord.y * CELL_SIZE as i32,
+            );
+        }
+        for event in &self.events {
+            if let TraceEvent::Jump { from, to } = *event {
+                let ((x1, y1), (x2, y2)) = (centre(from), centre(to));
+                let _ = writeln!(
+                    svg,
+                    r##"<line x1="{x1}" y1="{y1}" x2="{x2}" y2="{y2}" stroke="#264653" stroke-width="2"/>"##
+                );
+            }
+        }
+        if !self.path.is_empty() {
+            let points = self
+                .path
+                .iter()
+                .map(|&coord| {
+                    let (x, y) = centre(coord);
+                    format!("{x},{y}")
+                })
+                .collect::<Vec<_>>();
+            let _ = writeln!(
+                svg,
+                r##"<polyline points="{}" fill="none" stroke="#e63946" stroke-width="4"/>"##,
+                points.join(" ")
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Draws the grid with one character per cell, using background colours for the cells the
+    /// search reached. Jumped over cells are drawn as lines and the path as `*`.
+    pub fn render_ansi<C: CanEnter>(&self, can_enter: &C, size: Size) -> String {
+        let Drawing { marks, on_path } = self.drawing(size);
+        let mut jump_chars = Grid::new_copy(size, ' ');
+        for event in &self.events {
+            if let TraceEvent::Jump { from, to } = *event {
+                let step = Coord::new((to.x - from.x).signum(), (to.y - from.y).signum());
+                let ch = if step.x == 0 { '|' } else { '-' };
+                // cells past the edge of a grid which wraps around aren't drawn
+                for i in 1..from.manhattan_distance(to) {
+                    if let Some(cell) = jump_chars.get_mut(from + step * i as i32) {
+                        *cell = ch;
+                    }
+                }
+            }
+        }
+        let mut ansi = String::new();
+        for y in 0..size.height() as i32 {
+            for x in 0..size.width() as i32 {
+                let coord = Coord::new(x, y);
+                let background = match marks.get_checked(coord) {
+                    Mark::None => "49",
+                    Mark::Visited => "43",
+                    Mark::Frontier => "46",
+                    Mark::Expanded => "45",
+                };
+                let ch = if !can_enter.can_enter(coord) {
+                    '#'
+                } else if *on_path.get_checked(coord) {
+                    '*'
+                } else if *jump_chars.get_checked(coord) != ' ' {
+                    *jump_chars.get_checked(coord)
+                } else {
+                    '.'
+                };
+                let _ = write!(ansi, "\x1b[{background}m{ch}");
+            }
+            ansi.push_str("\x1b[0m\n");
+        }
+        ansi
+    }
+}
@@ -0,0 +1,73 @@
+use grid_2d::{Coord, Size};
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+
+/// Which axes of a grid wrap around, so that stepping off one edge enters the opposite edge
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Wrap {
+    #[default]
+    None,
+    X,
+    Y,
+    Both,
+}
+
+fn wrap_component(value: i32, len: u32) -> i32 {
+    value.rem_euclid(len as i32)
+}
+
+fn component_distance(a: i32, b: i32, len: u32, wraps: bool) -> u32 {
+    let distance = (a - b).unsigned_abs();
+    if wraps && len > 0 {
+        let distance = distance % len;
+        distance.min(len - distance)
+    } else {
+        distance
+    }
+}
+
+impl Wrap {
+    pub fn wraps_x(self) -> bool {
+        matches!(self, Self::X | Self::Both)
+    }
+
+    pub fn wraps_y(self) -> bool {
+        matches!(self, Self::Y | Self::Both)
+    }
+
+    /// Moves `coord` into the grid along each axis that wraps. Coordinates may still be out of
+    /// bounds along axes that don't wrap.
+    pub fn normalize(self, coord: Coord, size: Size) -> Coord {
+        let x = if self.wraps_x() && size.width() > 0 {
+            wrap_component(coord.x, size.width())
+        } else {
+            coord.x
+        };
+        let y = if self.wraps_y() && size.height() > 0 {
+            wrap_component(coord.y, size.height())
+        } else {
+            coord.y
+        };
+        Coord::new(x, y)
+    }
+
+    /// The manhattan distance between `a` and `b`, allowing either axis to cross a seam if it
+    /// wraps
+    pub fn manhattan_distance(self, a: Coord, b: Coord, size: Size) -> u32 {
+        component_distance(a.x, b.x, size.width(), self.wraps_x())
+            + component_distance(a.y, b.y, size.height(), self.wraps_y())
+    }
+
+    /// The number of steps in `direction` it takes to return to the same cell, if the axis
+    /// along `direction` wraps
+    pub fn period(self, direction: Coord, size: Size) -> Option<u32> {
+        if direction.x != 0 && self.wraps_x() {
+            Some(size.width())
+        } else if direction.y != 0 && self.wraps_y() {
+            Some(size.height())
+        } else {
+            None
+        }
+    }
+}
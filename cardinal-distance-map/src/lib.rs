@@ -1,16 +1,28 @@
 pub use direction::CardinalDirection;
 use direction::CardinalDirections;
-use grid_2d::Grid;
 pub use grid_2d::{Coord, Size};
-pub use grid_search_cardinal_common::{can_enter::CanEnter, coord::UnitCoord, step::Step};
+pub use grid_search_cardinal_common::storage::{Dense, Sparse, Storage};
+pub use grid_search_cardinal_common::{
+    can_enter::{CanEnter, Portal, Portals},
+    clearance::{AgentClearance, ClearanceMap, WithClearance},
+    coord::UnitCoord,
+    error::SearchError,
+    observer::{Observer, SearchStats},
+    path::{Move, PortalPath, PortalPathNode},
+    step::Step,
+    trace::{Trace, TraceEvent},
+    wrap::Wrap,
+};
 use grid_search_cardinal_common::{
     coord::UNIT_COORDS,
+    min_heap::MinHeapNode,
     path::Path,
     seen_set::{SeenSet, Visit},
+    storage::{DenseStore, SparseStore, Store},
 };
 #[cfg(feature = "serialize")]
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
 
 pub type Distance = u32;
 
@@ -23,9 +35,29 @@ struct Cell {
 
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone)]
-pub struct DistanceMap {
+pub struct DistanceMap<B: Storage = Dense> {
     count: u64,
-    grid: Grid<Cell>,
+    grid: B::Store<Cell>,
+    // missing from distance maps serialized before grids could wrap
+    #[cfg_attr(feature = "serialize", serde(default))]
+    wrap: Wrap,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PopulateMatrixError {
+    Search(SearchError),
+    /// No distance maps were given to hold the floods
+    NoDistanceMaps,
+}
+
+/// Shortest distances from each of a set of sources to each of a set of targets
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Default, Debug, Clone)]
+pub struct DistanceMatrix {
+    num_sources: usize,
+    num_targets: usize,
+    // distances[source_index * num_targets + target_index], or `None` if unreachable
+    distances: Vec<Option<Distance>>,
 }
 
 #[derive(Debug, Clone)]
@@ -34,9 +66,15 @@ struct PopulateNode {
     distance: Distance,
 }
 
+// portals have costs, so populating through them pops the closest cell first
+type PortalNode = MinHeapNode<Distance, Coord>;
+
 #[derive(Default, Debug, Clone)]
 pub struct PopulateContext {
     queue: VecDeque<PopulateNode>,
+    priority_queue: BinaryHeap<PortalNode>,
+    // maps the destination of each portal to where it's taken from and its cost
+    portals_into: HashMap<Coord, Vec<(Coord, Distance)>>,
 }
 
 #[derive(Debug, Clone)]
@@ -46,8 +84,8 @@ struct SearchNode {
 }
 
 #[derive(Debug, Clone)]
-pub struct SearchContext {
-    seen_set: SeenSet,
+pub struct SearchContext<B: Storage = Dense> {
+    seen_set: SeenSet<B>,
     queue: VecDeque<SearchNode>,
 }
 
@@ -56,8 +94,8 @@ struct SearchState {
     closest_coord: Coord,
 }
 
-struct SearchInstance<'a, C: 'a + CanEnter> {
-    distance_map: &'a DistanceMap,
+struct SearchInstance<'a, B: Storage, C: 'a + CanEnter> {
+    distance_map: &'a DistanceMap<B>,
     can_enter: &'a C,
     max_distance: Distance,
     search_state: SearchState,
@@ -68,7 +106,7 @@ struct Prune {
     distance_to_goal: Distance,
 }
 
-impl<'a, C: CanEnter> SearchInstance<'a, C> {
+impl<'a, B: Storage, C: CanEnter> SearchInstance<'a, B, C> {
     fn prune(&self, prune: Prune) -> bool {
         let remaining_distance = self.max_distance - prune.current_distance;
         if let Some(best_possible_distance_through_cell) = prune.distance_to_goal.checked_sub(remaining_distance) {
@@ -78,8 +116,16 @@ impl<'a, C: CanEnter> SearchInstance<'a, C> {
         }
         false
     }
-    fn consider(&mut self, context: &mut SearchContext, step: Step, distance: Distance) {
+    fn consider<O: Observer>(
+        &mut self,
+        context: &mut SearchContext<B>,
+        step: Step,
+        distance: Distance,
+        observer: &mut O,
+    ) {
+        let step = self.distance_map.wrap_step(step);
         if let Some(Visit) = context.seen_set.try_visit_step(step, distance) {
+            observer.visit(step.to_coord);
             if self.can_enter.can_step(step) {
                 if let Some(distance_to_goal) = self.distance_map.distance(step.to_coord) {
                     if distance <= self.max_distance {
@@ -93,7 +139,9 @@ impl<'a, C: CanEnter> SearchInstance<'a, C> {
                             self.search_state.closest_coord = step.to_coord;
                             self.search_state.distance_to_goal = distance_to_goal;
                         }
+                        observer.push(step.to_coord);
                         context.queue.push_back(SearchNode { step, distance });
+                        observer.queue_len(context.queue.len());
                     }
                 }
             }
@@ -130,30 +178,121 @@ impl<'a> Deserialize<'a> for SearchContext {
     }
 }
 
-impl DistanceMap {
+const UNSEEN: Cell = Cell { count: 0, distance: 0 };
+
+impl DistanceMap<Dense> {
     pub fn new(size: Size) -> Self {
         Self {
             count: 1,
-            grid: Grid::new_fn(size, |_| Cell { count: 0, distance: 0 }),
+            grid: DenseStore::new(size, UNSEEN),
+            wrap: Wrap::None,
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.bounds().expect("dense storage is bounded")
+    }
+
+    /// Makes the grid wrap around along some axes, so that distances are measured across the
+    /// seams. This applies the next time the map is populated, and to searches using the map.
+    pub fn set_wrap(&mut self, wrap: Wrap) {
+        self.wrap = wrap;
+    }
+}
+
+impl DistanceMap<Sparse> {
+    /// A distance map with no bounds, where coordinates can be any `i32`. Only the cells reached
+    /// when populating it are stored, so in open worlds keep `max_distance` small.
+    pub fn new_unbounded() -> Self {
+        Self {
+            count: 1,
+            grid: SparseStore::new(UNSEEN),
+            wrap: Wrap::None,
+        }
+    }
+}
+
+impl<B: Storage> DistanceMap<B> {
+    /// The size of the grid, or `None` if the map is unbounded
+    pub fn bounds(&self) -> Option<Size> {
+        self.grid.size()
+    }
+
+    pub fn wrap(&self) -> Wrap {
+        self.wrap
+    }
+
+    fn normalize(&self, coord: Coord) -> Coord {
+        match self.grid.size() {
+            Some(size) => self.wrap.normalize(coord, size),
+            None => coord,
+        }
+    }
+
+    fn wrap_step(&self, step: Step) -> Step {
+        Step {
+            to_coord: self.normalize(step.to_coord),
+            in_direction: step.in_direction,
         }
     }
 
     pub fn clear(&mut self) {
         self.count += 1;
+        let count = self.count;
+        self.grid.prune(|cell| cell.count == count);
     }
 
-    pub fn size(&self) -> Size {
-        self.grid.size()
+    // records `distance` for `coord` if it's the shortest found since the map was last cleared
+    fn improve(&mut self, coord: Coord, distance: Distance) -> bool {
+        match self.grid.get_mut(coord) {
+            Some(cell) if cell.count != self.count || distance < cell.distance => {
+                cell.count = self.count;
+                cell.distance = distance;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// For a map populated with `populate_approach_portals`, the cheapest move from `coord`
+    /// towards the nearest goal, which may be a step or a portal
+    pub fn best_move_with_portals<P: Portals>(&self, portals: &P, coord: Coord) -> Option<PortalPathNode> {
+        if self.distance(coord)? == 0 {
+            return None;
+        }
+        let mut best: Option<(Distance, PortalPathNode)> = None;
+        let mut consider = |distance: Distance, node: PortalPathNode| {
+            if best.map_or(true, |(best_distance, _)| distance < best_distance) {
+                best = Some((distance, node));
+            }
+        };
+        for direction in CardinalDirections {
+            let to_coord = self.normalize(coord + direction.coord());
+            if let Some(distance) = self.distance(to_coord) {
+                let via = Move::Step(direction);
+                consider(distance + 1, PortalPathNode { to_coord, via });
+            }
+        }
+        portals.for_each_portal(coord, |portal| {
+            if let Some(distance) = self.distance(portal.to_coord) {
+                let node = PortalPathNode {
+                    to_coord: portal.to_coord,
+                    via: Move::Portal,
+                };
+                consider(distance.saturating_add(portal.cost), node);
+            }
+        });
+        best.map(|(_, node)| node)
     }
 
     pub fn direction_to_best_neighbour(&self, coord: Coord) -> Option<CardinalDirection> {
-        let mut shortest_distance = std::u32::MAX;
+        let mut shortest_distance = u32::MAX;
         let mut direction_to_best_neighbour = None;
         if let Some(distance) = self.distance(coord) {
             shortest_distance = distance;
         }
         for direction in CardinalDirections {
-            let neighbour_coord = coord + direction.coord();
+            let neighbour_coord = self.normalize(coord + direction.coord());
             if let Some(distance) = self.distance(neighbour_coord) {
                 if distance <= shortest_distance {
                     shortest_distance = distance;
@@ -165,7 +304,7 @@ impl DistanceMap {
     }
 
     pub fn distance(&self, coord: Coord) -> Option<Distance> {
-        if let Some(cell) = self.grid.get(coord) {
+        if let Some(cell) = self.grid.get(self.normalize(coord)) {
             if cell.count == self.count {
                 return Some(cell.distance);
             }
@@ -183,11 +322,21 @@ impl PopulateContext {
         self.queue.push_front(PopulateNode { coord, distance: 0 });
     }
 
-    pub fn populate_approach<C: CanEnter>(
+    pub fn populate_approach<B: Storage, C: CanEnter>(
+        &mut self,
+        can_enter: &C,
+        max_distance: Distance,
+        distance_map: &mut DistanceMap<B>,
+    ) {
+        self.populate_approach_with_observer(can_enter, max_distance, distance_map, &mut ());
+    }
+
+    pub fn populate_approach_with_observer<B: Storage, C: CanEnter, O: Observer>(
         &mut self,
         can_enter: &C,
         max_distance: Distance,
-        distance_map: &mut DistanceMap,
+        distance_map: &mut DistanceMap<B>,
+        observer: &mut O,
     ) {
         distance_map.clear();
         for node in self.queue.iter() {
@@ -202,9 +351,10 @@ impl PopulateContext {
         }
         while let Some(PopulateNode { coord, distance }) = self.queue.pop_back() {
             debug_assert!(distance < max_distance);
+            observer.expand(coord);
             let neighbour_distance = distance + 1;
             for direction in CardinalDirections {
-                let neighbour_coord = coord + direction.coord();
+                let neighbour_coord = distance_map.normalize(coord + direction.coord());
                 if can_enter.can_step(Step {
                     to_coord: neighbour_coord,
                     in_direction: UnitCoord::from_cardinal_direction(direction),
@@ -213,11 +363,14 @@ impl PopulateContext {
                         if cell.count != distance_map.count {
                             cell.count = distance_map.count;
                             cell.distance = neighbour_distance;
+                            observer.visit(neighbour_coord);
                             if neighbour_distance != max_distance {
+                                observer.push(neighbour_coord);
                                 self.queue.push_front(PopulateNode {
                                     coord: neighbour_coord,
                                     distance: neighbour_distance,
                                 });
+                                observer.queue_len(self.queue.len());
                             }
                         }
                     }
@@ -226,13 +379,23 @@ impl PopulateContext {
         }
     }
 
-    pub fn populate_flee<C: CanEnter>(
+    pub fn populate_flee<B: Storage, C: CanEnter>(
         &mut self,
         can_enter: &C,
         max_distance: Distance,
-        distance_map: &mut DistanceMap,
+        distance_map: &mut DistanceMap<B>,
     ) {
-        distance_map.count += 1;
+        self.populate_flee_with_observer(can_enter, max_distance, distance_map, &mut ());
+    }
+
+    pub fn populate_flee_with_observer<B: Storage, C: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &C,
+        max_distance: Distance,
+        distance_map: &mut DistanceMap<B>,
+        observer: &mut O,
+    ) {
+        distance_map.clear();
         for node in self.queue.iter() {
             if let Some(cell) = distance_map.grid.get_mut(node.coord) {
                 cell.count = distance_map.count;
@@ -249,9 +412,10 @@ impl PopulateContext {
                 self.queue.push_back(PopulateNode { coord, distance });
                 break;
             }
+            observer.expand(coord);
             let neighbour_distance = distance + 1;
             for direction in CardinalDirections {
-                let neighbour_coord = coord + direction.coord();
+                let neighbour_coord = distance_map.normalize(coord + direction.coord());
                 if can_enter.can_step(Step {
                     to_coord: neighbour_coord,
                     in_direction: UnitCoord::from_cardinal_direction(direction),
@@ -260,10 +424,13 @@ impl PopulateContext {
                         if cell.count != distance_map.count {
                             cell.count = distance_map.count;
                             cell.distance = neighbour_distance;
+                            observer.visit(neighbour_coord);
+                            observer.push(neighbour_coord);
                             self.queue.push_front(PopulateNode {
                                 coord: neighbour_coord,
                                 distance: neighbour_distance,
                             });
+                            observer.queue_len(self.queue.len());
                         }
                     }
                 }
@@ -283,17 +450,103 @@ impl PopulateContext {
             }
         }
         while let Some(PopulateNode { coord, distance }) = self.queue.pop_back() {
+            observer.expand(coord);
             let neighbour_distance = distance + 1;
             for direction in CardinalDirections {
-                let neighbour_coord = coord + direction.coord();
+                let neighbour_coord = distance_map.normalize(coord + direction.coord());
                 if let Some(cell) = distance_map.grid.get_mut(neighbour_coord) {
                     if cell.count == distance_map.count - 1 {
                         cell.count += 1;
                         cell.distance = neighbour_distance;
+                        observer.visit(neighbour_coord);
+                        observer.push(neighbour_coord);
                         self.queue.push_front(PopulateNode {
                             coord: neighbour_coord,
                             distance: neighbour_distance,
                         });
+                        observer.queue_len(self.queue.len());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Like `populate_approach`, for a world with portals. Portals are followed backwards, so
+    /// each cell's distance is the cheapest cost of reaching an added coordinate from it, where
+    /// each step costs 1.
+    pub fn populate_approach_portals<B: Storage, P: Portals>(
+        &mut self,
+        portals: &P,
+        max_distance: Distance,
+        distance_map: &mut DistanceMap<B>,
+    ) {
+        self.populate_approach_portals_with_observer(portals, max_distance, distance_map, &mut ());
+    }
+
+    pub fn populate_approach_portals_with_observer<B: Storage, P: Portals, O: Observer>(
+        &mut self,
+        portals: &P,
+        max_distance: Distance,
+        distance_map: &mut DistanceMap<B>,
+        observer: &mut O,
+    ) {
+        distance_map.clear();
+        self.portals_into.clear();
+        let portals_into = &mut self.portals_into;
+        portals.for_each_portal_in_world(|from_coord, portal| {
+            let to_coord = distance_map.normalize(portal.to_coord);
+            portals_into
+                .entry(to_coord)
+                .or_default()
+                .push((from_coord, portal.cost));
+        });
+        self.priority_queue.clear();
+        for node in self.queue.drain(..) {
+            if distance_map.improve(node.coord, 0) {
+                self.priority_queue.push(PortalNode {
+                    key: 0,
+                    value: node.coord,
+                });
+            }
+        }
+        while let Some(PortalNode {
+            key: distance,
+            value: coord,
+        }) = self.priority_queue.pop()
+        {
+            if distance_map.distance(coord) != Some(distance) {
+                // a shorter distance to this cell was found after this node was queued
+                continue;
+            }
+            observer.expand(coord);
+            for direction in CardinalDirections {
+                let neighbour_coord = distance_map.normalize(coord + direction.coord());
+                if portals.can_step(Step {
+                    to_coord: neighbour_coord,
+                    in_direction: UnitCoord::from_cardinal_direction(direction),
+                }) {
+                    relax(
+                        &mut self.priority_queue,
+                        distance_map,
+                        neighbour_coord,
+                        distance + 1,
+                        max_distance,
+                        observer,
+                    );
+                }
+            }
+            if let Some(sources) = self.portals_into.get(&coord) {
+                for &(from_coord, cost) in sources {
+                    if portals.can_enter(from_coord) {
+                        let from_distance = distance.saturating_add(cost);
+                        relax(
+                            &mut self.priority_queue,
+                            distance_map,
+                            from_coord,
+                            from_distance,
+                            max_distance,
+                            observer,
+                        );
                     }
                 }
             }
@@ -301,28 +554,203 @@ impl PopulateContext {
     }
 }
 
-impl SearchContext {
+fn relax<B: Storage, O: Observer>(
+    priority_queue: &mut BinaryHeap<PortalNode>,
+    distance_map: &mut DistanceMap<B>,
+    coord: Coord,
+    distance: Distance,
+    max_distance: Distance,
+    observer: &mut O,
+) {
+    if distance <= max_distance && distance_map.improve(coord, distance) {
+        observer.visit(coord);
+        observer.push(coord);
+        priority_queue.push(PortalNode {
+            key: distance,
+            value: coord,
+        });
+        observer.queue_len(priority_queue.len());
+    }
+}
+
+impl DistanceMatrix {
+    pub fn num_sources(&self) -> usize {
+        self.num_sources
+    }
+
+    pub fn num_targets(&self) -> usize {
+        self.num_targets
+    }
+
+    /// The distance from the source at `source_index` to the target at `target_index`, or
+    /// `None` if it can't be reached
+    pub fn distance(&self, source_index: usize, target_index: usize) -> Option<Distance> {
+        assert!(target_index < self.num_targets, "target index out of range");
+        self.distances[source_index * self.num_targets + target_index]
+    }
+
+    /// Distances from the source at `source_index` to each target
+    pub fn row(&self, source_index: usize) -> &[Option<Distance>] {
+        let start = source_index * self.num_targets;
+        &self.distances[start..(start + self.num_targets)]
+    }
+
+    fn prepare<B: Storage>(
+        &mut self,
+        distance_map: &DistanceMap<B>,
+        sources: &[Coord],
+        targets: &[Coord],
+    ) -> Result<(), SearchError> {
+        if let Some(size) = distance_map.bounds() {
+            SearchError::check_bounds(size, sources, targets)?;
+        }
+        self.num_sources = sources.len();
+        self.num_targets = targets.len();
+        self.distances.clear();
+        self.distances.resize(sources.len() * targets.len(), None);
+        Ok(())
+    }
+
+    // `target_major` holds the distances from each source in turn for each target
+    fn finish(&mut self, target_major: Vec<Option<Distance>>) {
+        for (target_index, row) in target_major.chunks(self.num_sources.max(1)).enumerate() {
+            for (source_index, &distance) in row.iter().enumerate() {
+                self.distances[source_index * self.num_targets + target_index] = distance;
+            }
+        }
+    }
+}
+
+impl PopulateContext {
+    /// Fills `matrix` with the distance from each source to each target, with one flood per
+    /// target. Each distance is the one an approach map to the target gives at the source, so
+    /// worlds needn't be symmetric. `distance_map` is used to hold each flood in turn.
+    pub fn populate_matrix<B: Storage, C: CanEnter>(
+        &mut self,
+        can_enter: &C,
+        distance_map: &mut DistanceMap<B>,
+        sources: &[Coord],
+        targets: &[Coord],
+        matrix: &mut DistanceMatrix,
+    ) -> Result<(), SearchError> {
+        matrix.prepare(distance_map, sources, targets)?;
+        let mut target_major = vec![None; matrix.distances.len()];
+        self.flood_rows(can_enter, distance_map, targets, sources, &mut target_major);
+        matrix.finish(target_major);
+        Ok(())
+    }
+
+    /// Like `populate_matrix`, but divides the floods between threads, one per distance map in
+    /// `distance_maps`. The distance maps must all cover the same grid.
+    pub fn populate_matrix_parallel<B, C>(
+        &mut self,
+        can_enter: &C,
+        distance_maps: &mut [DistanceMap<B>],
+        sources: &[Coord],
+        targets: &[Coord],
+        matrix: &mut DistanceMatrix,
+    ) -> Result<(), PopulateMatrixError>
+    where
+        B: Storage,
+        C: CanEnter + Sync,
+        DistanceMap<B>: Send,
+    {
+        let (first, rest) = distance_maps
+            .split_first_mut()
+            .ok_or(PopulateMatrixError::NoDistanceMaps)?;
+        for distance_map in rest.iter() {
+            if let (Some(expected), Some(actual)) = (first.bounds(), distance_map.bounds()) {
+                if expected != actual {
+                    return Err(PopulateMatrixError::Search(SearchError::SizeMismatch {
+                        expected,
+                        actual,
+                    }));
+                }
+            }
+        }
+        matrix
+            .prepare(first, sources, targets)
+            .map_err(PopulateMatrixError::Search)?;
+        let targets_per_thread = targets.len().div_ceil(distance_maps.len()).max(1);
+        let mut target_major = vec![None; matrix.distances.len()];
+        std::thread::scope(|scope| {
+            let mut jobs = distance_maps
+                .iter_mut()
+                .zip(targets.chunks(targets_per_thread))
+                .zip(target_major.chunks_mut(targets_per_thread * sources.len().max(1)));
+            // this context floods the first chunk on the current thread
+            let first_job = jobs.next();
+            for ((distance_map, targets), rows) in jobs {
+                scope.spawn(move || {
+                    PopulateContext::default().flood_rows(can_enter, distance_map, targets, sources, rows)
+                });
+            }
+            if let Some(((distance_map, targets), rows)) = first_job {
+                self.flood_rows(can_enter, distance_map, targets, sources, rows);
+            }
+        });
+        matrix.finish(target_major);
+        Ok(())
+    }
+
+    fn flood_rows<B: Storage, C: CanEnter>(
+        &mut self,
+        can_enter: &C,
+        distance_map: &mut DistanceMap<B>,
+        targets: &[Coord],
+        sources: &[Coord],
+        rows: &mut [Option<Distance>],
+    ) {
+        for (&target, row) in targets.iter().zip(rows.chunks_mut(sources.len().max(1))) {
+            self.clear();
+            self.add(target);
+            self.populate_approach(can_enter, Distance::MAX, distance_map);
+            for (distance, &source) in row.iter_mut().zip(sources) {
+                *distance = distance_map.distance(source);
+            }
+        }
+    }
+}
+
+impl SearchContext<Dense> {
     pub fn new(size: Size) -> Self {
         Self {
             seen_set: SeenSet::new(size),
             queue: VecDeque::new(),
         }
     }
+}
 
-    fn search_core<C: CanEnter>(
+impl SearchContext<Sparse> {
+    pub fn new_unbounded() -> Self {
+        Self {
+            seen_set: SeenSet::new_unbounded(),
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl<B: Storage> SearchContext<B> {
+    fn search_core<C: CanEnter, O: Observer>(
         &mut self,
         can_enter: &C,
         start: Coord,
         max_distance: Distance,
-        distance_map: &DistanceMap,
-    ) -> Option<Coord> {
+        distance_map: &DistanceMap<B>,
+        observer: &mut O,
+    ) -> Result<Coord, SearchError> {
+        if let (Some(expected), Some(actual)) = (self.seen_set.bounds(), distance_map.bounds()) {
+            SearchError::check_size(expected, actual)?;
+        }
+        self.seen_set.check_bounds(&[start], &[])?;
+        self.seen_set.set_wrap(distance_map.wrap());
         let search_state = if let Some(distance_to_goal) = distance_map.distance(start) {
             SearchState {
                 distance_to_goal,
                 closest_coord: start,
             }
         } else {
-            return None;
+            return Err(SearchError::StartNotInDistanceMap(start));
         };
         self.seen_set.init(start);
         self.queue.clear();
@@ -337,7 +765,7 @@ impl SearchContext {
                 to_coord: start + in_direction.to_coord(),
                 in_direction,
             };
-            instance.consider(self, step, 1);
+            instance.consider(self, step, 1, observer);
         }
         while let Some(SearchNode { step, distance }) = self.queue.pop_front() {
             if instance.prune(Prune {
@@ -346,12 +774,15 @@ impl SearchContext {
             }) {
                 continue;
             }
+            observer.expand(step.to_coord);
             let next_distance = distance + 1;
-            instance.consider(self, step.forward(), next_distance);
-            instance.consider(self, step.left(), next_distance);
-            instance.consider(self, step.right(), next_distance);
+            instance.consider(self, step.forward(), next_distance, observer);
+            instance.consider(self, step.left(), next_distance, observer);
+            instance.consider(self, step.right(), next_distance, observer);
         }
-        Some(instance.search_state.closest_coord)
+        let end = instance.search_state.closest_coord;
+        observer.path_cost(self.seen_set.cost(end).unwrap_or(0));
+        Ok(end)
     }
 
     pub fn search_path<C: CanEnter>(
@@ -359,12 +790,24 @@ impl SearchContext {
         can_enter: &C,
         start: Coord,
         max_distance: Distance,
-        distance_map: &DistanceMap,
+        distance_map: &DistanceMap<B>,
         path: &mut Path,
-    ) {
-        if let Some(end) = self.search_core(can_enter, start, max_distance, distance_map) {
-            self.seen_set.build_path_to(end, path);
-        }
+    ) -> Result<(), SearchError> {
+        self.search_path_with_observer(can_enter, start, max_distance, distance_map, path, &mut ())
+    }
+
+    pub fn search_path_with_observer<C: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &C,
+        start: Coord,
+        max_distance: Distance,
+        distance_map: &DistanceMap<B>,
+        path: &mut Path,
+        observer: &mut O,
+    ) -> Result<(), SearchError> {
+        let end = self.search_core(can_enter, start, max_distance, distance_map, observer)?;
+        self.seen_set.build_path_to(end, path);
+        Ok(())
     }
 
     pub fn search_first<C: CanEnter>(
@@ -372,19 +815,28 @@ impl SearchContext {
         can_enter: &C,
         start: Coord,
         max_distance: Distance,
-        distance_map: &DistanceMap,
-    ) -> Option<CardinalDirection> {
-        if let Some(end) = self.search_core(can_enter, start, max_distance, distance_map) {
-            self.seen_set.first_direction_towards(end)
-        } else {
-            None
-        }
+        distance_map: &DistanceMap<B>,
+    ) -> Result<Option<CardinalDirection>, SearchError> {
+        self.search_first_with_observer(can_enter, start, max_distance, distance_map, &mut ())
+    }
+
+    pub fn search_first_with_observer<C: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &C,
+        start: Coord,
+        max_distance: Distance,
+        distance_map: &DistanceMap<B>,
+        observer: &mut O,
+    ) -> Result<Option<CardinalDirection>, SearchError> {
+        let end = self.search_core(can_enter, start, max_distance, distance_map, observer)?;
+        Ok(self.seen_set.first_direction_towards(end))
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use grid_2d::Grid;
 
     #[derive(Clone)]
     enum Cell {
@@ -478,7 +930,9 @@ mod test {
             distance_map.direction_to_best_neighbour(Coord::new(4, 6)),
             Some(CardinalDirection::South)
         );
-        search_context.search_path(&world, Coord::new(7, 7), 100, &distance_map, &mut path);
+        search_context
+            .search_path(&world, Coord::new(7, 7), 100, &distance_map, &mut path)
+            .unwrap();
         let directions = path.iter().map(|n| n.in_direction).collect::<Vec<_>>();
         assert_eq!(&directions, &[West, West, West, West, West, West, South]);
         assert_eq!(distance_map.direction_to_best_neighbour(Coord::new(1, 8)), None,);
@@ -493,11 +947,93 @@ mod test {
             distance_map.direction_to_best_neighbour(Coord::new(1, 7)),
             Some(CardinalDirection::East)
         );
-        search_context.search_path(&world, Coord::new(7, 7), 5, &distance_map, &mut path);
+        search_context
+            .search_path(&world, Coord::new(7, 7), 5, &distance_map, &mut path)
+            .unwrap();
         let directions = path.iter().map(|n| n.in_direction).collect::<Vec<_>>();
         assert_eq!(&directions, &[West, West, West, North, North]);
     }
 
+    #[test]
+    fn wrap() {
+        use CardinalDirection::*;
+        let mut path = Path::default();
+        let Test { world, goals } = Test::from_str_slice(GRID_A);
+        let mut populate_context = PopulateContext::default();
+        let mut distance_map = DistanceMap::new(world.grid.size());
+        distance_map.set_wrap(Wrap::X);
+        let mut search_context = SearchContext::new(distance_map.size());
+        for &coord in &goals {
+            populate_context.add(coord);
+        }
+        populate_context.populate_approach(&world, 100, &mut distance_map);
+        assert_eq!(distance_map.distance(Coord::new(9, 8)), Some(2));
+        assert_eq!(distance_map.distance(Coord::new(7, 7)), Some(5));
+        assert_eq!(distance_map.direction_to_best_neighbour(Coord::new(9, 8)), Some(East));
+        search_context
+            .search_path(&world, Coord::new(7, 7), 100, &distance_map, &mut path)
+            .unwrap();
+        assert_eq!(path.len(), 5);
+        assert_eq!(path.iter().filter(|node| node.in_direction == East).count(), 4);
+        assert_eq!(path.iter().last().map(|node| node.to_coord), Some(Coord::new(1, 8)));
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn deserialize_without_wrap() {
+        let Test { world, goals } = Test::from_str_slice(GRID_A);
+        let mut populate_context = PopulateContext::default();
+        let mut distance_map = DistanceMap::new(world.grid.size());
+        for &coord in &goals {
+            populate_context.add(coord);
+        }
+        populate_context.populate_approach(&world, 100, &mut distance_map);
+        let json = serde_json::to_string(&distance_map).unwrap();
+        // distance maps serialized before grids could wrap have no wrap field
+        let json = json.replace(r#","wrap":"None""#, "");
+        assert!(!json.contains("wrap"));
+        let deserialized: DistanceMap = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.wrap(), Wrap::None);
+        assert_eq!(
+            deserialized.distance(Coord::new(7, 7)),
+            distance_map.distance(Coord::new(7, 7))
+        );
+    }
+
+    #[test]
+    fn observer() {
+        let Test { world, goals } = Test::from_str_slice(GRID_A);
+        let mut populate_context = PopulateContext::default();
+        let mut distance_map = DistanceMap::new(world.grid.size());
+        let mut search_context = SearchContext::new(distance_map.size());
+        for &coord in &goals {
+            populate_context.add(coord);
+        }
+        let mut stats = SearchStats::default();
+        populate_context.populate_approach_with_observer(&world, 7, &mut distance_map, &mut stats);
+        let distances = world
+            .grid
+            .coord_iter()
+            .filter_map(|coord| distance_map.distance(coord))
+            .collect::<Vec<_>>();
+        let num_visited = distances.iter().filter(|&&distance| distance > 0).count() as u64;
+        let num_pushed = distances
+            .iter()
+            .filter(|&&distance| distance > 0 && distance < 7)
+            .count() as u64;
+        assert_eq!(stats.cells_visited, num_visited);
+        assert_eq!(stats.nodes_pushed, num_pushed);
+        assert_eq!(stats.nodes_expanded, num_pushed + goals.len() as u64);
+        assert_eq!(stats.path_cost, None);
+        let mut stats = SearchStats::default();
+        let mut path = Path::default();
+        search_context
+            .search_path_with_observer(&world, Coord::new(7, 7), 100, &distance_map, &mut path, &mut stats)
+            .unwrap();
+        assert_eq!(stats.path_cost, Some(path.len() as u32));
+        assert!(stats.nodes_expanded <= stats.nodes_pushed);
+    }
+
     const GRID_B: &[&str] = &[
         "..........",
         "..........",
@@ -522,9 +1058,24 @@ mod test {
         populate_context.populate_flee(&world, 7, &mut distance_map);
         assert_eq!(distance_map.distance(Coord::new(4, 5)), None);
         let mut path = Path::default();
-        search_context.search_path(&world, Coord::new(7, 7), 5, &distance_map, &mut path);
+        assert_eq!(
+            search_context.search_path(&world, Coord::new(7, 7), 5, &distance_map, &mut path),
+            Err(SearchError::StartNotInDistanceMap(Coord::new(7, 7)))
+        );
         let directions = path.iter().map(|n| n.in_direction).collect::<Vec<_>>();
         assert_eq!(&directions, &[]);
+        assert_eq!(
+            search_context.search_path(&world, Coord::new(10, 7), 5, &distance_map, &mut path),
+            Err(SearchError::StartOutOfBounds(Coord::new(10, 7)))
+        );
+        let small_distance_map = DistanceMap::new(Size::new(5, 5));
+        assert_eq!(
+            search_context.search_first(&world, Coord::new(3, 3), 5, &small_distance_map),
+            Err(SearchError::SizeMismatch {
+                expected: world.grid.size(),
+                actual: Size::new(5, 5)
+            })
+        );
     }
 
     const GRID_C: &[&str] = &[
@@ -568,4 +1119,294 @@ mod test {
             Some(CardinalDirection::West)
         );
     }
+
+    // open everywhere apart from a ring of solid cells around the origin
+    struct Ring;
+
+    impl CanEnter for Ring {
+        fn can_enter(&self, coord: Coord) -> bool {
+            coord.x.abs().max(coord.y.abs()) != 3
+        }
+    }
+
+    #[test]
+    fn unbounded() {
+        let mut path = Path::default();
+        let mut populate_context = PopulateContext::default();
+        let mut distance_map = DistanceMap::new_unbounded();
+        let mut search_context = SearchContext::new_unbounded();
+        let goal = Coord::new(-1000, -1000);
+        populate_context.add(goal);
+        populate_context.populate_approach(&Ring, 20, &mut distance_map);
+        assert_eq!(distance_map.bounds(), None);
+        assert_eq!(distance_map.distance(Coord::new(-1010, -990)), Some(20));
+        assert_eq!(distance_map.distance(Coord::new(-1010, -989)), None);
+        let start = Coord::new(-1005, -1007);
+        search_context
+            .search_path(&Ring, start, 100, &distance_map, &mut path)
+            .unwrap();
+        assert_eq!(path.len(), 12);
+        assert_eq!(path.iter().last().map(|node| node.to_coord), Some(goal));
+        assert_eq!(
+            search_context.search_path(&Ring, Coord::new(0, 0), 100, &distance_map, &mut path),
+            Err(SearchError::StartNotInDistanceMap(Coord::new(0, 0)))
+        );
+        // every cell inside the ring is within the max distance, so the distances are the same
+        // as when approaching
+        populate_context.add(Coord::new(0, 0));
+        populate_context.populate_flee(&Ring, 10, &mut distance_map);
+        assert_eq!(distance_map.distance(Coord::new(2, 2)), Some(4));
+        assert_eq!(distance_map.distance(goal), None);
+    }
+
+    #[test]
+    fn clearance() {
+        let Test { world, goals } = Test::from_str_slice(GRID_A);
+        let mut clearance_map = ClearanceMap::new(world.grid.size());
+        clearance_map.compute(&world);
+        let mut populate_context = PopulateContext::default();
+        let mut distance_map = DistanceMap::new(world.grid.size());
+        for agent_size in 1..=2 {
+            for &coord in &goals {
+                populate_context.add(coord);
+            }
+            populate_context.populate_approach(&clearance_map.for_agent(agent_size), 100, &mut distance_map);
+            // only a 1x1 agent fits through the gap in the wall
+            assert_eq!(distance_map.distance(Coord::new(4, 5)).is_some(), agent_size == 1);
+            assert_eq!(distance_map.distance(Coord::new(7, 7)), Some(7));
+        }
+    }
+
+    // an open world whose top row can't be walked along westwards
+    struct NoWestInTopRow;
+
+    impl CanEnter for NoWestInTopRow {
+        fn can_enter(&self, _coord: Coord) -> bool {
+            true
+        }
+        fn can_step(&self, step: Step) -> bool {
+            step.to_coord.y != 0 || step.in_direction.to_cardinal_direction() != CardinalDirection::West
+        }
+    }
+
+    #[test]
+    fn with_clearance() {
+        let size = Size::new(5, 3);
+        let mut clearance_map = ClearanceMap::new(size);
+        clearance_map.compute(&NoWestInTopRow);
+        let mut populate_context = PopulateContext::default();
+        let mut distance_map = DistanceMap::new(size);
+        for agent_size in 1..=2 {
+            let goal = Coord::new(4 - agent_size as i32, 0);
+            populate_context.add(goal);
+            populate_context.populate_approach(&clearance_map.for_agent(agent_size), 100, &mut distance_map);
+            assert_eq!(distance_map.distance(goal - Coord::new(1, 0)), Some(1));
+            // the flood leaves the goal's row through the second row, where the agent still fits
+            populate_context.add(goal);
+            let world = WithClearance::new(NoWestInTopRow, clearance_map.for_agent(agent_size));
+            populate_context.populate_approach(&world, 100, &mut distance_map);
+            assert_eq!(distance_map.distance(goal - Coord::new(1, 0)), Some(3));
+            assert_eq!(distance_map.distance(Coord::new(4, 2)).is_some(), agent_size == 1);
+        }
+    }
+
+    struct WithPortals<'a> {
+        world: &'a World,
+        portals: Vec<(Coord, Portal)>,
+    }
+
+    impl<'a> CanEnter for WithPortals<'a> {
+        fn can_enter(&self, coord: Coord) -> bool {
+            self.world.can_enter(coord)
+        }
+    }
+
+    impl<'a> Portals for WithPortals<'a> {
+        fn for_each_portal<F: FnMut(Portal)>(&self, from_coord: Coord, mut f: F) {
+            for &(coord, portal) in &self.portals {
+                if coord == from_coord {
+                    f(portal);
+                }
+            }
+        }
+        fn for_each_portal_in_world<F: FnMut(Coord, Portal)>(&self, mut f: F) {
+            for &(coord, portal) in &self.portals {
+                f(coord, portal);
+            }
+        }
+    }
+
+    #[test]
+    fn portals() {
+        let Test { world, goals } = Test::from_str_slice(GRID_A);
+        let world = WithPortals {
+            world: &world,
+            portals: vec![(
+                Coord::new(9, 0),
+                Portal {
+                    to_coord: Coord::new(9, 9),
+                    cost: 2,
+                },
+            )],
+        };
+        let mut populate_context = PopulateContext::default();
+        let mut distance_map = DistanceMap::new(world.world.grid.size());
+        for &coord in &goals {
+            populate_context.add(coord);
+        }
+        populate_context.populate_approach_portals(&world, 100, &mut distance_map);
+        // the portal is cheaper than walking through the gap in the wall
+        assert_eq!(distance_map.distance(Coord::new(9, 0)), Some(11));
+        assert_eq!(distance_map.distance(Coord::new(0, 0)), Some(15));
+        let mut coord = Coord::new(9, 0);
+        let mut cost = 0;
+        while let Some(PortalPathNode { to_coord, via }) = distance_map.best_move_with_portals(&world, coord) {
+            cost += match via {
+                Move::Step(_) => 1,
+                Move::Portal => 2,
+            };
+            coord = to_coord;
+        }
+        assert_eq!(coord, goals[0]);
+        assert_eq!(cost, 11);
+        // without portals the distances match populating without them
+        let mut expected = DistanceMap::new(world.world.grid.size());
+        for &coord in &goals {
+            populate_context.add(coord);
+        }
+        populate_context.populate_approach(world.world, 100, &mut expected);
+        for &coord in &goals {
+            populate_context.add(coord);
+        }
+        let world = WithPortals {
+            world: world.world,
+            portals: Vec::new(),
+        };
+        populate_context.populate_approach_portals(&world, 7, &mut distance_map);
+        for coord in world.world.grid.coord_iter() {
+            let expected = expected.distance(coord).filter(|&distance| distance <= 7);
+            assert_eq!(distance_map.distance(coord), expected);
+        }
+    }
+
+    #[test]
+    fn matrix() {
+        let Test { world, .. } = Test::from_str_slice(&[
+            "..#.......",
+            "..#.####..",
+            "..#.#..#..",
+            "....#..#..",
+            "....####..",
+            "..........",
+        ]);
+        let size = world.grid.size();
+        let mut populate_context = PopulateContext::default();
+        let mut distance_map = DistanceMap::new(size);
+        let few = [Coord::new(0, 0), Coord::new(5, 2), Coord::new(9, 5)];
+        let many = [
+            Coord::new(3, 0),
+            Coord::new(6, 3),
+            Coord::new(0, 5),
+            Coord::new(8, 1),
+            Coord::new(1, 2),
+        ];
+        let mut matrix = DistanceMatrix::default();
+        let mut parallel_matrix = DistanceMatrix::default();
+        let mut distance_maps = vec![DistanceMap::new(size); 2];
+        for (sources, targets) in [(&few[..], &many[..]), (&many[..], &few[..])] {
+            populate_context
+                .populate_matrix(&world, &mut distance_map, sources, targets, &mut matrix)
+                .unwrap();
+            assert_eq!(
+                (matrix.num_sources(), matrix.num_targets()),
+                (sources.len(), targets.len())
+            );
+            for (target_index, &target) in targets.iter().enumerate() {
+                populate_context.add(target);
+                populate_context.populate_approach(&world, Distance::MAX, &mut distance_map);
+                for (source_index, &source) in sources.iter().enumerate() {
+                    assert_eq!(
+                        matrix.distance(source_index, target_index),
+                        distance_map.distance(source)
+                    );
+                }
+            }
+            populate_context
+                .populate_matrix_parallel(&world, &mut distance_maps, sources, targets, &mut parallel_matrix)
+                .unwrap();
+            assert_eq!(parallel_matrix.distances, matrix.distances);
+        }
+        // (6, 3) and (5, 2) are walled in together
+        assert_eq!(matrix.distance(0, 0), Some(9));
+        assert_eq!(matrix.distance(0, 1), None);
+        assert_eq!(matrix.distance(1, 0), None);
+        assert_eq!(matrix.distance(1, 1), Some(2));
+        assert_eq!(matrix.distance(2, 2), Some(9));
+        assert_eq!(
+            populate_context.populate_matrix(&world, &mut distance_map, &few, &[Coord::new(10, 0)], &mut matrix),
+            Err(SearchError::GoalOutOfBounds(Coord::new(10, 0)))
+        );
+        assert_eq!(
+            populate_context.populate_matrix_parallel(
+                &world,
+                &mut distance_maps[..0],
+                &few,
+                &many,
+                &mut parallel_matrix
+            ),
+            Err(PopulateMatrixError::NoDistanceMaps)
+        );
+        let mut mismatched = [DistanceMap::new(size), DistanceMap::new(Size::new(20, 20))];
+        assert_eq!(
+            populate_context.populate_matrix_parallel(&world, &mut mismatched, &few, &many, &mut parallel_matrix),
+            Err(PopulateMatrixError::Search(SearchError::SizeMismatch {
+                expected: size,
+                actual: Size::new(20, 20)
+            }))
+        );
+        // a single distance map floods on the current thread
+        populate_context
+            .populate_matrix_parallel(&world, &mut distance_maps[..1], &few, &many, &mut parallel_matrix)
+            .unwrap();
+        populate_context
+            .populate_matrix(&world, &mut distance_map, &few, &many, &mut matrix)
+            .unwrap();
+        assert_eq!(parallel_matrix.distances, matrix.distances);
+    }
+
+    // the middle of a corridor can't be entered from the east
+    struct OneWay;
+
+    impl CanEnter for OneWay {
+        fn can_enter(&self, coord: Coord) -> bool {
+            Size::new(3, 1).is_valid(coord)
+        }
+        fn can_step(&self, step: Step) -> bool {
+            self.can_enter(step.to_coord)
+                && !(step.to_coord == Coord::new(1, 0)
+                    && step.in_direction.to_cardinal_direction() == CardinalDirection::West)
+        }
+    }
+
+    #[test]
+    fn matrix_one_way() {
+        let mut populate_context = PopulateContext::default();
+        let mut distance_map = DistanceMap::new(Size::new(3, 1));
+        let mut matrix = DistanceMatrix::default();
+        let (west, east) = (Coord::new(0, 0), Coord::new(2, 0));
+        populate_context.add(west);
+        populate_context.populate_approach(&OneWay, Distance::MAX, &mut distance_map);
+        let expected = distance_map.distance(east);
+        assert_eq!(expected, Some(2));
+        // the result doesn't depend on whether there are fewer sources or targets
+        populate_context
+            .populate_matrix(&OneWay, &mut distance_map, &[east], &[west], &mut matrix)
+            .unwrap();
+        assert_eq!(matrix.distance(0, 0), expected);
+        populate_context
+            .populate_matrix(&OneWay, &mut distance_map, &[east, west], &[west], &mut matrix)
+            .unwrap();
+        assert_eq!(matrix.distance(0, 0), expected);
+        assert_eq!(matrix.distance(1, 0), Some(0));
+    }
 }
@@ -1,6 +1,8 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use grid_2d::{Coord, Grid, Size};
-use grid_search_cardinal_point_to_point::{expand, CanEnter, Context};
+use grid_search_cardinal_point_to_point::{
+    expand, AdaptiveContext, CanEnter, CardinalDirection, Context, PreprocessedGrid,
+};
 use grid_search_maze::{MazeCell, MazeGenerator};
 use rand::{Rng, SeedableRng};
 use rand_isaac::Isaac64Rng;
@@ -101,6 +103,14 @@ impl Benchmark {
         assert!(first.is_some());
         black_box(first);
     }
+    fn search_preprocessed(&mut self, preprocessed_grid: &PreprocessedGrid) {
+        let first = self
+            .context
+            .preprocessed_search_first(preprocessed_grid, self.start, self.goal)
+            .unwrap();
+        assert!(first.is_some());
+        black_box(first);
+    }
     fn add(mut self, c: &mut Criterion, name: String) {
         c.bench_function(&format!("{} {:?}", name, expand::Sequential), |b| {
             b.iter(|| self.search(expand::Sequential))
@@ -108,6 +118,72 @@ impl Benchmark {
         c.bench_function(&format!("{} {:?}", name, expand::JumpPoint), |b| {
             b.iter(|| self.search(expand::JumpPoint))
         });
+        c.bench_function(&format!("{} {:?}", name, expand::Bidirectional), |b| {
+            b.iter(|| self.search(expand::Bidirectional))
+        });
+        let preprocessed_grid = PreprocessedGrid::new(&Search { world: &self.world }, self.size());
+        c.bench_function(&format!("{} Preprocessed", name), |b| {
+            b.iter(|| self.search_preprocessed(&preprocessed_grid))
+        });
+    }
+}
+
+// The goal wanders randomly while the start steps towards it each turn
+struct Chase {
+    world: World,
+    start: Coord,
+    goal_trajectory: Vec<Coord>,
+}
+
+impl Chase {
+    fn new_maze(size: Size, seed: u64, num_turns: usize) -> Self {
+        let Benchmark { world, start, goal, .. } = Benchmark::new_maze(size, seed);
+        let mut rng = Isaac64Rng::seed_from_u64(seed);
+        let search = Search { world: &world };
+        let mut goal_trajectory = vec![goal];
+        let mut goal = goal;
+        while goal_trajectory.len() < num_turns {
+            let direction = CardinalDirection::all().nth(rng.gen_range(0..4)).unwrap();
+            if search.can_enter(goal + direction.coord()) {
+                goal += direction.coord();
+                goal_trajectory.push(goal);
+            }
+        }
+        Self {
+            world,
+            start,
+            goal_trajectory,
+        }
+    }
+    fn chase<F: FnMut(&Search, Coord, Coord) -> Option<CardinalDirection>>(&self, mut search_first: F) {
+        let search = Search { world: &self.world };
+        let mut start = self.start;
+        for &goal in &self.goal_trajectory {
+            if let Some(direction) = search_first(&search, start, goal) {
+                start += direction.coord();
+            }
+        }
+        black_box(start);
+    }
+    fn add(self, c: &mut Criterion, name: String) {
+        let size = self.world.grid.size();
+        let mut context = Context::new(size);
+        c.bench_function(&format!("{} fresh", name), |b| {
+            b.iter(|| {
+                self.chase(|search, start, goal| {
+                    context
+                        .point_to_point_search_first(expand::Sequential, search, start, goal)
+                        .unwrap()
+                })
+            })
+        });
+        let mut adaptive_context = AdaptiveContext::new(size);
+        c.bench_function(&format!("{} adaptive", name), |b| {
+            b.iter(|| {
+                adaptive_context.clear_learned();
+                self.chase(|search, start, goal| adaptive_context.adaptive_search_first(search, start, goal).unwrap())
+            })
+        });
     }
 }
 
@@ -131,6 +207,14 @@ fn strings(c: &mut Criterion, strings: &[&str], name: &str) {
     benchmark.add(c, name);
 }
 
+fn chase_benchmark(c: &mut Criterion) {
+    let size = Size::new(50, 50);
+    let seed = 0;
+    let chase = Chase::new_maze(size, seed, 50);
+    let name = format!("chase maze (seed = {}) {}", seed, format_size(chase.world.grid.size()));
+    chase.add(c, name);
+}
+
 fn maze_benchmark(c: &mut Criterion) {
     let sizes = [Size::new(5, 5), Size::new(50, 50), Size::new(100, 100)];
     let mut rng = Isaac64Rng::seed_from_u64(0);
@@ -158,6 +242,7 @@ fn criterion_benchmark(c: &mut Criterion) {
     maze_benchmark(c);
     empty_benchmark(c);
     strings_benchmark(c);
+    chase_benchmark(c);
 }
 criterion_group!(benches, criterion_benchmark);
 criterion_main!(benches);
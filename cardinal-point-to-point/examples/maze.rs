@@ -1,4 +1,4 @@
-use grid_search_cardinal_point_to_point::{expand, CanEnter, Context};
+use grid_search_cardinal_point_to_point::{expand, CanEnter, Context, SearchStats};
 use grid_search_maze::{Coord, Grid, MazeCell, MazeGenerator, Size};
 use rand::SeedableRng;
 use rand_isaac::Isaac64Rng;
@@ -9,10 +9,7 @@ struct Search<'a> {
 
 impl<'a> CanEnter for Search<'a> {
     fn can_enter(&self, coord: Coord) -> bool {
-        match self.maze.get(coord) {
-            Some(MazeCell::Passage) => true,
-            _ => false,
-        }
+        matches!(self.maze.get(coord), Some(MazeCell::Passage))
     }
 }
 
@@ -25,6 +22,13 @@ fn main() {
     let maze = generator.generate(start, &mut rng);
     let goal = maze.size().to_coord().unwrap() - Coord::new(1, 1);
     let mut context = Context::new(maze.size());
-    let (profile, _) = context.point_to_point_search_profile(expand::JumpPoint, &Search { maze: &maze }, start, goal);
-    println!("{:#?}", profile);
+    let mut stats = SearchStats::default();
+    let _ = context.point_to_point_search_first_with_observer(
+        expand::JumpPoint,
+        &Search { maze: &maze },
+        start,
+        goal,
+        &mut stats,
+    );
+    println!("{:#?}", stats);
 }
@@ -0,0 +1,320 @@
+use crate::{
+    CanEnter, CardinalDirection, Coord, NoPath, Observer, Path, SearchError, Size, Step, UnitCoord, UNIT_COORDS,
+};
+use grid_2d::Grid;
+use grid_search_cardinal_common::min_heap::MinHeapNode;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// lowest cost plus heuristic first, breaking ties in favour of the node with the highest cost
+type Node = MinHeapNode<(u32, Reverse<u32>), Coord>;
+
+#[derive(Clone, Copy, Debug)]
+struct Learned {
+    heuristic: u32,
+    // the total correction at the time the heuristic was learned
+    correction: u64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Cell {
+    seen_count: u64,
+    expanded_count: u64,
+    cost: u32,
+    in_direction: UnitCoord,
+    learned: Option<Learned>,
+}
+
+/// Point-to-point search for chasing a moving goal (Moving Target Adaptive A*). After each
+/// search the heuristic of every expanded cell is raised to its true distance to the goal, and
+/// these learned values are kept between searches, corrected as the goal moves, so that later
+/// searches expand fewer cells. The start may also move freely between searches.
+///
+/// Learned values remain admissible as long as cells only become harder to enter. Call
+/// `clear_learned` after cells become enterable.
+pub struct AdaptiveContext {
+    count: u64,
+    grid: Grid<Cell>,
+    priority_queue: BinaryHeap<Node>,
+    expanded: Vec<Coord>,
+    goal: Option<Coord>,
+    // sum of the heuristic values of each new goal with respect to the goal before it
+    correction: u64,
+}
+
+impl AdaptiveContext {
+    pub fn new(size: Size) -> Self {
+        Self {
+            count: 0,
+            grid: Grid::new_copy(
+                size,
+                Cell {
+                    seen_count: 0,
+                    expanded_count: 0,
+                    cost: 0,
+                    in_direction: UNIT_COORDS[0],
+                    learned: None,
+                },
+            ),
+            priority_queue: BinaryHeap::new(),
+            expanded: Vec::new(),
+            goal: None,
+            correction: 0,
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.grid.size()
+    }
+
+    pub fn clear_learned(&mut self) {
+        for cell in self.grid.iter_mut() {
+            cell.learned = None;
+        }
+        self.goal = None;
+        self.correction = 0;
+    }
+
+    fn heuristic(&self, coord: Coord, goal: Coord) -> u32 {
+        let manhattan_distance = coord.manhattan_distance(goal);
+        match self.grid.get_checked(coord).learned {
+            Some(learned) => {
+                let correction = (self.correction - learned.correction).min(u32::MAX as u64) as u32;
+                manhattan_distance.max(learned.heuristic.saturating_sub(correction))
+            }
+            None => manhattan_distance,
+        }
+    }
+
+    fn set_goal(&mut self, goal: Coord) {
+        if let Some(previous_goal) = self.goal {
+            if previous_goal != goal {
+                self.correction += self.heuristic(goal, previous_goal) as u64;
+            }
+        }
+        self.goal = Some(goal);
+    }
+
+    fn visit<O: Observer>(&mut self, coord: Coord, cost: u32, in_direction: UnitCoord, goal: Coord, observer: &mut O) {
+        let count = self.count;
+        let cell = self.grid.get_checked_mut(coord);
+        if cell.seen_count == count && cell.cost <= cost {
+            return;
+        }
+        cell.seen_count = count;
+        cell.cost = cost;
+        cell.in_direction = in_direction;
+        observer.visit(coord);
+        let cost_plus_heuristic = cost + self.heuristic(coord, goal);
+        observer.push(coord);
+        self.priority_queue.push(Node {
+            key: (cost_plus_heuristic, Reverse(cost)),
+            value: coord,
+        });
+        observer.queue_len(self.priority_queue.len());
+    }
+
+    fn search_core<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        goal: Coord,
+        observer: &mut O,
+    ) -> Result<u32, NoPath> {
+        self.count += 1;
+        self.priority_queue.clear();
+        self.expanded.clear();
+        self.set_goal(goal);
+        self.visit(start, 0, UNIT_COORDS[0], goal, observer);
+        while let Some(Node {
+            key: (_, Reverse(cost)),
+            value: coord,
+        }) = self.priority_queue.pop()
+        {
+            let cell = self.grid.get_checked(coord);
+            if cell.expanded_count == self.count || cell.cost < cost {
+                continue;
+            }
+            if coord == goal {
+                for &coord in &self.expanded {
+                    let cell = self.grid.get_checked_mut(coord);
+                    cell.learned = Some(Learned {
+                        heuristic: cost - cell.cost,
+                        correction: self.correction,
+                    });
+                }
+                observer.path_cost(cost);
+                return Ok(cost);
+            }
+            self.grid.get_checked_mut(coord).expanded_count = self.count;
+            self.expanded.push(coord);
+            observer.expand(coord);
+            for &in_direction in &UNIT_COORDS {
+                let step = Step {
+                    to_coord: coord + in_direction.to_coord(),
+                    in_direction,
+                };
+                match self.grid.get(step.to_coord) {
+                    Some(cell) if cell.expanded_count != self.count => (),
+                    _ => continue,
+                }
+                if step.to_coord != goal && !can_enter.can_step(step) {
+                    continue;
+                }
+                self.visit(step.to_coord, cost + 1, in_direction, goal, observer);
+            }
+        }
+        Err(NoPath)
+    }
+
+    fn search<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        goal: Coord,
+        observer: &mut O,
+    ) -> Result<(), SearchError> {
+        SearchError::check_bounds(self.size(), &[start], &[goal])?;
+        self.search_core(can_enter, start, goal, observer).map_err(|NoPath| {
+            SearchError::goal_unreachable(start, self.expanded.iter().cloned(), |coord| {
+                coord.manhattan_distance(goal)
+            })
+        })?;
+        Ok(())
+    }
+
+    pub fn adaptive_search_path<S: CanEnter>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        goal: Coord,
+        path: &mut Path,
+    ) -> Result<(), SearchError> {
+        self.adaptive_search_path_with_observer(can_enter, start, goal, path, &mut ())
+    }
+
+    pub fn adaptive_search_path_with_observer<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        goal: Coord,
+        path: &mut Path,
+        observer: &mut O,
+    ) -> Result<(), SearchError> {
+        self.search(can_enter, start, goal, observer)?;
+        path.clear();
+        let mut coord = goal;
+        while coord != start {
+            let in_direction = self.grid.get_checked(coord).in_direction;
+            path.push_front(Step {
+                to_coord: coord,
+                in_direction,
+            })
+            .expect("each step enters the cell the path starts from");
+            coord -= in_direction.to_coord();
+        }
+        Ok(())
+    }
+
+    pub fn adaptive_search_first<S: CanEnter>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        goal: Coord,
+    ) -> Result<Option<CardinalDirection>, SearchError> {
+        self.adaptive_search_first_with_observer(can_enter, start, goal, &mut ())
+    }
+
+    pub fn adaptive_search_first_with_observer<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        goal: Coord,
+        observer: &mut O,
+    ) -> Result<Option<CardinalDirection>, SearchError> {
+        self.search(can_enter, start, goal, observer)?;
+        let mut first = None;
+        let mut coord = goal;
+        while coord != start {
+            let in_direction = self.grid.get_checked(coord).in_direction;
+            first = Some(in_direction.to_cardinal_direction());
+            coord -= in_direction.to_coord();
+        }
+        Ok(first)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_world::{random_grid, Search};
+    use crate::{expand, Context, SearchStats};
+    use rand::{Rng, SeedableRng};
+    use rand_isaac::Isaac64Rng;
+
+    #[test]
+    fn chase() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 50;
+        let num_turns = 40;
+        let size = Size::new(30, 30);
+        let mut ctx = Context::new(size);
+        let mut adaptive_ctx = AdaptiveContext::new(size);
+        let mut path = Path::default();
+        let mut expected_path = Path::default();
+        let (mut nodes_expanded, mut expected_nodes_expanded) = (0, 0);
+        for _ in 0..num_tests {
+            let grid = random_grid(size, 4, &mut rng);
+            let search = Search { grid: &grid };
+            adaptive_ctx.clear_learned();
+            let mut start = Coord::random_within(size, &mut rng);
+            let mut goal = Coord::random_within(size, &mut rng);
+            for _ in 0..num_turns {
+                let mut stats = SearchStats::default();
+                let result =
+                    adaptive_ctx.adaptive_search_path_with_observer(&search, start, goal, &mut path, &mut stats);
+                let mut expected_stats = SearchStats::default();
+                let expected = ctx.point_to_point_search_path_with_observer(
+                    expand::Sequential,
+                    &search,
+                    start,
+                    goal,
+                    &mut expected_path,
+                    &mut expected_stats,
+                );
+                assert_eq!(result, expected);
+                let first = adaptive_ctx.adaptive_search_first(&search, start, goal);
+                if result.is_err() {
+                    break;
+                }
+                assert_eq!(path.len(), expected_path.len());
+                // ties may be broken differently once more has been learned
+                match first.unwrap() {
+                    Some(direction) => {
+                        ctx.point_to_point_search_path(
+                            expand::Sequential,
+                            &search,
+                            start + direction.coord(),
+                            goal,
+                            &mut expected_path,
+                        )
+                        .unwrap();
+                        assert_eq!(expected_path.len() + 1, path.len());
+                    }
+                    None => assert!(path.is_empty()),
+                }
+                nodes_expanded += stats.nodes_expanded;
+                expected_nodes_expanded += expected_stats.nodes_expanded;
+                // the chaser takes one step and the goal wanders
+                if let Some(node) = path.pop() {
+                    start = node.to_coord;
+                }
+                let direction = CardinalDirection::all().nth(rng.gen_range(0..4)).unwrap();
+                if search.can_enter(goal + direction.coord()) {
+                    goal += direction.coord();
+                }
+            }
+        }
+        assert!(nodes_expanded < expected_nodes_expanded);
+    }
+}
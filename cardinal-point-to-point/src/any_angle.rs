@@ -0,0 +1,268 @@
+use crate::{CanEnter, Coord, LineOfSight, NoPath, Observer, SearchError, Size, Step};
+use grid_2d::Grid;
+use grid_search_cardinal_common::{coord::UNIT_COORDS, min_heap::MinHeapNode};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// lowest cost plus heuristic first, breaking ties in favour of the node with the highest cost.
+// Costs are never negative, so ordering the bits of the floats orders them by value.
+type Node = MinHeapNode<(u64, Reverse<u64>), (f64, Coord)>;
+
+#[derive(Clone, Copy, Debug)]
+struct Cell {
+    count: u64,
+    cost: f64,
+    parent: Coord,
+}
+
+fn distance(a: Coord, b: Coord) -> f64 {
+    let delta = b - a;
+    ((delta.x * delta.x + delta.y * delta.y) as f64).sqrt()
+}
+
+/// Finds any-angle paths in the style of Theta*. Cells are expanded as in a cardinal search, but
+/// each cell's parent may be any cell visible from it, so paths are made of straight lines
+/// between waypoints rather than unit steps.
+pub struct AnyAngleContext {
+    count: u64,
+    grid: Grid<Cell>,
+    priority_queue: BinaryHeap<Node>,
+}
+
+impl AnyAngleContext {
+    pub fn new(size: Size) -> Self {
+        Self {
+            count: 0,
+            grid: Grid::new_copy(
+                size,
+                Cell {
+                    count: 0,
+                    cost: 0.,
+                    parent: Coord::new(0, 0),
+                },
+            ),
+            priority_queue: BinaryHeap::new(),
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.grid.size()
+    }
+
+    fn visit<O: Observer>(&mut self, coord: Coord, cost: f64, parent: Coord, goal: Coord, observer: &mut O) {
+        let cell = self.grid.get_checked_mut(coord);
+        if cell.count != self.count || cost < cell.cost {
+            *cell = Cell {
+                count: self.count,
+                cost,
+                parent,
+            };
+            observer.visit(coord);
+            observer.push(coord);
+            self.priority_queue.push(Node {
+                key: ((cost + distance(coord, goal)).to_bits(), Reverse(cost.to_bits())),
+                value: (cost, coord),
+            });
+            observer.queue_len(self.priority_queue.len());
+        }
+    }
+
+    fn search_core<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        goal: Coord,
+        line_of_sight: LineOfSight,
+        observer: &mut O,
+    ) -> Result<(), NoPath> {
+        self.count += 1;
+        self.priority_queue.clear();
+        self.visit(start, 0., start, goal, observer);
+        while let Some(Node {
+            value: (cost, coord), ..
+        }) = self.priority_queue.pop()
+        {
+            if coord == goal {
+                return Ok(());
+            }
+            let cell = *self.grid.get_checked(coord);
+            if cost > cell.cost {
+                continue;
+            }
+            observer.expand(coord);
+            for &in_direction in &UNIT_COORDS {
+                let to_coord = coord + in_direction.to_coord();
+                if self.grid.get(to_coord).is_none() {
+                    continue;
+                }
+                if to_coord != goal && !can_enter.can_step(Step { to_coord, in_direction }) {
+                    continue;
+                }
+                // skip straight from this cell's parent if nothing is in the way
+                if cell.parent != coord && line_of_sight.is_visible(can_enter, cell.parent, to_coord) {
+                    let parent_cost = self.grid.get_checked(cell.parent).cost;
+                    self.visit(
+                        to_coord,
+                        parent_cost + distance(cell.parent, to_coord),
+                        cell.parent,
+                        goal,
+                        observer,
+                    );
+                } else {
+                    self.visit(to_coord, cost + 1., coord, goal, observer);
+                }
+            }
+        }
+        Err(NoPath)
+    }
+
+    /// Sets `waypoints` to the cells where the path changes direction, ending with the goal
+    pub fn any_angle_search_waypoints<S: CanEnter>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        goal: Coord,
+        line_of_sight: LineOfSight,
+        waypoints: &mut Vec<Coord>,
+    ) -> Result<(), SearchError> {
+        self.any_angle_search_waypoints_with_observer(can_enter, start, goal, line_of_sight, waypoints, &mut ())
+    }
+
+    /// The path cost given to the observer is the length of the path rounded to the nearest
+    /// whole number
+    pub fn any_angle_search_waypoints_with_observer<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        goal: Coord,
+        line_of_sight: LineOfSight,
+        waypoints: &mut Vec<Coord>,
+        observer: &mut O,
+    ) -> Result<(), SearchError> {
+        SearchError::check_bounds(self.size(), &[start], &[goal])?;
+        self.search_core(can_enter, start, goal, line_of_sight, observer)
+            .map_err(|NoPath| {
+                let visited = self
+                    .grid
+                    .enumerate()
+                    .filter(|(_, cell)| cell.count == self.count)
+                    .map(|(coord, _)| coord);
+                SearchError::goal_unreachable(start, visited, |coord| coord.manhattan_distance(goal))
+            })?;
+        waypoints.clear();
+        let mut coord = goal;
+        while coord != start {
+            waypoints.push(coord);
+            coord = self.grid.get_checked(coord).parent;
+        }
+        waypoints.reverse();
+        observer.path_cost(self.grid.get_checked(goal).cost.round() as u32);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_world::{random_grid, Search};
+    use crate::{expand, line_of_sight, Context, Path, SearchStats};
+    use rand::SeedableRng;
+    use rand_isaac::Isaac64Rng;
+
+    fn length(start: Coord, waypoints: &[Coord]) -> f64 {
+        let mut from = start;
+        let mut length = 0.;
+        for &waypoint in waypoints {
+            length += distance(from, waypoint);
+            from = waypoint;
+        }
+        length
+    }
+
+    fn assert_visible(search: &Search, start: Coord, waypoints: &[Coord], line_of_sight: LineOfSight) {
+        let mut from = start;
+        for &waypoint in waypoints {
+            assert!(line_of_sight.is_visible(search, from, waypoint));
+            from = waypoint;
+        }
+    }
+
+    #[test]
+    fn corner() {
+        let mut grid = Grid::new_copy(Size::new(3, 3), true);
+        *grid.get_checked_mut(Coord::new(1, 0)) = false;
+        let search = Search { grid: &grid };
+        assert!(LineOfSight::Bresenham.is_visible(&search, Coord::new(0, 0), Coord::new(2, 2)));
+        assert!(!LineOfSight::Supercover.is_visible(&search, Coord::new(0, 0), Coord::new(2, 2)));
+        let mut ctx = AnyAngleContext::new(grid.size());
+        let mut waypoints = Vec::new();
+        ctx.any_angle_search_waypoints(
+            &search,
+            Coord::new(0, 0),
+            Coord::new(2, 2),
+            LineOfSight::Bresenham,
+            &mut waypoints,
+        )
+        .unwrap();
+        assert_eq!(waypoints, vec![Coord::new(2, 2)]);
+        ctx.any_angle_search_waypoints(
+            &search,
+            Coord::new(0, 0),
+            Coord::new(2, 2),
+            LineOfSight::Supercover,
+            &mut waypoints,
+        )
+        .unwrap();
+        assert_eq!(waypoints.last(), Some(&Coord::new(2, 2)));
+        assert!(waypoints.len() > 1);
+    }
+
+    #[test]
+    fn random_grids() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 500;
+        let size = Size::new(20, 20);
+        let mut ctx = Context::new(size);
+        let mut any_angle_ctx = AnyAngleContext::new(size);
+        let mut path = Path::default();
+        let mut waypoints = Vec::new();
+        for i in 0..num_tests {
+            let line_of_sight = if i % 2 == 0 {
+                LineOfSight::Bresenham
+            } else {
+                LineOfSight::Supercover
+            };
+            let grid = random_grid(size, 4, &mut rng);
+            let search = Search { grid: &grid };
+            let start = Coord::random_within(size, &mut rng);
+            let goal = Coord::random_within(size, &mut rng);
+            let result = ctx.point_to_point_search_path(expand::JumpPoint, &search, start, goal, &mut path);
+            let mut stats = SearchStats::default();
+            assert_eq!(
+                any_angle_ctx
+                    .any_angle_search_waypoints_with_observer(
+                        &search,
+                        start,
+                        goal,
+                        line_of_sight,
+                        &mut waypoints,
+                        &mut stats
+                    )
+                    .is_ok(),
+                result.is_ok()
+            );
+            if result.is_err() {
+                assert_eq!(stats.path_cost, None);
+                continue;
+            }
+            assert_eq!(stats.path_cost, Some(length(start, &waypoints).round() as u32));
+            assert_eq!(waypoints.last().cloned().unwrap_or(start), goal);
+            assert_visible(&search, start, &waypoints, line_of_sight);
+            assert!(length(start, &waypoints) <= path.len() as f64 + 1e-9);
+            line_of_sight::waypoints(&search, &path, line_of_sight, &mut waypoints);
+            assert_eq!(waypoints.last().cloned().unwrap_or(start), goal);
+            assert_visible(&search, start, &waypoints, line_of_sight);
+            assert!(waypoints.len() <= path.len());
+        }
+    }
+}
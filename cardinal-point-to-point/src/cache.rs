@@ -0,0 +1,254 @@
+use crate::{expand::Expand, Context, Coord, Path, Rect, SearchError, WorldVersion, Wrap};
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    start: Coord,
+    goal: Coord,
+    expand: TypeId,
+    wrap: Wrap,
+}
+
+#[derive(Debug)]
+struct Entry {
+    path: Path,
+    last_used: u64,
+}
+
+/// Remembers the paths found by a `Context`, keyed by their start, goal, expand strategy and the
+/// context's wrap.
+/// When full, the least recently used path is evicted. Every cached path is dropped when the
+/// world's version changes. Failed searches aren't cached.
+#[derive(Debug)]
+pub struct PathCache {
+    capacity: usize,
+    entries: HashMap<Key, Entry>,
+    // maps the time each entry was last used to its key, for finding the least recently used
+    by_last_used: BTreeMap<u64, Key>,
+    time: u64,
+    world_version: Option<u64>,
+    hits: u64,
+    misses: u64,
+}
+
+impl PathCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            by_last_used: BTreeMap::new(),
+            time: 0,
+            world_version: None,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    pub fn reset_counters(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.by_last_used.clear();
+    }
+
+    /// Drops every cached path which enters a cell in `region`. Use this when cells in `region`
+    /// become harder to enter. If cells become easier to enter, cached paths elsewhere may no
+    /// longer be the shortest, so the world's version should be changed instead.
+    pub fn invalidate_region(&mut self, region: Rect) {
+        let by_last_used = &mut self.by_last_used;
+        self.entries.retain(|key, entry| {
+            let passes_through_region =
+                region.contains(key.start) || entry.path.iter().any(|node| region.contains(node.to_coord));
+            if passes_through_region {
+                by_last_used.remove(&entry.last_used);
+            }
+            !passes_through_region
+        });
+    }
+
+    pub fn search_path<S, E>(
+        &mut self,
+        context: &mut Context,
+        expand: E,
+        point_to_point_search: &S,
+        start: Coord,
+        goal: Coord,
+        path: &mut Path,
+    ) -> Result<(), SearchError>
+    where
+        S: WorldVersion,
+        E: Expand + 'static,
+    {
+        let world_version = point_to_point_search.world_version();
+        if self.world_version != Some(world_version) {
+            self.clear();
+            self.world_version = Some(world_version);
+        }
+        let key = Key {
+            start,
+            goal,
+            expand: TypeId::of::<E>(),
+            wrap: context.wrap(),
+        };
+        self.time += 1;
+        if let Some(entry) = self.entries.get_mut(&key) {
+            self.hits += 1;
+            self.by_last_used.remove(&entry.last_used);
+            self.by_last_used.insert(self.time, key);
+            entry.last_used = self.time;
+            path.clone_from(&entry.path);
+            return Ok(());
+        }
+        self.misses += 1;
+        context.point_to_point_search_path(expand, point_to_point_search, start, goal, path)?;
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        if self.entries.len() >= self.capacity {
+            if let Some((_, least_recently_used)) = self.by_last_used.pop_first() {
+                self.entries.remove(&least_recently_used);
+            }
+        }
+        self.by_last_used.insert(self.time, key);
+        self.entries.insert(
+            key,
+            Entry {
+                path: path.clone(),
+                last_used: self.time,
+            },
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_world::Search;
+    use crate::{expand, CanEnter, Size};
+    use grid_2d::Grid;
+
+    struct World {
+        grid: Grid<bool>,
+        version: u64,
+    }
+
+    impl CanEnter for World {
+        fn can_enter(&self, coord: Coord) -> bool {
+            Search { grid: &self.grid }.can_enter(coord)
+        }
+    }
+
+    impl WorldVersion for World {
+        fn world_version(&self) -> u64 {
+            self.version
+        }
+    }
+
+    #[test]
+    fn lru() {
+        let world = World {
+            grid: Grid::new_copy(Size::new(10, 10), true),
+            version: 0,
+        };
+        let mut ctx = Context::new(world.grid.size());
+        let mut cache = PathCache::new(2);
+        let mut path = Path::default();
+        let a = Coord::new(0, 0);
+        let b = Coord::new(9, 9);
+        let c = Coord::new(0, 9);
+        cache
+            .search_path(&mut ctx, expand::JumpPoint, &world, a, b, &mut path)
+            .unwrap();
+        cache
+            .search_path(&mut ctx, expand::JumpPoint, &world, a, c, &mut path)
+            .unwrap();
+        cache
+            .search_path(&mut ctx, expand::JumpPoint, &world, a, b, &mut path)
+            .unwrap();
+        assert_eq!(path.len(), 18);
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+        // different expand strategies are cached separately
+        cache
+            .search_path(&mut ctx, expand::Sequential, &world, a, b, &mut path)
+            .unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (1, 3));
+        assert_eq!(cache.len(), 2);
+        // (a, c) was least recently used so was evicted
+        cache
+            .search_path(&mut ctx, expand::JumpPoint, &world, a, c, &mut path)
+            .unwrap();
+        cache
+            .search_path(&mut ctx, expand::Sequential, &world, a, b, &mut path)
+            .unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (2, 4));
+        assert_eq!(path.len(), 18);
+        // paths found on a grid which wraps are cached separately
+        ctx.set_wrap(Wrap::Both);
+        cache
+            .search_path(&mut ctx, expand::JumpPoint, &world, a, b, &mut path)
+            .unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (2, 5));
+        assert_eq!(path.len(), 2);
+    }
+
+    #[test]
+    fn invalidation() {
+        let mut world = World {
+            grid: Grid::new_copy(Size::new(10, 10), true),
+            version: 0,
+        };
+        let mut ctx = Context::new(world.grid.size());
+        let mut cache = PathCache::new(10);
+        let mut path = Path::default();
+        let (start, goal) = (Coord::new(0, 0), Coord::new(9, 0));
+        let other_goal = Coord::new(0, 9);
+        cache
+            .search_path(&mut ctx, expand::JumpPoint, &world, start, goal, &mut path)
+            .unwrap();
+        cache
+            .search_path(&mut ctx, expand::JumpPoint, &world, start, other_goal, &mut path)
+            .unwrap();
+        *world.grid.get_checked_mut(Coord::new(5, 0)) = false;
+        cache.invalidate_region(Rect::new(Coord::new(5, 0), Size::new(1, 1)));
+        assert_eq!(cache.len(), 1);
+        cache
+            .search_path(&mut ctx, expand::JumpPoint, &world, start, goal, &mut path)
+            .unwrap();
+        assert!(path.iter().all(|node| node.to_coord != Coord::new(5, 0)));
+        cache
+            .search_path(&mut ctx, expand::JumpPoint, &world, start, other_goal, &mut path)
+            .unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (1, 3));
+        world.version += 1;
+        cache
+            .search_path(&mut ctx, expand::JumpPoint, &world, start, other_goal, &mut path)
+            .unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (1, 4));
+        assert_eq!(cache.len(), 1);
+    }
+}
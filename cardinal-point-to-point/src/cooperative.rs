@@ -0,0 +1,440 @@
+use crate::{CanEnter, Coord, Observer, SearchError, Size, Step, UNIT_COORDS};
+use grid_search_cardinal_common::min_heap::MinHeapNode;
+use grid_search_cardinal_distance_map::{DistanceMap, PopulateContext};
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Agent {
+    pub start: Coord,
+    pub goal: Coord,
+}
+
+/// Cells and moves claimed by agents which have already been planned
+#[derive(Debug, Default)]
+struct Reservations {
+    cells: HashSet<(Coord, u32)>,
+    // (from, to, time) for a move which leaves `from` at `time`
+    moves: HashSet<(Coord, Coord, u32)>,
+}
+
+impl Reservations {
+    fn clear(&mut self) {
+        self.cells.clear();
+        self.moves.clear();
+    }
+
+    fn reserve(&mut self, from: Coord, to: Coord, time: u32) {
+        self.cells.insert((to, time + 1));
+        if from != to {
+            self.moves.insert((from, to, time));
+        }
+    }
+
+    // moving into a cell another agent is moving out of is allowed, unless they swap
+    fn is_blocked(&self, from: Coord, to: Coord, time: u32) -> bool {
+        self.cells.contains(&(to, time + 1)) || self.moves.contains(&(to, from, time))
+    }
+}
+
+// lowest cost plus heuristic first, breaking ties in favour of the node closest to the goal
+type Node = MinHeapNode<(u32, u32), (u32, Coord, u32)>;
+
+#[derive(Clone, Copy, Debug)]
+struct SeenNode {
+    cost: u32,
+    parent: Coord,
+}
+
+#[derive(Debug, Default)]
+struct SpaceTimeSearch {
+    // keyed by (coord, time)
+    seen: HashMap<(Coord, u32), SeenNode>,
+    priority_queue: BinaryHeap<Node>,
+}
+
+impl SpaceTimeSearch {
+    fn visit<O: Observer>(
+        &mut self,
+        coord: Coord,
+        time: u32,
+        cost: u32,
+        heuristic: u32,
+        parent: Coord,
+        observer: &mut O,
+    ) {
+        if let Some(seen_node) = self.seen.get(&(coord, time)) {
+            if seen_node.cost <= cost {
+                return;
+            }
+        }
+        self.seen.insert((coord, time), SeenNode { cost, parent });
+        observer.visit(coord);
+        observer.push(coord);
+        self.priority_queue.push(Node {
+            key: (cost + heuristic, heuristic),
+            value: (cost, coord, time),
+        });
+        observer.queue_len(self.priority_queue.len());
+    }
+
+    // Returns the coordinate of the agent at the end of the window
+    fn search<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        agent: Agent,
+        window: u32,
+        reservations: &Reservations,
+        distance_map: &DistanceMap,
+        observer: &mut O,
+    ) -> Result<Coord, SearchError> {
+        self.seen.clear();
+        self.priority_queue.clear();
+        let heuristic = match distance_map.distance(agent.start) {
+            Some(heuristic) => heuristic,
+            // the goal can't be reached from the start even without other agents in the way
+            None => return Err(SearchError::StartNotInDistanceMap(agent.start)),
+        };
+        self.visit(agent.start, 0, 0, heuristic, agent.start, observer);
+        while let Some(Node {
+            value: (cost, coord, time),
+            ..
+        }) = self.priority_queue.pop()
+        {
+            if time == window {
+                return Ok(coord);
+            }
+            if self.seen[&(coord, time)].cost < cost {
+                continue;
+            }
+            observer.expand(coord);
+            // waiting at the goal is free so that arriving early isn't penalised
+            if !reservations.is_blocked(coord, coord, time) {
+                let wait_cost = if coord == agent.goal { 0 } else { 1 };
+                let heuristic = distance_map.distance(coord).unwrap();
+                self.visit(coord, time + 1, cost + wait_cost, heuristic, coord, observer);
+            }
+            for &in_direction in &UNIT_COORDS {
+                let step = Step {
+                    to_coord: coord + in_direction.to_coord(),
+                    in_direction,
+                };
+                let heuristic = match distance_map.distance(step.to_coord) {
+                    Some(heuristic) => heuristic,
+                    None => continue,
+                };
+                if step.to_coord != agent.goal && !can_enter.can_step(step) {
+                    continue;
+                }
+                if reservations.is_blocked(coord, step.to_coord, time) {
+                    continue;
+                }
+                self.visit(step.to_coord, time + 1, cost + 1, heuristic, coord, observer);
+            }
+        }
+        let visited = self.seen.keys().map(|&(coord, _)| coord).collect::<HashSet<_>>();
+        Err(SearchError::goal_unreachable(agent.start, visited, |coord| {
+            coord.manhattan_distance(agent.goal)
+        }))
+    }
+}
+
+/// Plans paths for a group of agents so that no two agents occupy the same cell, or swap cells,
+/// at the same time step (Windowed Hierarchical Cooperative A*). Agents are planned in priority
+/// order, each avoiding the cells reserved by the agents before it. Only the first `window`
+/// steps are planned and reserved, with the remaining distance to each goal estimated by the
+/// true distance from a reverse search. Plans should be recomputed before the window runs out.
+pub struct CooperativeContext {
+    size: Size,
+    window: u32,
+    reservations: Reservations,
+    search: SpaceTimeSearch,
+    populate_context: PopulateContext,
+    // keyed by goal
+    distance_maps: HashMap<Coord, DistanceMap>,
+}
+
+impl CooperativeContext {
+    pub fn new(size: Size, window: u32) -> Self {
+        Self {
+            size,
+            window,
+            reservations: Reservations::default(),
+            search: SpaceTimeSearch::default(),
+            populate_context: PopulateContext::default(),
+            distance_maps: HashMap::new(),
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn window(&self) -> u32 {
+        self.window
+    }
+
+    pub fn set_window(&mut self, window: u32) {
+        self.window = window;
+    }
+
+    /// Distances to each goal are cached between calls to `plan`. Call this after the world
+    /// changes.
+    pub fn clear_distance_cache(&mut self) {
+        self.distance_maps.clear();
+    }
+
+    /// Plans the next `window` steps of each agent, in order of decreasing priority. Each plan
+    /// is the position of the agent after each step, with waiting shown as a repeated position.
+    /// Agents which can't be planned are assumed to stay where they are. Agents which can't
+    /// reach their goal at all are avoided by every other agent, but an agent boxed in by the
+    /// agents planned before it is only avoided by the agents planned after it.
+    pub fn plan<S: CanEnter>(
+        &mut self,
+        can_enter: &S,
+        agents: &[Agent],
+        plans: &mut Vec<Result<Vec<Coord>, SearchError>>,
+    ) {
+        self.plan_with_observer(can_enter, agents, plans, &mut ());
+    }
+
+    /// The observer is notified of the search for each agent in turn, and is given the cost of
+    /// each plan found, which doesn't include the estimated distance remaining after the window
+    pub fn plan_with_observer<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        agents: &[Agent],
+        plans: &mut Vec<Result<Vec<Coord>, SearchError>>,
+        observer: &mut O,
+    ) {
+        self.reservations.clear();
+        plans.clear();
+        // agents which haven't been planned yet keep their starts for the first step, so that
+        // agents planned before them can't run into them before they get out of the way
+        for &agent in agents {
+            if !self.size.is_valid(agent.start) {
+                continue;
+            }
+            let stuck = !self.size.is_valid(agent.goal)
+                || self.distance_map(can_enter, agent.goal).distance(agent.start).is_none();
+            let num_steps = if stuck { self.window } else { 1 };
+            for time in 0..num_steps {
+                self.reservations.reserve(agent.start, agent.start, time);
+            }
+        }
+        for &agent in agents {
+            let plan = self.plan_agent(can_enter, agent, observer);
+            match plan {
+                Ok(ref plan) => {
+                    let mut from = agent.start;
+                    for (time, &to) in plan.iter().enumerate() {
+                        self.reservations.reserve(from, to, time as u32);
+                        from = to;
+                    }
+                }
+                Err(_) => {
+                    if self.size.is_valid(agent.start) {
+                        for time in 0..self.window {
+                            self.reservations.reserve(agent.start, agent.start, time);
+                        }
+                    }
+                }
+            }
+            plans.push(plan);
+        }
+    }
+
+    fn distance_map<S: CanEnter>(&mut self, can_enter: &S, goal: Coord) -> &DistanceMap {
+        let size = self.size;
+        let populate_context = &mut self.populate_context;
+        self.distance_maps.entry(goal).or_insert_with(|| {
+            let mut distance_map = DistanceMap::new(size);
+            populate_context.add(goal);
+            populate_context.populate_approach(can_enter, u32::MAX, &mut distance_map);
+            distance_map
+        })
+    }
+
+    fn plan_agent<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        agent: Agent,
+        observer: &mut O,
+    ) -> Result<Vec<Coord>, SearchError> {
+        SearchError::check_bounds(self.size, &[agent.start], &[agent.goal])?;
+        let distance_map = &self.distance_maps[&agent.goal];
+        if distance_map.distance(agent.start).is_some() {
+            // release the start, which was kept for this agent
+            self.reservations.cells.remove(&(agent.start, 1));
+        }
+        let mut coord = self.search.search(
+            can_enter,
+            agent,
+            self.window,
+            &self.reservations,
+            distance_map,
+            observer,
+        )?;
+        observer.path_cost(self.search.seen[&(coord, self.window)].cost);
+        let mut plan = vec![coord; self.window as usize];
+        for time in (1..self.window).rev() {
+            coord = self.search.seen[&(coord, time + 1)].parent;
+            plan[time as usize - 1] = coord;
+        }
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_world::{random_grid, Search};
+    use grid_2d::Grid;
+    use rand::{seq::SliceRandom, SeedableRng};
+    use rand_isaac::Isaac64Rng;
+
+    #[derive(Default)]
+    struct PathCosts(Vec<u32>);
+
+    impl Observer for PathCosts {
+        fn path_cost(&mut self, cost: u32) {
+            self.0.push(cost);
+        }
+    }
+
+    fn position(agent: Agent, plan: &[Coord], time: usize) -> Coord {
+        if time == 0 {
+            agent.start
+        } else {
+            plan[time - 1]
+        }
+    }
+
+    fn assert_plans(search: &Search, agents: &[Agent], plans: &[Result<Vec<Coord>, SearchError>], window: usize) {
+        // agents which couldn't be planned stay where they are
+        let stay = agents.iter().map(|agent| vec![agent.start; window]).collect::<Vec<_>>();
+        let planned = agents
+            .iter()
+            .zip(plans)
+            .zip(&stay)
+            .map(|((&agent, plan), stay)| (agent, plan.as_ref().unwrap_or(stay).as_slice()))
+            .collect::<Vec<_>>();
+        for &(agent, plan) in &planned {
+            assert_eq!(plan.len(), window);
+            for time in 0..window {
+                let (from, to) = (position(agent, plan, time), position(agent, plan, time + 1));
+                assert!(from.manhattan_distance(to) <= 1);
+                assert!(to == agent.goal || search.can_enter(to));
+            }
+        }
+        for (i, &(a, plan_a)) in planned.iter().enumerate() {
+            for &(b, plan_b) in &planned[i + 1..] {
+                for time in 0..=window {
+                    assert_ne!(position(a, plan_a, time), position(b, plan_b, time));
+                }
+                for time in 0..window {
+                    let swapped = position(a, plan_a, time) == position(b, plan_b, time + 1)
+                        && position(b, plan_b, time) == position(a, plan_a, time + 1);
+                    assert!(!swapped);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn corridor() {
+        // a corridor with a single alcove halfway along
+        let grid = Grid::new_fn(Size::new(10, 2), |coord| coord.y == 0 || coord.x == 5);
+        let search = Search { grid: &grid };
+        let agents = [
+            Agent {
+                start: Coord::new(0, 0),
+                goal: Coord::new(9, 0),
+            },
+            Agent {
+                start: Coord::new(9, 0),
+                goal: Coord::new(0, 0),
+            },
+        ];
+        let window = 16;
+        let mut ctx = CooperativeContext::new(grid.size(), window);
+        let mut plans = Vec::new();
+        ctx.plan(&search, &agents, &mut plans);
+        assert_plans(&search, &agents, &plans, window as usize);
+        for (agent, plan) in agents.iter().zip(&plans) {
+            assert_eq!(plan.as_ref().unwrap().last(), Some(&agent.goal));
+        }
+        assert!(plans[1].as_ref().unwrap().contains(&Coord::new(5, 1)));
+    }
+
+    #[test]
+    fn unreachable() {
+        let grid = Grid::new_fn(Size::new(5, 1), |coord| coord.x != 2);
+        let search = Search { grid: &grid };
+        let agents = [
+            Agent {
+                start: Coord::new(0, 0),
+                goal: Coord::new(4, 0),
+            },
+            Agent {
+                start: Coord::new(1, 0),
+                goal: Coord::new(0, 0),
+            },
+        ];
+        let mut ctx = CooperativeContext::new(grid.size(), 4);
+        let mut plans = Vec::new();
+        ctx.plan(&search, &agents, &mut plans);
+        // the goal's distance map doesn't reach the start, so no search is run
+        assert_eq!(plans[0], Err(SearchError::StartNotInDistanceMap(Coord::new(0, 0))));
+        // the second agent waits for the first, which is assumed not to move
+        assert_eq!(plans[1], Ok(vec![Coord::new(1, 0); 4]));
+        // the stuck agent is avoided even by agents planned before it
+        ctx.plan(&search, &[agents[1], agents[0]], &mut plans);
+        assert_eq!(plans[0], Ok(vec![Coord::new(1, 0); 4]));
+        assert!(plans[1].is_err());
+    }
+
+    #[test]
+    fn random_grids() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 50;
+        let num_agents = 12;
+        let window = 10;
+        let size = Size::new(20, 20);
+        let mut ctx = CooperativeContext::new(size, window);
+        let mut plans = Vec::new();
+        for _ in 0..num_tests {
+            let grid = random_grid(size, 5, &mut rng);
+            let search = Search { grid: &grid };
+            let mut open = grid
+                .enumerate()
+                .filter_map(|(coord, &open)| if open { Some(coord) } else { None })
+                .collect::<Vec<_>>();
+            open.shuffle(&mut rng);
+            let starts = open.clone();
+            open.shuffle(&mut rng);
+            let agents = starts
+                .iter()
+                .zip(&open)
+                .take(num_agents)
+                .map(|(&start, &goal)| Agent { start, goal })
+                .collect::<Vec<_>>();
+            ctx.clear_distance_cache();
+            let mut path_costs = PathCosts::default();
+            ctx.plan_with_observer(&search, &agents, &mut plans, &mut path_costs);
+            assert_plans(&search, &agents, &plans, window as usize);
+            assert_eq!(path_costs.0.len(), plans.iter().filter(|plan| plan.is_ok()).count());
+            // the first agent has nothing to avoid so follows a shortest path
+            if let Ok(plan) = &plans[0] {
+                let distance_map = &ctx.distance_maps[&agents[0].goal];
+                let distance = distance_map.distance(agents[0].start).unwrap();
+                let remaining = distance_map.distance(*plan.last().unwrap()).unwrap();
+                assert_eq!(remaining, distance.saturating_sub(window));
+                // waiting at the goal is free
+                assert_eq!(path_costs.0[0], distance.min(window));
+            }
+        }
+    }
+}
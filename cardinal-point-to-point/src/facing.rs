@@ -0,0 +1,360 @@
+use crate::{CanEnter, CardinalDirection, Coord, NoPath, Observer, Path, SearchError, Size, Step, UnitCoord};
+use grid_2d::Grid;
+use grid_search_cardinal_common::min_heap::MinHeapNode;
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+use std::collections::BinaryHeap;
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TurnPenalty {
+    /// Added to the cost of a path for each 90 degree turn
+    pub cost_per_turn: u32,
+    /// Allows turning 90 degrees without moving. Otherwise each step may only go forward or turn
+    /// left or right, so turning around requires space to do so.
+    pub rotate_in_place: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Key {
+    cost_plus_heuristic: u32,
+    turns: u32,
+}
+
+type Node = MinHeapNode<Key, (u32, Coord, UnitCoord)>;
+
+#[derive(Clone, Copy, Debug)]
+enum Parent {
+    Start,
+    // moved into this cell while facing the parent's facing
+    Move { facing: UnitCoord },
+    Rotate { facing: UnitCoord },
+}
+
+#[derive(Clone, Copy, Debug)]
+struct State {
+    count: u64,
+    cost: u32,
+    turns: u32,
+    parent: Parent,
+}
+
+fn index(facing: UnitCoord) -> usize {
+    facing.to_cardinal_direction() as usize
+}
+
+/// Searches over (coord, facing) pairs, so that turning can have a cost. Among the paths of
+/// minimal cost, paths with the fewest turns are preferred.
+pub struct FacingContext {
+    count: u64,
+    // indexed by facing
+    grid: Grid<[State; 4]>,
+    priority_queue: BinaryHeap<Node>,
+}
+
+impl FacingContext {
+    pub fn new(size: Size) -> Self {
+        let state = State {
+            count: 0,
+            cost: 0,
+            turns: 0,
+            parent: Parent::Start,
+        };
+        Self {
+            count: 0,
+            grid: Grid::new_copy(size, [state; 4]),
+            priority_queue: BinaryHeap::new(),
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.grid.size()
+    }
+
+    fn state(&self, cost: u32, turns: u32, parent: Parent) -> State {
+        State {
+            count: self.count,
+            cost,
+            turns,
+            parent,
+        }
+    }
+
+    fn visit<O: Observer>(&mut self, coord: Coord, facing: UnitCoord, new_state: State, goal: Coord, observer: &mut O) {
+        let State { cost, turns, .. } = new_state;
+        let state = &mut self.grid.get_checked_mut(coord)[index(facing)];
+        if state.count == self.count && (state.cost, state.turns) <= (cost, turns) {
+            return;
+        }
+        *state = new_state;
+        observer.visit(coord);
+        observer.push(coord);
+        self.priority_queue.push(Node {
+            key: Key {
+                cost_plus_heuristic: cost + coord.manhattan_distance(goal),
+                turns,
+            },
+            value: (cost, coord, facing),
+        });
+        observer.queue_len(self.priority_queue.len());
+    }
+
+    fn search_core<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        start_facing: UnitCoord,
+        goal: Coord,
+        turn_penalty: TurnPenalty,
+        observer: &mut O,
+    ) -> Result<UnitCoord, NoPath> {
+        self.count += 1;
+        self.priority_queue.clear();
+        self.visit(start, start_facing, self.state(0, 0, Parent::Start), goal, observer);
+        while let Some(Node {
+            value: (cost, coord, facing),
+            ..
+        }) = self.priority_queue.pop()
+        {
+            if coord == goal {
+                return Ok(facing);
+            }
+            let state = self.grid.get_checked(coord)[index(facing)];
+            if cost > state.cost {
+                continue;
+            }
+            observer.expand(coord);
+            let parent = Parent::Move { facing };
+            for (in_direction, turns) in [(facing, 0), (facing.left90(), 1), (facing.right90(), 1)] {
+                let step = Step {
+                    to_coord: coord + in_direction.to_coord(),
+                    in_direction,
+                };
+                if self.grid.get(step.to_coord).is_none() {
+                    continue;
+                }
+                if step.to_coord != goal && !can_enter.can_step(step) {
+                    continue;
+                }
+                let cost = cost + 1 + turns * turn_penalty.cost_per_turn;
+                let new_state = self.state(cost, state.turns + turns, parent);
+                self.visit(step.to_coord, in_direction, new_state, goal, observer);
+            }
+            if turn_penalty.rotate_in_place {
+                let parent = Parent::Rotate { facing };
+                let cost = cost + turn_penalty.cost_per_turn;
+                for to_facing in [facing.left90(), facing.right90()] {
+                    self.visit(
+                        coord,
+                        to_facing,
+                        self.state(cost, state.turns + 1, parent),
+                        goal,
+                        observer,
+                    );
+                }
+            }
+        }
+        Err(NoPath)
+    }
+
+    /// Finds a path from `start`, initially facing `start_facing`, to `goal`. Turns in place
+    /// don't appear in the path, so turning around shows up as two consecutive steps in
+    /// opposite directions.
+    pub fn facing_search_path<S: CanEnter>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        start_facing: CardinalDirection,
+        goal: Coord,
+        turn_penalty: TurnPenalty,
+        path: &mut Path,
+    ) -> Result<(), SearchError> {
+        self.facing_search_path_with_observer(can_enter, start, start_facing, goal, turn_penalty, path, &mut ())
+    }
+
+    /// The path cost given to the observer includes the cost of turning
+    #[allow(clippy::too_many_arguments)]
+    pub fn facing_search_path_with_observer<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        start_facing: CardinalDirection,
+        goal: Coord,
+        turn_penalty: TurnPenalty,
+        path: &mut Path,
+        observer: &mut O,
+    ) -> Result<(), SearchError> {
+        SearchError::check_bounds(self.size(), &[start], &[goal])?;
+        let start_facing = UnitCoord::from_cardinal_direction(start_facing);
+        let mut facing = self
+            .search_core(can_enter, start, start_facing, goal, turn_penalty, observer)
+            .map_err(|NoPath| {
+                let visited = self
+                    .grid
+                    .enumerate()
+                    .filter(|(_, states)| states.iter().any(|state| state.count == self.count))
+                    .map(|(coord, _)| coord);
+                SearchError::goal_unreachable(start, visited, |coord| coord.manhattan_distance(goal))
+            })?;
+        observer.path_cost(self.grid.get_checked(goal)[index(facing)].cost);
+        path.clear();
+        let mut coord = goal;
+        loop {
+            match self.grid.get_checked(coord)[index(facing)].parent {
+                Parent::Start => break,
+                Parent::Move { facing: parent_facing } => {
+                    path.push_front(Step {
+                        to_coord: coord,
+                        in_direction: facing,
+                    })
+                    .expect("each step enters the cell the path starts from");
+                    coord -= facing.to_coord();
+                    facing = parent_facing;
+                }
+                Parent::Rotate { facing: parent_facing } => facing = parent_facing,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_world::{random_grid, Search};
+    use crate::{expand, Context, SearchStats};
+    use rand::{Rng, SeedableRng};
+    use rand_isaac::Isaac64Rng;
+
+    fn count_turns(start_facing: CardinalDirection, path: &Path) -> u32 {
+        let mut facing = start_facing;
+        let mut turns = 0;
+        for node in path.iter() {
+            turns += if node.in_direction == facing {
+                0
+            } else if node.in_direction == facing.opposite() {
+                2
+            } else {
+                1
+            };
+            facing = node.in_direction;
+        }
+        turns
+    }
+
+    fn assert_path(search: &Search, path: &Path, start: Coord, goal: Coord) {
+        let mut coord = start;
+        for node in path.iter() {
+            assert_eq!(node.to_coord - node.in_direction.coord(), coord);
+            assert!(node.to_coord == goal || search.can_enter(node.to_coord));
+            coord = node.to_coord;
+        }
+        assert_eq!(coord, goal);
+    }
+
+    #[test]
+    fn fewest_turns() {
+        let grid = Grid::new_copy(Size::new(10, 10), true);
+        let search = Search { grid: &grid };
+        let mut ctx = FacingContext::new(grid.size());
+        let mut path = Path::default();
+        let turn_penalty = TurnPenalty {
+            cost_per_turn: 0,
+            rotate_in_place: false,
+        };
+        ctx.facing_search_path(
+            &search,
+            Coord::new(0, 0),
+            CardinalDirection::East,
+            Coord::new(5, 5),
+            turn_penalty,
+            &mut path,
+        )
+        .unwrap();
+        assert_eq!(path.len(), 10);
+        assert_eq!(count_turns(CardinalDirection::East, &path), 1);
+        assert_eq!(path.iter().next().unwrap().in_direction, CardinalDirection::East);
+    }
+
+    #[test]
+    fn turn_around() {
+        let grid = Grid::new_fn(Size::new(10, 1), |_| true);
+        let search = Search { grid: &grid };
+        let mut ctx = FacingContext::new(grid.size());
+        let mut path = Path::default();
+        let start = Coord::new(5, 0);
+        let goal = Coord::new(2, 0);
+        let fixed = TurnPenalty {
+            cost_per_turn: 1,
+            rotate_in_place: false,
+        };
+        // without turning on the spot the agent can only drive to the east end of the corridor
+        assert_eq!(
+            ctx.facing_search_path(&search, start, CardinalDirection::East, goal, fixed, &mut path),
+            Err(SearchError::GoalUnreachable {
+                closest: start,
+                num_reachable: 5
+            })
+        );
+        let rotate = TurnPenalty {
+            rotate_in_place: true,
+            ..fixed
+        };
+        ctx.facing_search_path(&search, start, CardinalDirection::East, goal, rotate, &mut path)
+            .unwrap();
+        assert_eq!(path.len(), 3);
+        assert_eq!(count_turns(CardinalDirection::East, &path), 2);
+    }
+
+    #[test]
+    fn random_grids() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 500;
+        let size = Size::new(20, 20);
+        let mut ctx = Context::new(size);
+        let mut facing_ctx = FacingContext::new(size);
+        let mut path = Path::default();
+        let mut expected_path = Path::default();
+        for _ in 0..num_tests {
+            let grid = random_grid(size, 4, &mut rng);
+            let search = Search { grid: &grid };
+            let start = Coord::random_within(size, &mut rng);
+            let goal = Coord::random_within(size, &mut rng);
+            let start_facing = CardinalDirection::all().nth(rng.gen_range(0..4)).unwrap();
+            let cost_per_turn = rng.gen_range(0..4);
+            let expected = ctx.point_to_point_search_path(expand::JumpPoint, &search, start, goal, &mut expected_path);
+            let free_turns = TurnPenalty {
+                cost_per_turn: 0,
+                rotate_in_place: true,
+            };
+            let result = facing_ctx.facing_search_path(&search, start, start_facing, goal, free_turns, &mut path);
+            assert_eq!(result.is_ok(), expected.is_ok());
+            if result.is_err() {
+                continue;
+            }
+            assert_path(&search, &path, start, goal);
+            assert_eq!(path.len(), expected_path.len());
+            assert!(count_turns(start_facing, &path) <= count_turns(start_facing, &expected_path));
+            let turn_penalty = TurnPenalty {
+                cost_per_turn,
+                rotate_in_place: true,
+            };
+            let mut stats = SearchStats::default();
+            facing_ctx
+                .facing_search_path_with_observer(
+                    &search,
+                    start,
+                    start_facing,
+                    goal,
+                    turn_penalty,
+                    &mut path,
+                    &mut stats,
+                )
+                .unwrap();
+            assert_path(&search, &path, start, goal);
+            let cost = |path: &Path| path.len() as u32 + cost_per_turn * count_turns(start_facing, path);
+            assert!(cost(&path) <= cost(&expected_path));
+            assert_eq!(stats.path_cost, Some(cost(&path)));
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::Coord;
+
+pub trait Goal {
+    fn is_goal(&self, coord: Coord) -> bool;
+    fn heuristic(&self, coord: Coord) -> u32;
+    fn coords(&self) -> &[Coord];
+    // With a single goal, the first path to reach it is a shortest path, so the search can stop
+    // immediately. With multiple goals, a jump can reach a distant goal before a nearer one, so
+    // goals are queued like any other cell and the search stops when one is popped.
+    fn stop_when_visited(&self) -> bool;
+}
+
+impl Goal for Coord {
+    fn is_goal(&self, coord: Coord) -> bool {
+        *self == coord
+    }
+    fn heuristic(&self, coord: Coord) -> u32 {
+        coord.manhattan_distance(*self)
+    }
+    fn coords(&self) -> &[Coord] {
+        std::slice::from_ref(self)
+    }
+    fn stop_when_visited(&self) -> bool {
+        true
+    }
+}
+
+// Above this many goals, the heuristic is the distance to the bounding box of the goals rather
+// than the distance to the nearest goal, and goal membership is tested with a binary search.
+const MAX_GOALS_FOR_LINEAR_SCAN: usize = 16;
+
+#[derive(Debug, Default)]
+pub struct GoalSet {
+    goals: Vec<Coord>,
+    top_left: Coord,
+    bottom_right: Coord,
+}
+
+impl GoalSet {
+    pub fn set(&mut self, goals: &[Coord]) {
+        self.goals.clear();
+        self.goals.extend_from_slice(goals);
+        self.goals.sort_unstable();
+        self.goals.dedup();
+        if let Some(&first) = self.goals.first() {
+            self.top_left = first;
+            self.bottom_right = first;
+            for &coord in &self.goals {
+                self.top_left = Coord::new(self.top_left.x.min(coord.x), self.top_left.y.min(coord.y));
+                self.bottom_right = Coord::new(self.bottom_right.x.max(coord.x), self.bottom_right.y.max(coord.y));
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.goals.is_empty()
+    }
+}
+
+fn distance_to_range(value: i32, min: i32, max: i32) -> u32 {
+    if value < min {
+        (min - value) as u32
+    } else if value > max {
+        (value - max) as u32
+    } else {
+        0
+    }
+}
+
+impl Goal for GoalSet {
+    fn is_goal(&self, coord: Coord) -> bool {
+        if self.goals.len() <= MAX_GOALS_FOR_LINEAR_SCAN {
+            self.goals.contains(&coord)
+        } else {
+            self.goals.binary_search(&coord).is_ok()
+        }
+    }
+    fn heuristic(&self, coord: Coord) -> u32 {
+        if self.goals.len() <= MAX_GOALS_FOR_LINEAR_SCAN {
+            self.goals
+                .iter()
+                .map(|&goal| coord.manhattan_distance(goal))
+                .min()
+                .unwrap_or(0)
+        } else {
+            distance_to_range(coord.x, self.top_left.x, self.bottom_right.x)
+                + distance_to_range(coord.y, self.top_left.y, self.bottom_right.y)
+        }
+    }
+    fn coords(&self) -> &[Coord] {
+        &self.goals
+    }
+    fn stop_when_visited(&self) -> bool {
+        false
+    }
+}
+
+// Used to find jump points that don't depend on where the goal is
+pub struct NoGoal;
+
+impl Goal for NoGoal {
+    fn is_goal(&self, _coord: Coord) -> bool {
+        false
+    }
+    fn heuristic(&self, _coord: Coord) -> u32 {
+        0
+    }
+    fn coords(&self) -> &[Coord] {
+        &[]
+    }
+    fn stop_when_visited(&self) -> bool {
+        true
+    }
+}
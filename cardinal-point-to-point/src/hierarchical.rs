@@ -0,0 +1,662 @@
+use crate::{expand, CanEnter, Context, Coord, NoPath, Observer, Path, SearchError, Size, Step, UnitCoord};
+use grid_2d::Grid;
+use grid_search_cardinal_common::{
+    coord::{CardinalCoord, UNIT_COORDS},
+    min_heap::MinHeapNode,
+};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+// Runs of open cells along a cluster border at least this wide get an entrance at each end
+// rather than one in the middle
+const MIN_WIDTH_FOR_TWO_ENTRANCES: u32 = 6;
+
+#[derive(Clone, Copy, Debug)]
+struct Edge {
+    to_coord: Coord,
+    cost: u32,
+}
+
+#[derive(Debug)]
+struct Entrance {
+    coord: Coord,
+    // edges to entrances in neighbouring clusters, which are always adjacent
+    inter_cluster_edges: Vec<Edge>,
+    // cached distances to the other entrances of the same cluster
+    intra_cluster_edges: Vec<Edge>,
+}
+
+#[derive(Debug, Default)]
+struct Cluster {
+    entrances: Vec<Entrance>,
+}
+
+// lowest cost plus heuristic first, breaking ties in favour of the node with the highest cost
+type Node = MinHeapNode<(u32, Reverse<u32>), Coord>;
+
+#[derive(Clone, Copy, Debug)]
+struct SeenNode {
+    cost: u32,
+    parent: Option<Coord>,
+}
+
+// Presents the cells of a single cluster to a `Context` the size of a cluster
+struct InCluster<'a, S> {
+    can_enter: &'a S,
+    top_left: Coord,
+    size: Size,
+}
+
+impl<'a, S: CanEnter> CanEnter for InCluster<'a, S> {
+    fn can_enter(&self, coord: Coord) -> bool {
+        self.size.is_valid(coord) && self.can_enter.can_enter(coord + self.top_left)
+    }
+    fn can_step(&self, step: Step) -> bool {
+        self.size.is_valid(step.to_coord)
+            && self.can_enter.can_step(Step {
+                to_coord: step.to_coord + self.top_left,
+                in_direction: step.in_direction,
+            })
+    }
+}
+
+/// Splits a large grid into clusters connected by entrances on their borders, and finds paths
+/// by searching the graph of entrances before refining each leg of the route within a single
+/// cluster. Paths are usually close to, but not always, the shortest path.
+pub struct HierarchicalContext {
+    size: Size,
+    cluster_size: Size,
+    clusters: Grid<Cluster>,
+    cluster_context: Context,
+    cluster_path: Path,
+    priority_queue: BinaryHeap<Node>,
+    seen: HashMap<Coord, SeenNode>,
+    // edges to and from the start and goal of the current search
+    temporary_edges: Vec<(Coord, Edge)>,
+    edges: Vec<Edge>,
+    route: Vec<Coord>,
+}
+
+impl HierarchicalContext {
+    pub fn new<S: CanEnter>(can_enter: &S, size: Size, cluster_size: Size) -> Self {
+        let num_clusters = Size::new(
+            size.width().div_ceil(cluster_size.width()),
+            size.height().div_ceil(cluster_size.height()),
+        );
+        let mut context = Self {
+            size,
+            cluster_size,
+            clusters: Grid::new_fn(num_clusters, |_| Cluster::default()),
+            cluster_context: Context::new(cluster_size),
+            cluster_path: Path::default(),
+            priority_queue: BinaryHeap::new(),
+            seen: HashMap::new(),
+            temporary_edges: Vec::new(),
+            edges: Vec::new(),
+            route: Vec::new(),
+        };
+        for cluster_coord in num_clusters.coord_iter_row_major() {
+            for &direction in &[UNIT_COORDS[0], UNIT_COORDS[3]] {
+                context.add_entrances(can_enter, cluster_coord, direction);
+            }
+        }
+        for cluster_coord in num_clusters.coord_iter_row_major() {
+            context.compute_intra_cluster_edges(can_enter, cluster_coord);
+        }
+        context
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn cluster_size(&self) -> Size {
+        self.cluster_size
+    }
+
+    fn cluster_coord(&self, coord: Coord) -> Coord {
+        Coord::new(
+            coord.x / self.cluster_size.width() as i32,
+            coord.y / self.cluster_size.height() as i32,
+        )
+    }
+
+    fn cluster_top_left(&self, cluster_coord: Coord) -> Coord {
+        Coord::new(
+            cluster_coord.x * self.cluster_size.width() as i32,
+            cluster_coord.y * self.cluster_size.height() as i32,
+        )
+    }
+
+    // clusters on the bottom and right edges may be smaller than `cluster_size`
+    fn cluster_bounds(&self, cluster_coord: Coord) -> (Coord, Size) {
+        let top_left = self.cluster_top_left(cluster_coord);
+        let bottom_right =
+            (top_left + self.cluster_size.to_coord().unwrap()).pairwise_min(self.size.to_coord().unwrap());
+        let size = (bottom_right - top_left).to_size().unwrap();
+        (top_left, size)
+    }
+
+    fn entrance_mut(&mut self, coord: Coord) -> &mut Entrance {
+        let cluster_coord = self.cluster_coord(coord);
+        let cluster = self.clusters.get_checked_mut(cluster_coord);
+        let index = match cluster.entrances.iter().position(|entrance| entrance.coord == coord) {
+            Some(index) => index,
+            None => {
+                cluster.entrances.push(Entrance {
+                    coord,
+                    inter_cluster_edges: Vec::new(),
+                    intra_cluster_edges: Vec::new(),
+                });
+                cluster.entrances.len() - 1
+            }
+        };
+        &mut cluster.entrances[index]
+    }
+
+    fn add_transition<S: CanEnter>(&mut self, can_enter: &S, coord: Coord, direction: UnitCoord) {
+        let neighbour_coord = coord + direction.to_coord();
+        for (from_coord, to_coord, in_direction) in [
+            (coord, neighbour_coord, direction),
+            (neighbour_coord, coord, direction.opposite()),
+        ] {
+            let entrance = self.entrance_mut(from_coord);
+            if can_enter.can_step(Step { to_coord, in_direction }) {
+                entrance.inter_cluster_edges.push(Edge { to_coord, cost: 1 });
+            }
+        }
+    }
+
+    // Adds entrances on the border between a cluster and its neighbour in `direction`, which
+    // must be east or south
+    fn add_entrances<S: CanEnter>(&mut self, can_enter: &S, cluster_coord: Coord, direction: UnitCoord) {
+        let neighbour_cluster_coord = cluster_coord + direction.to_coord();
+        if self.clusters.get(neighbour_cluster_coord).is_none() {
+            return;
+        }
+        let (top_left, size) = self.cluster_bounds(cluster_coord);
+        // the last row or column of the cluster, and the direction along it
+        let (first_coord, along, length) = if direction.to_coord().x > 0 {
+            (
+                top_left + Coord::new(size.width() as i32 - 1, 0),
+                Coord::new(0, 1),
+                size.height(),
+            )
+        } else {
+            (
+                top_left + Coord::new(0, size.height() as i32 - 1),
+                Coord::new(1, 0),
+                size.width(),
+            )
+        };
+        let is_open = |i: u32| {
+            let coord = first_coord + along * i as i32;
+            can_enter.can_enter(coord) && can_enter.can_enter(coord + direction.to_coord())
+        };
+        let mut i = 0;
+        while i < length {
+            if !is_open(i) {
+                i += 1;
+                continue;
+            }
+            let run_start = i;
+            while i < length && is_open(i) {
+                i += 1;
+            }
+            let width = i - run_start;
+            if width >= MIN_WIDTH_FOR_TWO_ENTRANCES {
+                self.add_transition(can_enter, first_coord + along * run_start as i32, direction);
+                self.add_transition(can_enter, first_coord + along * (i - 1) as i32, direction);
+            } else {
+                let middle = run_start + width / 2;
+                self.add_transition(can_enter, first_coord + along * middle as i32, direction);
+            }
+        }
+    }
+
+    fn cluster_distance<S: CanEnter>(
+        &mut self,
+        can_enter: &S,
+        cluster_coord: Coord,
+        start: Coord,
+        goal: Coord,
+    ) -> Result<(), NoPath> {
+        let (top_left, size) = self.cluster_bounds(cluster_coord);
+        let in_cluster = InCluster {
+            can_enter,
+            top_left,
+            size,
+        };
+        self.cluster_context
+            .point_to_point_search_path(
+                expand::JumpPoint,
+                &in_cluster,
+                start - top_left,
+                goal - top_left,
+                &mut self.cluster_path,
+            )
+            .map_err(|_| NoPath)
+    }
+
+    fn compute_intra_cluster_edges<S: CanEnter>(&mut self, can_enter: &S, cluster_coord: Coord) {
+        let coords = self
+            .clusters
+            .get_checked(cluster_coord)
+            .entrances
+            .iter()
+            .map(|entrance| entrance.coord)
+            .collect::<Vec<_>>();
+        for (i, &from_coord) in coords.iter().enumerate() {
+            let mut edges = Vec::new();
+            for &to_coord in &coords {
+                if to_coord != from_coord
+                    && self
+                        .cluster_distance(can_enter, cluster_coord, from_coord, to_coord)
+                        .is_ok()
+                {
+                    edges.push(Edge {
+                        to_coord,
+                        cost: self.cluster_path.len() as u32,
+                    });
+                }
+            }
+            self.clusters.get_checked_mut(cluster_coord).entrances[i].intra_cluster_edges = edges;
+        }
+    }
+
+    /// Recomputes the entrances and cached distances of the cluster containing `coord`, and of
+    /// its neighbours, after cells in that cluster changed. Does nothing if `coord` is outside
+    /// the grid.
+    pub fn rebuild_cluster<S: CanEnter>(&mut self, can_enter: &S, coord: Coord) {
+        if !self.size.is_valid(coord) {
+            return;
+        }
+        let cluster_coord = self.cluster_coord(coord);
+        let (top_left, size) = self.cluster_bounds(cluster_coord);
+        let in_cluster = |coord: Coord| size.is_valid(coord - top_left);
+        let mut affected_cluster_coords = vec![cluster_coord];
+        for &direction in &UNIT_COORDS {
+            let neighbour_cluster_coord = cluster_coord + direction.to_coord();
+            if self.clusters.get(neighbour_cluster_coord).is_some() {
+                affected_cluster_coords.push(neighbour_cluster_coord);
+            }
+        }
+        // forget every transition into or out of the cluster
+        for &affected_cluster_coord in &affected_cluster_coords {
+            let cluster = self.clusters.get_checked_mut(affected_cluster_coord);
+            for entrance in cluster.entrances.iter_mut() {
+                let entrance_in_cluster = in_cluster(entrance.coord);
+                entrance
+                    .inter_cluster_edges
+                    .retain(|edge| !entrance_in_cluster && !in_cluster(edge.to_coord));
+            }
+            cluster
+                .entrances
+                .retain(|entrance| !entrance.inter_cluster_edges.is_empty());
+        }
+        for &direction in &UNIT_COORDS {
+            let neighbour_cluster_coord = cluster_coord + direction.to_coord();
+            if self.clusters.get(neighbour_cluster_coord).is_none() {
+                continue;
+            }
+            if direction.to_coord().x > 0 || direction.to_coord().y > 0 {
+                self.add_entrances(can_enter, cluster_coord, direction);
+            } else {
+                self.add_entrances(can_enter, neighbour_cluster_coord, direction.opposite());
+            }
+        }
+        for affected_cluster_coord in affected_cluster_coords {
+            self.compute_intra_cluster_edges(can_enter, affected_cluster_coord);
+        }
+    }
+
+    fn add_edges_from(&self, coord: Coord, edges: &mut Vec<Edge>) {
+        edges.clear();
+        if let Some(entrance) = self
+            .clusters
+            .get_checked(self.cluster_coord(coord))
+            .entrances
+            .iter()
+            .find(|entrance| entrance.coord == coord)
+        {
+            edges.extend_from_slice(&entrance.inter_cluster_edges);
+            edges.extend_from_slice(&entrance.intra_cluster_edges);
+        }
+        edges.extend(
+            self.temporary_edges
+                .iter()
+                .filter(|&&(from_coord, _)| from_coord == coord)
+                .map(|&(_, edge)| edge),
+        );
+    }
+
+    fn entrance_coords(&self, cluster_coord: Coord) -> Vec<Coord> {
+        self.clusters
+            .get_checked(cluster_coord)
+            .entrances
+            .iter()
+            .map(|entrance| entrance.coord)
+            .collect()
+    }
+
+    fn add_temporary_edge<S: CanEnter>(&mut self, can_enter: &S, from_coord: Coord, to_coord: Coord) {
+        let cluster_coord = self.cluster_coord(from_coord);
+        if self
+            .cluster_distance(can_enter, cluster_coord, from_coord, to_coord)
+            .is_ok()
+        {
+            let cost = self.cluster_path.len() as u32;
+            self.temporary_edges.push((from_coord, Edge { to_coord, cost }));
+        }
+    }
+
+    // Connects the start and goal to the entrances of their clusters. Unenterable starts and
+    // goals can only be left or entered through an adjacent cell, which may be in another
+    // cluster without being an entrance, so those cells are connected too.
+    fn connect_endpoints<S: CanEnter>(&mut self, can_enter: &S, start: Coord, goal: Coord) {
+        self.temporary_edges.clear();
+        let mut sources = vec![start];
+        let mut sinks = vec![goal];
+        let start_cluster_coord = self.cluster_coord(start);
+        let goal_cluster_coord = self.cluster_coord(goal);
+        for &direction in &UNIT_COORDS {
+            let coord = start + direction.to_coord();
+            if coord == goal {
+                self.temporary_edges.push((
+                    start,
+                    Edge {
+                        to_coord: goal,
+                        cost: 1,
+                    },
+                ));
+            } else if !can_enter.can_enter(start)
+                && self.size.is_valid(coord)
+                && self.cluster_coord(coord) != start_cluster_coord
+                && can_enter.can_step(Step {
+                    to_coord: coord,
+                    in_direction: direction,
+                })
+            {
+                self.temporary_edges.push((
+                    start,
+                    Edge {
+                        to_coord: coord,
+                        cost: 1,
+                    },
+                ));
+                sources.push(coord);
+            }
+            let coord = goal + direction.to_coord();
+            if !can_enter.can_enter(goal)
+                && coord != start
+                && self.size.is_valid(coord)
+                && self.cluster_coord(coord) != goal_cluster_coord
+                && can_enter.can_enter(coord)
+            {
+                self.temporary_edges.push((
+                    coord,
+                    Edge {
+                        to_coord: goal,
+                        cost: 1,
+                    },
+                ));
+                sinks.push(coord);
+            }
+        }
+        for &source in &sources {
+            for entrance_coord in self.entrance_coords(self.cluster_coord(source)) {
+                self.add_temporary_edge(can_enter, source, entrance_coord);
+            }
+            for &sink in &sinks {
+                if self.cluster_coord(source) == self.cluster_coord(sink) {
+                    self.add_temporary_edge(can_enter, source, sink);
+                }
+            }
+        }
+        for &sink in &sinks {
+            for entrance_coord in self.entrance_coords(self.cluster_coord(sink)) {
+                self.add_temporary_edge(can_enter, entrance_coord, sink);
+            }
+        }
+    }
+
+    fn visit<O: Observer>(&mut self, coord: Coord, cost: u32, parent: Option<Coord>, goal: Coord, observer: &mut O) {
+        let seen_node = self.seen.entry(coord).or_insert(SeenNode {
+            cost: u32::MAX,
+            parent: None,
+        });
+        if cost < seen_node.cost {
+            *seen_node = SeenNode { cost, parent };
+            observer.visit(coord);
+            observer.push(coord);
+            self.priority_queue.push(Node {
+                key: (cost + coord.manhattan_distance(goal), Reverse(cost)),
+                value: coord,
+            });
+            observer.queue_len(self.priority_queue.len());
+        }
+    }
+
+    // Finds the sequence of entrances between the start and goal, storing it in `route`
+    fn search_route<O: Observer>(&mut self, start: Coord, goal: Coord, observer: &mut O) -> Result<(), NoPath> {
+        self.seen.clear();
+        self.priority_queue.clear();
+        self.visit(start, 0, None, goal, observer);
+        while let Some(Node {
+            key: (_, Reverse(cost)),
+            value: coord,
+        }) = self.priority_queue.pop()
+        {
+            if coord == goal {
+                self.route.clear();
+                let mut coord = Some(goal);
+                while let Some(current) = coord {
+                    self.route.push(current);
+                    coord = self.seen[&current].parent;
+                }
+                self.route.reverse();
+                return Ok(());
+            }
+            if self.seen[&coord].cost != cost {
+                continue;
+            }
+            observer.expand(coord);
+            let mut edges = std::mem::take(&mut self.edges);
+            self.add_edges_from(coord, &mut edges);
+            for &edge in &edges {
+                self.visit(edge.to_coord, cost + edge.cost, Some(coord), goal, observer);
+            }
+            self.edges = edges;
+        }
+        Err(NoPath)
+    }
+
+    fn refine_route<S: CanEnter>(&mut self, can_enter: &S, path: &mut Path) -> Result<(), NoPath> {
+        path.clear();
+        for i in 1..self.route.len() {
+            let from_coord = self.route[i - 1];
+            let to_coord = self.route[i];
+            let cluster_coord = self.cluster_coord(from_coord);
+            if cluster_coord != self.cluster_coord(to_coord) {
+                let in_direction = CardinalCoord::from_coord(to_coord - from_coord)
+                    .expect("transitions are between adjacent cells")
+                    .to_unit_coord();
+                path.push_back(Step { to_coord, in_direction })
+                    .expect("the route continues from the end of the path");
+                continue;
+            }
+            self.cluster_distance(can_enter, cluster_coord, from_coord, to_coord)?;
+            let (top_left, _) = self.cluster_bounds(cluster_coord);
+            for node in self.cluster_path.iter() {
+                path.push_back(Step {
+                    to_coord: node.to_coord + top_left,
+                    in_direction: UnitCoord::from_cardinal_direction(node.in_direction),
+                })
+                .expect("the route continues from the end of the path");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn point_to_point_search_path<S: CanEnter>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        goal: Coord,
+        path: &mut Path,
+    ) -> Result<(), SearchError> {
+        self.point_to_point_search_path_with_observer(can_enter, start, goal, path, &mut ())
+    }
+
+    /// The observer sees the search over cluster entrances, but not the searches within
+    /// clusters which refine the route it finds into a path
+    pub fn point_to_point_search_path_with_observer<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        goal: Coord,
+        path: &mut Path,
+        observer: &mut O,
+    ) -> Result<(), SearchError> {
+        SearchError::check_bounds(self.size(), &[start], &[goal])?;
+        if start == goal {
+            path.clear();
+            observer.path_cost(0);
+            return Ok(());
+        }
+        self.connect_endpoints(can_enter, start, goal);
+        self.search_route(start, goal, observer)
+            .and_then(|()| self.refine_route(can_enter, path))
+            .map_err(|NoPath| {
+                SearchError::goal_unreachable(start, self.seen.keys().cloned(), |coord| coord.manhattan_distance(goal))
+            })?;
+        observer.path_cost(path.len() as u32);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_world::{random_grid, Search};
+    use crate::SearchStats;
+    use rand::SeedableRng;
+    use rand_isaac::Isaac64Rng;
+
+    fn check(
+        hierarchical_ctx: &mut HierarchicalContext,
+        ctx: &mut Context,
+        grid: &Grid<bool>,
+        start: Coord,
+        goal: Coord,
+        path: &mut Path,
+    ) {
+        let search = Search { grid };
+        let expected = ctx.point_to_point_search_path(expand::Sequential, &search, start, goal, path);
+        let shortest = path.len();
+        let mut stats = SearchStats::default();
+        let result = hierarchical_ctx.point_to_point_search_path_with_observer(&search, start, goal, path, &mut stats);
+        assert_eq!(result.is_ok(), expected.is_ok());
+        assert_eq!(stats.path_cost, result.ok().map(|()| path.len() as u32));
+        if result.is_ok() {
+            assert!(path.len() >= shortest);
+            let mut coord = start;
+            for node in path.iter() {
+                assert_eq!(node.to_coord - node.in_direction.coord(), coord);
+                assert!(node.to_coord == goal || search.can_enter(node.to_coord));
+                coord = node.to_coord;
+            }
+            assert_eq!(coord, goal);
+        }
+    }
+
+    #[test]
+    fn random_grids() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_grids = 20;
+        let num_tests = 20;
+        let size = Size::new(45, 37);
+        let mut ctx = Context::new(size);
+        let mut path = Path::default();
+        for _ in 0..num_grids {
+            let mut grid = random_grid(size, 4, &mut rng);
+            let mut hierarchical_ctx = HierarchicalContext::new(&Search { grid: &grid }, size, Size::new(8, 8));
+            for _ in 0..num_tests {
+                let start = Coord::random_within(size, &mut rng);
+                let goal = Coord::random_within(size, &mut rng);
+                check(&mut hierarchical_ctx, &mut ctx, &grid, start, goal, &mut path);
+            }
+            for _ in 0..num_tests {
+                let changed = Coord::random_within(size, &mut rng);
+                let cell = grid.get_checked_mut(changed);
+                *cell = !*cell;
+                hierarchical_ctx.rebuild_cluster(&Search { grid: &grid }, changed);
+                let start = Coord::random_within(size, &mut rng);
+                let goal = Coord::random_within(size, &mut rng);
+                check(&mut hierarchical_ctx, &mut ctx, &grid, start, goal, &mut path);
+            }
+        }
+    }
+
+    #[test]
+    fn rebuild_out_of_bounds() {
+        let size = Size::new(20, 12);
+        let grid = Grid::new_copy(size, true);
+        let mut hierarchical_ctx = HierarchicalContext::new(&Search { grid: &grid }, size, Size::new(8, 8));
+        let mut ctx = Context::new(size);
+        let mut path = Path::default();
+        for coord in [
+            Coord::new(-1, 0),
+            Coord::new(0, -1),
+            Coord::new(-9, -9),
+            Coord::new(20, 0),
+            Coord::new(0, 12),
+        ] {
+            hierarchical_ctx.rebuild_cluster(&Search { grid: &grid }, coord);
+        }
+        check(
+            &mut hierarchical_ctx,
+            &mut ctx,
+            &grid,
+            Coord::new(0, 0),
+            Coord::new(19, 11),
+            &mut path,
+        );
+    }
+
+    #[test]
+    fn rebuild_edges_of_clusters() {
+        // clusters in the last column and row are smaller than the rest
+        let size = Size::new(20, 12);
+        let mut grid = Grid::new_copy(size, true);
+        let mut hierarchical_ctx = HierarchicalContext::new(&Search { grid: &grid }, size, Size::new(8, 8));
+        let mut ctx = Context::new(size);
+        let mut path = Path::default();
+        let (start, goal) = (Coord::new(0, 0), Coord::new(19, 11));
+        // wall off the last column of clusters along its boundary
+        for y in 0..size.height() as i32 {
+            let coord = Coord::new(16, y);
+            *grid.get_checked_mut(coord) = false;
+            hierarchical_ctx.rebuild_cluster(&Search { grid: &grid }, coord);
+        }
+        check(&mut hierarchical_ctx, &mut ctx, &grid, start, goal, &mut path);
+        assert!(hierarchical_ctx
+            .point_to_point_search_path(&Search { grid: &grid }, start, goal, &mut path)
+            .is_err());
+        // open the wall in the bottom right corner of the smaller clusters
+        let gap = Coord::new(16, 11);
+        *grid.get_checked_mut(gap) = true;
+        hierarchical_ctx.rebuild_cluster(&Search { grid: &grid }, gap);
+        check(&mut hierarchical_ctx, &mut ctx, &grid, start, goal, &mut path);
+        assert!(path.iter().any(|node| node.to_coord == gap));
+        // close the goal's corner off with a wall in the same cluster
+        for coord in [Coord::new(18, 11), Coord::new(18, 10), Coord::new(19, 10)] {
+            *grid.get_checked_mut(coord) = false;
+            hierarchical_ctx.rebuild_cluster(&Search { grid: &grid }, coord);
+        }
+        check(&mut hierarchical_ctx, &mut ctx, &grid, start, goal, &mut path);
+        assert!(hierarchical_ctx
+            .point_to_point_search_path(&Search { grid: &grid }, start, goal, &mut path)
+            .is_err());
+    }
+}
@@ -0,0 +1,383 @@
+use crate::{CanEnter, Coord, Observer, Path, SearchError, Size, Step};
+use grid_2d::Grid;
+use grid_search_cardinal_common::{coord::UNIT_COORDS, min_heap::MinHeapNode};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+const INFINITY: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Key {
+    cost_plus_heuristic: u32,
+    cost: u32,
+}
+
+type Node = MinHeapNode<Key, Coord>;
+
+#[derive(Clone, Copy, Debug)]
+struct Cell {
+    cost: u32,
+    one_step_lookahead_cost: u32,
+}
+
+impl Cell {
+    const UNVISITED: Self = Self {
+        cost: INFINITY,
+        one_step_lookahead_cost: INFINITY,
+    };
+
+    fn min_cost(&self) -> u32 {
+        self.cost.min(self.one_step_lookahead_cost)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Endpoints {
+    start: Coord,
+    goal: Coord,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpdatePathError {
+    Search(SearchError),
+    /// `update_path` was called before any call to `init_path`
+    NotInitialized,
+}
+
+/// Keeps the state of a D* Lite search between calls, so that replanning after a few cells
+/// change only repairs the affected part of the search. Costs are distances to the goal.
+pub struct IncrementalContext {
+    grid: Grid<Cell>,
+    priority_queue: BinaryHeap<Node>,
+    // added to all keys whenever the start moves, rather than re-keying the queue
+    key_modifier: u32,
+    endpoints: Option<Endpoints>,
+}
+
+impl IncrementalContext {
+    pub fn new(size: Size) -> Self {
+        Self {
+            grid: Grid::new_copy(size, Cell::UNVISITED),
+            priority_queue: BinaryHeap::new(),
+            key_modifier: 0,
+            endpoints: None,
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.grid.size()
+    }
+
+    fn cost(&self, coord: Coord) -> u32 {
+        self.grid.get(coord).map_or(INFINITY, |cell| cell.cost)
+    }
+
+    fn key(&self, coord: Coord, start: Coord) -> Key {
+        let cost = self.grid.get_checked(coord).min_cost();
+        Key {
+            cost_plus_heuristic: cost
+                .saturating_add(coord.manhattan_distance(start))
+                .saturating_add(self.key_modifier),
+            cost,
+        }
+    }
+
+    // cost of stepping from `coord` onto the neighbour in `step` and then following the current
+    // best path to the goal
+    fn cost_via<S: CanEnter>(&self, can_enter: &S, step: Step, goal: Coord) -> u32 {
+        if step.to_coord != goal && !can_enter.can_step(step) {
+            return INFINITY;
+        }
+        self.cost(step.to_coord).saturating_add(1)
+    }
+
+    fn push<O: Observer>(&mut self, coord: Coord, start: Coord, observer: &mut O) {
+        let key = self.key(coord, start);
+        observer.push(coord);
+        self.priority_queue.push(Node { key, value: coord });
+        observer.queue_len(self.priority_queue.len());
+    }
+
+    fn update_cell<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        coord: Coord,
+        Endpoints { start, goal }: Endpoints,
+        observer: &mut O,
+    ) {
+        if !self.grid.size().is_valid(coord) {
+            return;
+        }
+        if coord != goal {
+            let one_step_lookahead_cost = UNIT_COORDS
+                .iter()
+                .map(|&in_direction| {
+                    let step = Step {
+                        to_coord: coord + in_direction.to_coord(),
+                        in_direction,
+                    };
+                    self.cost_via(can_enter, step, goal)
+                })
+                .min()
+                .unwrap_or(INFINITY);
+            self.grid.get_checked_mut(coord).one_step_lookahead_cost = one_step_lookahead_cost;
+        }
+        let cell = self.grid.get_checked(coord);
+        // stale entries for this cell are left in the queue and skipped when popped
+        if cell.cost != cell.one_step_lookahead_cost {
+            self.push(coord, start, observer);
+        }
+    }
+
+    fn update_neighbours<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        coord: Coord,
+        endpoints: Endpoints,
+        observer: &mut O,
+    ) {
+        for &direction in &UNIT_COORDS {
+            self.update_cell(can_enter, coord + direction.to_coord(), endpoints, observer);
+        }
+    }
+
+    fn compute_shortest_path<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        endpoints: Endpoints,
+        observer: &mut O,
+    ) {
+        let start = endpoints.start;
+        while let Some(&Node { key, value: coord }) = self.priority_queue.peek() {
+            let start_cell = *self.grid.get_checked(start);
+            if key >= self.key(start, start) && start_cell.cost == start_cell.one_step_lookahead_cost {
+                break;
+            }
+            self.priority_queue.pop();
+            let cell = *self.grid.get_checked(coord);
+            if cell.cost == cell.one_step_lookahead_cost {
+                continue;
+            }
+            let current_key = self.key(coord, start);
+            match key.cmp(&current_key) {
+                Ordering::Less => self.push(coord, start, observer),
+                Ordering::Greater => (),
+                Ordering::Equal => {
+                    observer.expand(coord);
+                    if cell.cost > cell.one_step_lookahead_cost {
+                        self.grid.get_checked_mut(coord).cost = cell.one_step_lookahead_cost;
+                        observer.visit(coord);
+                    } else {
+                        self.grid.get_checked_mut(coord).cost = INFINITY;
+                        self.update_cell(can_enter, coord, endpoints, observer);
+                    }
+                    self.update_neighbours(can_enter, coord, endpoints, observer);
+                }
+            }
+        }
+    }
+
+    fn build_path<S: CanEnter>(&self, can_enter: &S, Endpoints { start, goal }: Endpoints, path: &mut Path) {
+        path.clear();
+        let mut coord = start;
+        while coord != goal {
+            let step = UNIT_COORDS
+                .iter()
+                .map(|&in_direction| Step {
+                    to_coord: coord + in_direction.to_coord(),
+                    in_direction,
+                })
+                .min_by_key(|&step| self.cost_via(can_enter, step, goal))
+                .unwrap();
+            path.push_back(step)
+                .expect("each step leaves the cell the last one entered");
+            coord = step.to_coord;
+        }
+    }
+
+    fn search_path<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        endpoints: Endpoints,
+        path: &mut Path,
+        observer: &mut O,
+    ) -> Result<(), SearchError> {
+        self.compute_shortest_path(can_enter, endpoints, observer);
+        if self.cost(endpoints.start) == INFINITY {
+            // the search runs backwards from the goal, so it reports the cell it reached closest
+            // to the start
+            let visited = self
+                .grid
+                .enumerate()
+                .filter(|(_, cell)| cell.cost != INFINITY)
+                .map(|(coord, _)| coord);
+            return Err(SearchError::goal_unreachable(endpoints.goal, visited, |coord| {
+                coord.manhattan_distance(endpoints.start)
+            }));
+        }
+        self.build_path(can_enter, endpoints, path);
+        observer.path_cost(self.cost(endpoints.start));
+        Ok(())
+    }
+
+    /// Starts a new search, discarding any state from previous searches
+    pub fn init_path<S: CanEnter>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        goal: Coord,
+        path: &mut Path,
+    ) -> Result<(), SearchError> {
+        self.init_path_with_observer(can_enter, start, goal, path, &mut ())
+    }
+
+    pub fn init_path_with_observer<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        goal: Coord,
+        path: &mut Path,
+        observer: &mut O,
+    ) -> Result<(), SearchError> {
+        SearchError::check_bounds(self.size(), &[start], &[goal])?;
+        let endpoints = Endpoints { start, goal };
+        for cell in self.grid.iter_mut() {
+            *cell = Cell::UNVISITED;
+        }
+        self.priority_queue.clear();
+        self.key_modifier = 0;
+        self.endpoints = Some(endpoints);
+        self.grid.get_checked_mut(goal).one_step_lookahead_cost = 0;
+        self.push(goal, start, observer);
+        self.search_path(can_enter, endpoints, path, observer)
+    }
+
+    /// Replans towards the goal of the last call to `init_path` from `start`, after the cells
+    /// in `changed` became enterable or unenterable
+    pub fn update_path<S: CanEnter>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        changed: &[Coord],
+        path: &mut Path,
+    ) -> Result<(), UpdatePathError> {
+        self.update_path_with_observer(can_enter, start, changed, path, &mut ())
+    }
+
+    /// Only the work done to repair the search is reported to the observer
+    pub fn update_path_with_observer<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        changed: &[Coord],
+        path: &mut Path,
+        observer: &mut O,
+    ) -> Result<(), UpdatePathError> {
+        let previous = self.endpoints.ok_or(UpdatePathError::NotInitialized)?;
+        SearchError::check_bounds(self.size(), &[start], &[]).map_err(UpdatePathError::Search)?;
+        let endpoints = Endpoints {
+            start,
+            goal: previous.goal,
+        };
+        self.key_modifier += previous.start.manhattan_distance(start);
+        self.endpoints = Some(endpoints);
+        for &coord in changed {
+            self.update_cell(can_enter, coord, endpoints, observer);
+            self.update_neighbours(can_enter, coord, endpoints, observer);
+        }
+        self.search_path(can_enter, endpoints, path, observer)
+            .map_err(UpdatePathError::Search)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_world::{random_grid, Search};
+    use crate::{expand, Context, SearchStats};
+    use rand::{Rng, SeedableRng};
+    use rand_isaac::Isaac64Rng;
+
+    fn assert_path(search: &Search, path: &Path, start: Coord, goal: Coord, len: usize) {
+        assert_eq!(path.len(), len);
+        let mut coord = start;
+        for node in path.iter() {
+            assert_eq!(node.to_coord - node.in_direction.coord(), coord);
+            assert!(node.to_coord == goal || search.can_enter(node.to_coord));
+            coord = node.to_coord;
+        }
+        assert_eq!(coord, goal);
+    }
+
+    #[test]
+    fn random_changes() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 100;
+        let num_turns = 20;
+        let size = Size::new(20, 20);
+        let mut ctx = Context::new(size);
+        let mut incremental_ctx = IncrementalContext::new(size);
+        let mut path = Path::default();
+        let mut expected_path = Path::default();
+        for _ in 0..num_tests {
+            let mut grid = random_grid(size, 4, &mut rng);
+            let mut start = Coord::random_within(size, &mut rng);
+            let goal = Coord::random_within(size, &mut rng);
+            let mut stats = SearchStats::default();
+            let mut result =
+                incremental_ctx.init_path_with_observer(&Search { grid: &grid }, start, goal, &mut path, &mut stats);
+            for _ in 0..num_turns {
+                let expected = ctx.point_to_point_search_path(
+                    expand::Sequential,
+                    &Search { grid: &grid },
+                    start,
+                    goal,
+                    &mut expected_path,
+                );
+                assert_eq!(result.is_ok(), expected.is_ok());
+                if result.is_ok() {
+                    assert_path(&Search { grid: &grid }, &path, start, goal, expected_path.len());
+                    assert_eq!(stats.path_cost, Some(expected_path.len() as u32));
+                } else {
+                    assert_eq!(stats.path_cost, None);
+                }
+                if let (Ok(()), Some(node)) = (result, path.pop()) {
+                    start = node.to_coord;
+                }
+                let changed = (0..rng.gen_range(0..5))
+                    .map(|_| Coord::random_within(size, &mut rng))
+                    .collect::<Vec<_>>();
+                for &coord in &changed {
+                    let cell = grid.get_checked_mut(coord);
+                    *cell = !*cell;
+                }
+                stats = SearchStats::default();
+                result = incremental_ctx
+                    .update_path_with_observer(&Search { grid: &grid }, start, &changed, &mut path, &mut stats)
+                    .map_err(|error| match error {
+                        UpdatePathError::Search(error) => error,
+                        UpdatePathError::NotInitialized => panic!("update_path failed after init_path"),
+                    });
+            }
+        }
+    }
+
+    #[test]
+    fn update_before_init() {
+        let grid = Grid::new_copy(Size::new(5, 5), true);
+        let mut incremental_ctx = IncrementalContext::new(grid.size());
+        let mut path = Path::default();
+        let start = Coord::new(0, 0);
+        assert_eq!(
+            incremental_ctx.update_path(&Search { grid: &grid }, start, &[], &mut path),
+            Err(UpdatePathError::NotInitialized)
+        );
+        incremental_ctx
+            .init_path(&Search { grid: &grid }, start, Coord::new(4, 4), &mut path)
+            .unwrap();
+        assert_eq!(
+            incremental_ctx.update_path(&Search { grid: &grid }, Coord::new(5, 0), &[], &mut path),
+            Err(UpdatePathError::Search(SearchError::StartOutOfBounds(Coord::new(5, 0))))
+        );
+    }
+}
@@ -1,84 +1,119 @@
+pub use adaptive::AdaptiveContext;
+pub use any_angle::AnyAngleContext;
+pub use cache::PathCache;
+pub use cooperative::{Agent, CooperativeContext};
 pub use coord_2d::{Coord, Size};
 pub use direction::CardinalDirection;
-pub use grid_search_cardinal_common::{can_enter::CanEnter, coord::UnitCoord, path::Path, step::Step};
+pub use facing::{FacingContext, TurnPenalty};
+use goal::{Goal, GoalSet};
+pub use grid_search_cardinal_common::storage::{Dense, Sparse, Storage};
+pub use grid_search_cardinal_common::{
+    can_enter::{CanEnter, CanEnterAt, Portal, Portals, StepCost, WorldVersion},
+    clearance::{AgentClearance, ClearanceMap, WithClearance},
+    coord::UnitCoord,
+    error::SearchError,
+    line_of_sight::{self, LineOfSight},
+    observer::{Observer, SearchStats},
+    path::{
+        CompactPath, CompactPathIter, Move, Path, PathError, PathNode, PortalPath, PortalPathNode, TimedPath,
+        TimedPathNode,
+    },
+    step::Step,
+    trace::{Trace, TraceEvent},
+    wrap::Wrap,
+};
 use grid_search_cardinal_common::{
     coord::UNIT_COORDS,
+    min_heap::MinHeapNode,
     seen_set::{SeenSet, Visit},
     step::Jump,
 };
+pub use hierarchical::HierarchicalContext;
+pub use incremental::{IncrementalContext, UpdatePathError};
+use limits::{Bounded, Limiter, OutOfBounds};
+pub use limits::{Limit, LimitedSearchError, Rect, SearchLimits};
+pub use preprocessed::PreprocessedGrid;
 #[cfg(feature = "serialize")]
 use serde::{Deserialize, Serialize};
-use std::cmp::Ordering;
-use std::collections::BinaryHeap;
-
-#[derive(Debug)]
-struct Node {
-    cost: u32,
-    cost_plus_heuristic: u32,
-    step: Step,
-}
-
-impl PartialEq for Node {
-    fn eq(&self, other: &Self) -> bool {
-        self.cost_plus_heuristic.eq(&other.cost_plus_heuristic)
-    }
-}
-
-impl PartialOrd for Node {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match other.cost_plus_heuristic.partial_cmp(&self.cost_plus_heuristic) {
-            Some(Ordering::Equal) => self.cost.partial_cmp(&other.cost),
-            other => other,
-        }
-    }
-}
-
-impl Eq for Node {}
-
-impl Ord for Node {
-    fn cmp(&self, other: &Self) -> Ordering {
-        match other.cost_plus_heuristic.cmp(&self.cost_plus_heuristic) {
-            Ordering::Equal => self.cost.cmp(&other.cost),
-            other => other,
-        }
-    }
-}
-
-trait Profiler {
-    fn expand(&mut self);
-}
-
-impl Profiler for () {
-    fn expand(&mut self) {}
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+pub use timed::TimedContext;
+pub use tour::{Tour, TourContext, TourKind, MAX_EXACT_TOUR_WAYPOINTS};
+
+mod adaptive;
+mod any_angle;
+mod cache;
+mod cooperative;
+mod facing;
+mod goal;
+mod hierarchical;
+mod incremental;
+mod limits;
+mod preprocessed;
+#[cfg(test)]
+mod test_world;
+mod timed;
+mod tour;
+
+// lowest cost plus heuristic first, breaking ties in favour of the node with the highest cost
+type Node = MinHeapNode<(u32, Reverse<u32>), Step>;
+
+// cells reached through a portal have no step leading to them, so searches which may take
+// portals queue coordinates
+type PortalNode = MinHeapNode<(u32, Reverse<u32>), Coord>;
+
+struct PortalGoal<'a, G> {
+    goal: &'a G,
+    // a lower bound on the cost of any path to the goal which takes a portal, which caps the
+    // heuristic so that it stays admissible
+    min_cost_via_portal: u32,
 }
 
+#[deprecated(note = "use `SearchStats`, which also counts the nodes expanded")]
 #[derive(Default, Debug)]
 pub struct Profile {
     expand: u64,
 }
 
-impl Profiler for Profile {
-    fn expand(&mut self) {
+#[allow(deprecated)]
+impl Observer for Profile {
+    fn expand(&mut self, _coord: Coord) {
         self.expand += 1;
     }
 }
 
-pub struct Context {
-    seen_set: SeenSet,
+// Jumps through an unbounded world may never reach an obstacle, so they stop after this many
+// cells and continue from a new jump point
+const MAX_UNBOUNDED_JUMP: u32 = 64;
+
+pub struct Context<B: Storage = Dense> {
+    seen_set: SeenSet<B>,
     priority_queue: BinaryHeap<Node>,
+    portal_queue: BinaryHeap<PortalNode>,
+    // only allocated once a bidirectional search runs
+    reverse_seen_set: Option<SeenSet<B>>,
+    queue: VecDeque<Step>,
+    reverse_queue: VecDeque<Step>,
+    reached_coord: Coord,
+    goal_set: GoalSet,
+    wrap: Wrap,
 }
 
 #[cfg(feature = "serialize")]
 impl Serialize for Context {
     fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
-        self.seen_set.size().serialize(s)
+        (self.seen_set.size(), self.wrap).serialize(s)
     }
 }
 
 #[cfg(feature = "serialize")]
 impl<'a> Deserialize<'a> for Context {
     fn deserialize<D: serde::Deserializer<'a>>(d: D) -> Result<Self, D::Error> {
-        Deserialize::deserialize(d).map(Self::new)
+        Deserialize::deserialize(d).map(|(size, wrap)| {
+            let mut context = Self::new(size);
+            context.set_wrap(wrap);
+            context
+        })
     }
 }
 
@@ -92,172 +127,476 @@ pub mod expand {
     #[derive(Debug, Clone, Copy)]
     pub struct Sequential;
 
+    #[derive(Debug, Clone, Copy)]
+    pub struct Bidirectional;
+
     impl Expand for JumpPoint {}
     impl Expand for Sequential {}
+    impl Expand for Bidirectional {}
 }
 
+/// The start and goal joined by the path found by a set-to-set search
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub struct NoPath;
+pub struct Endpoints {
+    pub start: Coord,
+    pub goal: Coord,
+}
+
+impl Endpoints {
+    fn of_path(path: &Path, reached_coord: Coord) -> Self {
+        match (path.start(), path.last()) {
+            (Some(start), Some(last)) => Self {
+                start,
+                goal: last.to_coord,
+            },
+            _ => Self {
+                start: reached_coord,
+                goal: reached_coord,
+            },
+        }
+    }
+}
 
 mod private_expand {
-    use super::{expand, CanEnter, Context, Coord, Step};
+    use super::{
+        expand, goal::Goal, CanEnter, CardinalDirection, CompactPath, Context, Coord, Observer, Path, Step, Storage,
+    };
     pub struct Stop;
+
+    // the search core failed, before the cause is worked out for `SearchError`
+    #[derive(Debug)]
+    pub struct NoPath;
+
+    // Observes the search, and is called before each node is expanded so it can end the search
+    // early. `min_path_cost` is a lower bound on the cost of any path not yet found.
+    pub trait Profiler: Observer {
+        fn before_expand(&mut self, _coord: Coord, _cost: u32, _min_path_cost: u32) -> Option<Stop> {
+            None
+        }
+        // expanding a node in the search tree rooted at the goal
+        fn before_expand_reverse(&mut self, _min_path_cost: u32) -> Option<Stop> {
+            None
+        }
+    }
+
+    impl Profiler for () {}
+
+    impl<O: Observer> Profiler for &mut O {}
+
     pub trait PrivateExpand {
-        fn consider<P: CanEnter>(
-            context: &mut Context,
+        fn search<B: Storage, S: CanEnter, G: Goal, P: Profiler>(
+            context: &mut Context<B>,
+            point_to_point_search: &S,
+            starts: &[Coord],
+            goal: &G,
+            profiler: &mut P,
+        ) -> Result<(), NoPath>;
+        fn build_path<B: Storage>(context: &Context<B>, path: &mut Path) {
+            context.seen_set.build_path_to(context.reached_coord, path);
+        }
+        fn build_compact_path<B: Storage>(context: &Context<B>, path: &mut CompactPath) {
+            context.seen_set.build_compact_path_to(context.reached_coord, path);
+        }
+        fn first_direction<B: Storage>(context: &Context<B>) -> Option<CardinalDirection> {
+            context.seen_set.first_direction_towards(context.reached_coord)
+        }
+        fn path_cost<B: Storage>(context: &Context<B>) -> u32 {
+            context.seen_set.cost(context.reached_coord).unwrap_or(0)
+        }
+    }
+
+    pub trait PrivateUnidirectionalExpand {
+        fn consider<B: Storage, P: CanEnter, G: Goal, R: Profiler>(
+            context: &mut Context<B>,
             point_to_point_search: &P,
             step: Step,
             cost: u32,
-            goal: Coord,
+            goal: &G,
+            profiler: &mut R,
         ) -> Option<Stop>;
-        fn expand<P: CanEnter>(
-            context: &mut Context,
+        fn expand<B: Storage, P: CanEnter, G: Goal, R: Profiler>(
+            context: &mut Context<B>,
             point_to_point_search: &P,
             step: Step,
             cost: u32,
-            goal: Coord,
+            goal: &G,
+            profiler: &mut R,
         ) -> Option<Stop>;
     }
 
-    impl PrivateExpand for expand::JumpPoint {
-        fn consider<P: CanEnter>(
-            context: &mut Context,
+    impl<E: PrivateUnidirectionalExpand> PrivateExpand for E {
+        fn search<B: Storage, S: CanEnter, G: Goal, P: Profiler>(
+            context: &mut Context<B>,
+            point_to_point_search: &S,
+            starts: &[Coord],
+            goal: &G,
+            profiler: &mut P,
+        ) -> Result<(), NoPath> {
+            context.point_to_point_search_core::<_, Self, _, _>(point_to_point_search, starts, goal, profiler)
+        }
+    }
+
+    impl PrivateUnidirectionalExpand for expand::JumpPoint {
+        fn consider<B: Storage, P: CanEnter, G: Goal, R: Profiler>(
+            context: &mut Context<B>,
             point_to_point_search: &P,
             step: Step,
             cost: u32,
-            goal: Coord,
+            goal: &G,
+            profiler: &mut R,
         ) -> Option<Stop> {
-            context.consider_jps(point_to_point_search, step, cost, goal)
+            context.consider_jps(point_to_point_search, step, cost, goal, profiler)
         }
 
-        fn expand<P: CanEnter>(
-            context: &mut Context,
+        fn expand<B: Storage, P: CanEnter, G: Goal, R: Profiler>(
+            context: &mut Context<B>,
             point_to_point_search: &P,
             step: Step,
             cost: u32,
-            goal: Coord,
+            goal: &G,
+            profiler: &mut R,
         ) -> Option<Stop> {
-            if let Some(Stop) = Self::consider(context, point_to_point_search, step.forward(), cost, goal) {
+            if let Some(Stop) = Self::consider(context, point_to_point_search, step.forward(), cost, goal, profiler) {
                 return Some(Stop);
             }
-            if let Some(Stop) = Self::consider(context, point_to_point_search, step.left(), cost, goal) {
+            if let Some(Stop) = Self::consider(context, point_to_point_search, step.left(), cost, goal, profiler) {
                 return Some(Stop);
             }
-            if let Some(Stop) = Self::consider(context, point_to_point_search, step.right(), cost, goal) {
+            if let Some(Stop) = Self::consider(context, point_to_point_search, step.right(), cost, goal, profiler) {
                 return Some(Stop);
             }
             None
         }
     }
 
-    impl PrivateExpand for expand::Sequential {
-        fn consider<P: CanEnter>(
-            context: &mut Context,
+    impl PrivateUnidirectionalExpand for expand::Sequential {
+        fn consider<B: Storage, P: CanEnter, G: Goal, R: Profiler>(
+            context: &mut Context<B>,
             point_to_point_search: &P,
             step: Step,
             cost: u32,
-            goal: Coord,
+            goal: &G,
+            profiler: &mut R,
         ) -> Option<Stop> {
-            context.consider(point_to_point_search, step, cost, goal)
+            context.consider(point_to_point_search, step, cost, goal, profiler)
         }
 
-        fn expand<P: CanEnter>(
-            context: &mut Context,
+        fn expand<B: Storage, P: CanEnter, G: Goal, R: Profiler>(
+            context: &mut Context<B>,
             point_to_point_search: &P,
             step: Step,
             cost: u32,
-            goal: Coord,
+            goal: &G,
+            profiler: &mut R,
         ) -> Option<Stop> {
-            if let Some(Stop) = Self::consider(context, point_to_point_search, step.forward(), cost, goal) {
+            if let Some(Stop) = Self::consider(context, point_to_point_search, step.forward(), cost, goal, profiler) {
                 return Some(Stop);
             }
-            if let Some(Stop) = Self::consider(context, point_to_point_search, step.left(), cost, goal) {
+            if let Some(Stop) = Self::consider(context, point_to_point_search, step.left(), cost, goal, profiler) {
                 return Some(Stop);
             }
-            if let Some(Stop) = Self::consider(context, point_to_point_search, step.right(), cost, goal) {
+            if let Some(Stop) = Self::consider(context, point_to_point_search, step.right(), cost, goal, profiler) {
                 return Some(Stop);
             }
             None
         }
     }
+
+    impl PrivateExpand for expand::Bidirectional {
+        fn search<B: Storage, S: CanEnter, G: Goal, P: Profiler>(
+            context: &mut Context<B>,
+            point_to_point_search: &S,
+            starts: &[Coord],
+            goal: &G,
+            profiler: &mut P,
+        ) -> Result<(), NoPath> {
+            context.bidirectional_search_core(point_to_point_search, starts, goal, profiler)
+        }
+
+        // the search stops where the two frontiers meet
+        fn build_path<B: Storage>(context: &Context<B>, path: &mut Path) {
+            context.seen_set.build_path_to(context.reached_coord, path);
+            context
+                .reverse_seen_set()
+                .extend_path_in_reverse_from(context.reached_coord, path);
+        }
+
+        fn build_compact_path<B: Storage>(context: &Context<B>, path: &mut CompactPath) {
+            context.seen_set.build_compact_path_to(context.reached_coord, path);
+            context
+                .reverse_seen_set()
+                .extend_compact_path_in_reverse_from(context.reached_coord, path);
+        }
+
+        fn first_direction<B: Storage>(context: &Context<B>) -> Option<CardinalDirection> {
+            context
+                .seen_set
+                .first_direction_towards(context.reached_coord)
+                .or_else(|| {
+                    context
+                        .reverse_seen_set()
+                        .first_direction_in_reverse_from(context.reached_coord)
+                })
+        }
+
+        fn path_cost<B: Storage>(context: &Context<B>) -> u32 {
+            let cost = context.seen_set.cost(context.reached_coord).unwrap_or(0);
+            let reverse_cost = context.reverse_seen_set().cost(context.reached_coord).unwrap_or(0);
+            cost + reverse_cost
+        }
+    }
 }
 
 use expand::Expand;
-use private_expand::Stop;
+use private_expand::{NoPath, PrivateUnidirectionalExpand, Profiler, Stop};
 
-impl Context {
+impl Context<Dense> {
     pub fn new(size: Size) -> Self {
+        Self::with_seen_set(SeenSet::new(size))
+    }
+
+    pub fn size(&self) -> Size {
+        self.seen_set.size()
+    }
+
+    /// Makes searches treat the grid as wrapping around along some axes. Paths may then cross
+    /// the seams, and every coordinate passed to the `CanEnter` or in a `Path` is normalized to
+    /// lie within the grid. Searches using a `PreprocessedGrid` ignore this.
+    pub fn set_wrap(&mut self, wrap: Wrap) {
+        self.wrap = wrap;
+        self.seen_set.set_wrap(wrap);
+        if let Some(reverse_seen_set) = self.reverse_seen_set.as_mut() {
+            reverse_seen_set.set_wrap(wrap);
+        }
+    }
+}
+
+impl Context<Sparse> {
+    /// A context for searching worlds with no bounds, where coordinates can be any `i32`. Only
+    /// the cells reached by a search are stored. A search for an unreachable goal only
+    /// terminates if finitely many cells are reachable from the start, so use a limited search
+    /// when this isn't known.
+    pub fn new_unbounded() -> Self {
+        Self::with_seen_set(SeenSet::new_unbounded())
+    }
+}
+
+impl<B: Storage> Context<B> {
+    fn with_seen_set(seen_set: SeenSet<B>) -> Self {
         Self {
-            seen_set: SeenSet::new(size),
+            seen_set,
             priority_queue: BinaryHeap::new(),
+            portal_queue: BinaryHeap::new(),
+            reverse_seen_set: None,
+            queue: VecDeque::new(),
+            reverse_queue: VecDeque::new(),
+            reached_coord: Coord::new(0, 0),
+            goal_set: GoalSet::default(),
+            wrap: Wrap::None,
+        }
+    }
+
+    fn reverse_seen_set(&self) -> &SeenSet<B> {
+        self.reverse_seen_set
+            .as_ref()
+            .expect("reverse seen set is allocated by bidirectional search")
+    }
+
+    fn reverse_seen_set_mut(&mut self) -> &mut SeenSet<B> {
+        let seen_set = &self.seen_set;
+        self.reverse_seen_set.get_or_insert_with(|| seen_set.new_like())
+    }
+
+    /// The size of the grid, or `None` if the context is unbounded
+    pub fn bounds(&self) -> Option<Size> {
+        self.seen_set.bounds()
+    }
+
+    pub fn wrap(&self) -> Wrap {
+        self.wrap
+    }
+
+    fn normalize(&self, coord: Coord) -> Coord {
+        match self.bounds() {
+            Some(size) => self.wrap.normalize(coord, size),
+            None => coord,
         }
     }
 
-    fn consider<P: CanEnter>(&mut self, point_to_point_search: &P, step: Step, cost: u32, goal: Coord) -> Option<Stop> {
+    fn distance(&self, a: Coord, b: Coord) -> u32 {
+        match self.bounds() {
+            Some(size) => self.wrap.manhattan_distance(a, b, size),
+            None => a.manhattan_distance(b),
+        }
+    }
+
+    fn period(&self, in_direction: UnitCoord) -> Option<u32> {
+        self.bounds()
+            .and_then(|size| self.wrap.period(in_direction.to_coord(), size))
+    }
+
+    fn wrap_step(&self, step: Step) -> Step {
+        Step {
+            to_coord: self.normalize(step.to_coord),
+            in_direction: step.in_direction,
+        }
+    }
+
+    // A jump which reaches the goal follows a shortest path to it on a flat grid, but may have
+    // gone the long way around on a wrapping grid, so there the goal is queued like any other cell
+    fn stop_when_visited<G: Goal>(&self, goal: &G) -> bool {
+        goal.stop_when_visited() && self.wrap == Wrap::None
+    }
+
+    // the nearest goal may lie across a seam
+    fn heuristic<G: Goal>(&self, goal: &G, coord: Coord) -> u32 {
+        if self.wrap == Wrap::None {
+            goal.heuristic(coord)
+        } else {
+            goal.coords()
+                .iter()
+                .map(|&goal_coord| self.distance(coord, goal_coord))
+                .min()
+                .unwrap_or(0)
+        }
+    }
+
+    fn push<R: Profiler>(&mut self, node: Node, profiler: &mut R) {
+        profiler.push(node.value.to_coord);
+        self.priority_queue.push(node);
+        profiler.queue_len(self.priority_queue.len());
+    }
+
+    fn consider<P: CanEnter, G: Goal, R: Profiler>(
+        &mut self,
+        point_to_point_search: &P,
+        step: Step,
+        cost: u32,
+        goal: &G,
+        profiler: &mut R,
+    ) -> Option<Stop> {
+        let step = self.wrap_step(step);
         let cost = cost + 1;
         if let Some(Visit) = self.seen_set.try_visit_step(step, cost) {
-            if step.to_coord == goal {
-                return Some(Stop);
+            profiler.visit(step.to_coord);
+            if goal.is_goal(step.to_coord) {
+                if goal.stop_when_visited() {
+                    self.reached_coord = step.to_coord;
+                    return Some(Stop);
+                }
+                self.push_goal(step, cost, profiler);
+                return None;
             }
             if point_to_point_search.can_step(step) {
-                let heuristic = step.to_coord.manhattan_distance(goal);
+                let heuristic = self.heuristic(goal, step.to_coord);
                 let cost_plus_heuristic = cost + heuristic;
                 let node = Node {
-                    cost,
-                    cost_plus_heuristic,
-                    step,
+                    key: (cost_plus_heuristic, Reverse(cost)),
+                    value: step,
                 };
-                self.priority_queue.push(node);
+                self.push(node, profiler);
+            } else {
+                self.seen_set.mark_blocked(step.to_coord);
             }
         }
         None
     }
 
-    fn consider_jps<P: CanEnter>(
+    fn consider_jps<P: CanEnter, G: Goal, R: Profiler>(
         &mut self,
         point_to_point_search: &P,
         mut step: Step,
         cost: u32,
-        goal: Coord,
+        goal: &G,
+        profiler: &mut R,
     ) -> Option<Stop> {
+        step = self.wrap_step(step);
+        let from_coord = self.normalize(step.from_coord());
+        // a jump which wraps all the way around has scanned every cell in its row or column
+        let period = self.period(step.in_direction);
+        let side_period = self.period(step.in_direction.left90());
+        let max_jump = match self.bounds() {
+            Some(_) => u32::MAX,
+            None => MAX_UNBOUNDED_JUMP,
+        };
         let mut jump_cost = 1;
         'outer: loop {
-            if step.to_coord == goal {
+            if period.is_some_and(|period| jump_cost > period) {
+                return None;
+            }
+            profiler.rescan(step.to_coord);
+            if goal.is_goal(step.to_coord) {
                 let jump = Jump {
                     in_direction: step.in_direction.scale(jump_cost),
-                    to_coord: goal,
+                    to_coord: step.to_coord,
                 };
-                self.seen_set.try_visit_jump(jump, cost + jump_cost);
-                return Some(Stop);
+                let visit = self.seen_set.try_visit_jump(jump, cost + jump_cost);
+                if visit.is_some() {
+                    profiler.jump(from_coord, from_coord + step.in_direction.to_coord() * jump_cost as i32);
+                    profiler.visit(step.to_coord);
+                }
+                if self.stop_when_visited(goal) {
+                    self.reached_coord = step.to_coord;
+                    return Some(Stop);
+                }
+                if let Some(Visit) = visit {
+                    self.push_goal(step, cost + jump_cost, profiler);
+                }
+                return None;
             }
             if !point_to_point_search.can_step(step) {
                 return None;
             }
-            if has_forced_neighbour(point_to_point_search, step, goal) {
+            if jump_cost >= max_jump
+                || has_forced_neighbour(point_to_point_search, step, goal, |coord| self.normalize(coord))
+            {
                 break;
             }
             // explore to the left only
-            let mut side_step = step.left();
+            let mut side_step = self.wrap_step(step.left());
             let mut side_jump_cost = 1;
             'inner: loop {
-                if side_step.to_coord == goal {
+                if side_period.is_some_and(|side_period| side_jump_cost > side_period) {
+                    break 'inner;
+                }
+                profiler.rescan(side_step.to_coord);
+                if goal.is_goal(side_step.to_coord) {
+                    if !self.stop_when_visited(goal) {
+                        // treat the current cell as a jump point so the goal is queued when it's
+                        // expanded
+                        break 'outer;
+                    }
                     let jump_to_intermediate = Jump {
                         in_direction: step.in_direction.scale(jump_cost),
                         to_coord: step.to_coord,
                     };
                     let jump_to_goal = Jump {
                         in_direction: side_step.in_direction.scale(side_jump_cost),
-                        to_coord: goal,
+                        to_coord: side_step.to_coord,
                     };
-                    self.seen_set.try_visit_jump(jump_to_intermediate, cost + jump_cost);
-                    self.seen_set
-                        .try_visit_jump(jump_to_goal, cost + jump_cost + side_jump_cost);
+                    if let Some(Visit) = self.seen_set.try_visit_jump(jump_to_intermediate, cost + jump_cost) {
+                        profiler.jump(from_coord, from_coord + step.in_direction.to_coord() * jump_cost as i32);
+                        profiler.visit(step.to_coord);
+                    }
+                    if let Some(Visit) = self
+                        .seen_set
+                        .try_visit_jump(jump_to_goal, cost + jump_cost + side_jump_cost)
+                    {
+                        profiler.jump(
+                            step.to_coord,
+                            step.to_coord + side_step.in_direction.to_coord() * side_jump_cost as i32,
+                        );
+                        profiler.visit(side_step.to_coord);
+                    }
+                    self.reached_coord = side_step.to_coord;
                     return Some(Stop);
                 }
                 if !point_to_point_search.can_step(side_step) {
                     break 'inner;
                 }
-                if has_forced_neighbour(point_to_point_search, side_step, goal) {
+                if side_jump_cost >= max_jump
+                    || has_forced_neighbour(point_to_point_search, side_step, goal, |coord| self.normalize(coord))
+                {
                     let jump_to_side_jump_point = Jump {
                         in_direction: side_step.in_direction.scale(side_jump_cost),
                         to_coord: side_step.to_coord,
@@ -266,47 +605,88 @@ impl Context {
                         .seen_set
                         .try_visit_jump(jump_to_side_jump_point, cost + jump_cost + side_jump_cost)
                     {
-                        let heuristic = side_step.to_coord.manhattan_distance(goal);
+                        profiler.jump(from_coord, from_coord + step.in_direction.to_coord() * jump_cost as i32);
+                        profiler.jump(
+                            step.to_coord,
+                            step.to_coord + side_step.in_direction.to_coord() * side_jump_cost as i32,
+                        );
+                        profiler.visit(side_step.to_coord);
+                        let heuristic = self.heuristic(goal, side_step.to_coord);
                         let cost = cost + jump_cost + side_jump_cost;
                         let node = Node {
-                            cost,
-                            cost_plus_heuristic: cost + heuristic,
-                            step: side_step,
+                            key: (cost + heuristic, Reverse(cost)),
+                            value: side_step,
                         };
-                        self.priority_queue.push(node);
+                        self.push(node, profiler);
                     }
                     break 'outer;
                 }
-                side_step = side_step.forward();
+                side_step = self.wrap_step(side_step.forward());
                 side_jump_cost += 1;
             }
-            step = step.forward();
+            step = self.wrap_step(step.forward());
             jump_cost += 1;
         }
         let jump = step.scale_back(jump_cost);
         let cost = cost + jump_cost;
         if let Some(Visit) = self.seen_set.try_visit_jump(jump, cost) {
-            let heuristic = step.to_coord.manhattan_distance(goal);
+            profiler.jump(from_coord, from_coord + step.in_direction.to_coord() * jump_cost as i32);
+            profiler.visit(step.to_coord);
+            let heuristic = self.heuristic(goal, step.to_coord);
             let node = Node {
-                cost,
-                cost_plus_heuristic: cost + heuristic,
-                step,
+                key: (cost + heuristic, Reverse(cost)),
+                value: step,
             };
-            self.priority_queue.push(node);
+            self.push(node, profiler);
         }
         None
     }
 
-    fn point_to_point_search_core<S, E, P>(
+    fn push_goal<R: Profiler>(&mut self, step: Step, cost: u32, profiler: &mut R) {
+        let node = Node {
+            key: (cost, Reverse(cost)),
+            value: step,
+        };
+        self.push(node, profiler);
+    }
+
+    fn consider_weighted<S: StepCost, R: Profiler>(
         &mut self,
-        point_to_point_search: &S,
+        step_cost: &S,
+        step: Step,
+        cost: u32,
+        goal: Coord,
+        profiler: &mut R,
+    ) {
+        let step = self.wrap_step(step);
+        if step.to_coord != goal && !step_cost.can_step(step) {
+            return;
+        }
+        let cost = cost + step_cost.step_cost(step);
+        if let Some(Visit) = self.seen_set.try_visit_step(step, cost) {
+            profiler.visit(step.to_coord);
+            let distance = self.distance(step.to_coord, goal);
+            let heuristic = distance * step_cost.min_step_cost();
+            let node = Node {
+                key: (cost + heuristic, Reverse(cost)),
+                value: step,
+            };
+            self.push(node, profiler);
+        }
+    }
+
+    // Jump points assume that every step costs the same, so weighted searches always expand
+    // sequentially. Unlike the uniform-cost search, the goal is only known to be reached by a
+    // shortest path once it is popped from the queue.
+    fn weighted_point_to_point_search_core<S, P>(
+        &mut self,
+        step_cost: &S,
         start: Coord,
         goal: Coord,
         profiler: &mut P,
     ) -> Result<(), NoPath>
     where
-        S: CanEnter,
-        E: Expand,
+        S: StepCost,
         P: Profiler,
     {
         self.seen_set.init(start);
@@ -317,495 +697,2451 @@ impl Context {
         for &in_direction in &UNIT_COORDS {
             let to_coord = start + in_direction.to_coord();
             let step = Step { to_coord, in_direction };
-            if let Some(Stop) = E::consider(self, point_to_point_search, step, 1, goal) {
-                return Ok(());
-            }
+            self.consider_weighted(step_cost, step, 0, goal, profiler);
         }
-        while let Some(Node { cost, step, .. }) = self.priority_queue.pop() {
-            profiler.expand();
-            if let Some(Stop) = E::expand(self, point_to_point_search, step, cost, goal) {
+        while let Some(Node {
+            key: (cost_plus_heuristic, Reverse(cost)),
+            value: step,
+        }) = self.priority_queue.pop()
+        {
+            if step.to_coord == goal {
                 return Ok(());
             }
+            if self.seen_set.cost(step.to_coord) != Some(cost) {
+                // a cheaper route to this cell was found after this node was queued
+                continue;
+            }
+            if let Some(Stop) = profiler.before_expand(step.to_coord, cost, cost_plus_heuristic) {
+                return Err(NoPath);
+            }
+            profiler.expand(step.to_coord);
+            self.consider_weighted(step_cost, step.forward(), cost, goal, profiler);
+            self.consider_weighted(step_cost, step.left(), cost, goal, profiler);
+            self.consider_weighted(step_cost, step.right(), cost, goal, profiler);
         }
         Err(NoPath)
     }
 
-    pub fn point_to_point_search_path<S, E>(
+    fn consider_forward<S: CanEnter, G: Goal, R: Profiler>(
         &mut self,
-        expand: E,
         point_to_point_search: &S,
-        start: Coord,
-        goal: Coord,
-        path: &mut Path,
-    ) -> Result<(), NoPath>
-    where
-        S: CanEnter,
-        E: Expand,
-    {
-        let _ = expand;
-        self.point_to_point_search_core::<_, E, _>(point_to_point_search, start, goal, &mut ())?;
-        self.seen_set.build_path_to(goal, path);
-        Ok(())
+        step: Step,
+        cost: u32,
+        goal: &G,
+        profiler: &mut R,
+    ) -> Option<Stop> {
+        let step = self.wrap_step(step);
+        if !goal.is_goal(step.to_coord) && !point_to_point_search.can_step(step) {
+            return None;
+        }
+        if let Some(Visit) = self.seen_set.try_visit_step(step, cost) {
+            profiler.visit(step.to_coord);
+            if self.reverse_seen_set().cost(step.to_coord).is_some() {
+                self.reached_coord = step.to_coord;
+                return Some(Stop);
+            }
+            profiler.push(step.to_coord);
+            self.queue.push_back(step);
+            profiler.queue_len(self.queue.len() + self.reverse_queue.len());
+        }
+        None
     }
 
-    pub fn point_to_point_search_first<S, E>(
+    // `step` moves away from the goal, so the step that would be taken along the path is its
+    // opposite
+    fn consider_reverse<S: CanEnter, G: Goal, R: Profiler>(
         &mut self,
-        expand: E,
         point_to_point_search: &S,
-        start: Coord,
-        goal: Coord,
-    ) -> Result<Option<CardinalDirection>, NoPath>
-    where
-        S: CanEnter,
-        E: Expand,
-    {
-        let _ = expand;
-        self.point_to_point_search_core::<_, E, _>(point_to_point_search, start, goal, &mut ())?;
-        Ok(self.seen_set.first_direction_towards(goal))
+        step: Step,
+        cost: u32,
+        goal: &G,
+        profiler: &mut R,
+    ) -> Option<Stop> {
+        let step = self.wrap_step(step);
+        let path_step = Step {
+            to_coord: self.normalize(step.from_coord()),
+            in_direction: step.in_direction.opposite(),
+        };
+        if !goal.is_goal(path_step.to_coord) && !point_to_point_search.can_step(path_step) {
+            return None;
+        }
+        if let Some(Visit) = self.reverse_seen_set_mut().try_visit_step(step, cost) {
+            profiler.visit(step.to_coord);
+            if self.seen_set.cost(step.to_coord).is_some() {
+                self.reached_coord = step.to_coord;
+                return Some(Stop);
+            }
+            profiler.push(step.to_coord);
+            self.reverse_queue.push_back(step);
+            profiler.queue_len(self.queue.len() + self.reverse_queue.len());
+        }
+        None
     }
 
-    pub fn point_to_point_search_profile<S, E>(
+    // Breadth-first search from both ends, expanding a whole layer at a time from whichever end
+    // has the smaller frontier. The forward seen set only holds cells that can be reached from the
+    // start. The reverse seen set holds every cell with a step onto a cell that can reach the goal,
+    // including cells that can't be entered, but those are never reached by the forward search.
+    // So the first cell found in both seen sets lies on a shortest path.
+    fn bidirectional_search_core<S, G, P>(
         &mut self,
-        expand: E,
         point_to_point_search: &S,
-        start: Coord,
-        goal: Coord,
-    ) -> (Profile, Result<(), NoPath>)
+        starts: &[Coord],
+        goal: &G,
+        profiler: &mut P,
+    ) -> Result<(), NoPath>
     where
         S: CanEnter,
-        E: Expand,
+        G: Goal,
+        P: Profiler,
     {
-        let _ = expand;
-        let mut profile = Profile::default();
-        let result = self.point_to_point_search_core::<_, E, _>(point_to_point_search, start, goal, &mut profile);
-        (profile, result)
+        self.init_starts(starts);
+        let reverse_seen_set = self.reverse_seen_set_mut();
+        reverse_seen_set.clear();
+        for &coord in goal.coords() {
+            reverse_seen_set.add_start(coord);
+        }
+        self.queue.clear();
+        self.reverse_queue.clear();
+        if let Some(&start) = starts.iter().find(|&&start| goal.is_goal(start)) {
+            self.reached_coord = start;
+            return Ok(());
+        }
+        for &start in starts {
+            for &in_direction in &UNIT_COORDS {
+                let to_coord = start + in_direction.to_coord();
+                let step = Step { to_coord, in_direction };
+                if let Some(Stop) = self.consider_forward(point_to_point_search, step, 1, goal, profiler) {
+                    return Ok(());
+                }
+            }
+        }
+        for &goal_coord in goal.coords() {
+            for &in_direction in &UNIT_COORDS {
+                let to_coord = goal_coord + in_direction.to_coord();
+                let step = Step { to_coord, in_direction };
+                if let Some(Stop) = self.consider_reverse(point_to_point_search, step, 1, goal, profiler) {
+                    return Ok(());
+                }
+            }
+        }
+        let mut cost = 1;
+        let mut reverse_cost = 1;
+        while !self.queue.is_empty() && !self.reverse_queue.is_empty() {
+            if self.queue.len() <= self.reverse_queue.len() {
+                cost += 1;
+                for _ in 0..self.queue.len() {
+                    let step = self.queue.pop_front().unwrap();
+                    if let Some(Stop) = profiler.before_expand(step.to_coord, cost - 1, cost + reverse_cost) {
+                        return Err(NoPath);
+                    }
+                    profiler.expand(step.to_coord);
+                    for step in [step.forward(), step.left(), step.right()] {
+                        if let Some(Stop) = self.consider_forward(point_to_point_search, step, cost, goal, profiler) {
+                            return Ok(());
+                        }
+                    }
+                }
+            } else {
+                reverse_cost += 1;
+                for _ in 0..self.reverse_queue.len() {
+                    let step = self.reverse_queue.pop_front().unwrap();
+                    if let Some(Stop) = profiler.before_expand_reverse(cost + reverse_cost) {
+                        return Err(NoPath);
+                    }
+                    profiler.expand(step.to_coord);
+                    for step in [step.forward(), step.left(), step.right()] {
+                        if let Some(Stop) =
+                            self.consider_reverse(point_to_point_search, step, reverse_cost, goal, profiler)
+                        {
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        }
+        Err(NoPath)
     }
-}
 
-fn step_from(from_coord: Coord, in_direction: UnitCoord) -> Step {
-    Step {
-        to_coord: from_coord + in_direction.to_coord(),
-        in_direction,
+    fn portal_goal<'a, P: Portals, G: Goal>(&self, portals: &P, goal: &'a G) -> PortalGoal<'a, G> {
+        let mut min_cost_via_portal = u32::MAX;
+        portals.for_each_portal_in_world(|_, portal| {
+            let heuristic = self.heuristic(goal, self.normalize(portal.to_coord));
+            min_cost_via_portal = min_cost_via_portal.min(portal.cost.saturating_add(heuristic));
+        });
+        PortalGoal {
+            goal,
+            min_cost_via_portal,
+        }
     }
-}
 
-fn has_forced_neighbour<P: CanEnter>(point_to_point_search: &P, step: Step, goal: Coord) -> bool {
-    (!point_to_point_search.can_enter(step.to_coord + step.in_direction.left135())
-        && (point_to_point_search.can_step(step_from(step.to_coord, step.in_direction.left90()))
-            || step.to_coord + step.in_direction.left90().to_coord() == goal))
-        || (!point_to_point_search.can_enter(step.to_coord + step.in_direction.right135())
-            && (point_to_point_search.can_step(step_from(step.to_coord, step.in_direction.right90()))
-                || step.to_coord + step.in_direction.right90().to_coord() == goal))
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
-    use grid_2d::Grid;
-    use rand::{Rng, SeedableRng};
-    use rand_isaac::Isaac64Rng;
+    fn portal_heuristic<G: Goal>(&self, goal: &PortalGoal<G>, coord: Coord) -> u32 {
+        self.heuristic(goal.goal, coord).min(goal.min_cost_via_portal)
+    }
 
-    #[derive(Clone)]
-    enum Cell {
-        Solid,
-        Traversable,
+    fn push_portal_node<G: Goal, R: Profiler>(
+        &mut self,
+        coord: Coord,
+        cost: u32,
+        goal: &PortalGoal<G>,
+        profiler: &mut R,
+    ) {
+        profiler.visit(coord);
+        let heuristic = self.portal_heuristic(goal, coord);
+        profiler.push(coord);
+        self.portal_queue.push(PortalNode {
+            key: (cost.saturating_add(heuristic), Reverse(cost)),
+            value: coord,
+        });
+        profiler.queue_len(self.portal_queue.len());
     }
 
-    struct Test {
-        grid: Grid<Cell>,
-        start: Coord,
-        goal: Coord,
+    fn consider_step_with_portals<P: Portals, G: Goal, R: Profiler>(
+        &mut self,
+        portals: &P,
+        step: Step,
+        cost: u32,
+        goal: &PortalGoal<G>,
+        profiler: &mut R,
+    ) {
+        let step = self.wrap_step(step);
+        if !goal.goal.is_goal(step.to_coord) && !portals.can_step(step) {
+            return;
+        }
+        if let Some(Visit) = self.seen_set.try_visit_step(step, cost) {
+            self.push_portal_node(step.to_coord, cost, goal, profiler);
+        }
     }
 
-    fn print_test(test: &Test) {
-        for row in test.grid.rows() {
-            print!("\"");
-            for cell in row {
-                let ch = match cell {
-                    Cell::Solid => '#',
-                    Cell::Traversable => '.',
-                };
-                print!("{}", ch);
-            }
-            println!("\",");
+    fn consider_portal<P: Portals, G: Goal, R: Profiler>(
+        &mut self,
+        portals: &P,
+        from_coord: Coord,
+        portal: Portal,
+        cost: u32,
+        goal: &PortalGoal<G>,
+        profiler: &mut R,
+    ) {
+        let to_coord = self.normalize(portal.to_coord);
+        if !goal.goal.is_goal(to_coord) && !portals.can_enter(to_coord) {
+            return;
+        }
+        let cost = cost.saturating_add(portal.cost);
+        if let Some(Visit) = self.seen_set.try_visit_portal(from_coord, to_coord, cost) {
+            self.push_portal_node(to_coord, cost, goal, profiler);
         }
     }
 
-    fn str_slice_to_test(str_slice: &[&str]) -> Test {
-        let width = str_slice[0].len() as u32;
-        let height = str_slice.len() as u32;
-        let size = Size::new(width, height);
-        let mut grid = Grid::new_clone(size, Cell::Solid);
-        let mut start = None;
-        let mut goal = None;
-        for (y, line) in str_slice.iter().enumerate() {
-            for (x, ch) in line.chars().enumerate() {
-                let coord = Coord::new(x as i32, y as i32);
-                let cell = match ch {
-                    '.' => Cell::Traversable,
-                    '@' => {
-                        start = Some(coord);
-                        Cell::Traversable
-                    }
-                    '*' => {
-                        goal = Some(coord);
-                        Cell::Traversable
-                    }
-                    '#' => Cell::Solid,
-                    _ => Cell::Traversable,
+    // Portals break the assumptions jump point search relies on, so cells are expanded one at a
+    // time. A portal may lead to a cheaper route to a cell after it was first visited, so the
+    // goal is only known to be reached by a shortest path once it is popped from the queue.
+    fn portal_search_core<P, G, R>(
+        &mut self,
+        portals: &P,
+        starts: &[Coord],
+        goal: &G,
+        profiler: &mut R,
+    ) -> Result<(), NoPath>
+    where
+        P: Portals,
+        G: Goal,
+        R: Profiler,
+    {
+        self.init_starts(starts);
+        self.portal_queue.clear();
+        let portal_goal = self.portal_goal(portals, goal);
+        for &start in starts {
+            let heuristic = self.portal_heuristic(&portal_goal, start);
+            self.portal_queue.push(PortalNode {
+                key: (heuristic, Reverse(0)),
+                value: start,
+            });
+        }
+        while let Some(PortalNode {
+            key: (cost_plus_heuristic, Reverse(cost)),
+            value: coord,
+        }) = self.portal_queue.pop()
+        {
+            if self.seen_set.cost(coord) != Some(cost) {
+                // a cheaper route to this cell was found after this node was queued
+                continue;
+            }
+            if goal.is_goal(coord) {
+                self.reached_coord = coord;
+                return Ok(());
+            }
+            if let Some(Stop) = profiler.before_expand(coord, cost, cost_plus_heuristic) {
+                return Err(NoPath);
+            }
+            profiler.expand(coord);
+            for &in_direction in &UNIT_COORDS {
+                let to_coord = coord + in_direction.to_coord();
+                let step = Step { to_coord, in_direction };
+                self.consider_step_with_portals(portals, step, cost + 1, &portal_goal, profiler);
+            }
+            portals.for_each_portal(coord, |portal| {
+                self.consider_portal(portals, coord, portal, cost, &portal_goal, profiler);
+            });
+        }
+        Err(NoPath)
+    }
+
+    fn goal_unreachable(&self, starts: &[Coord], goals: &[Coord]) -> SearchError {
+        self.seen_set.goal_unreachable(starts, goals)
+    }
+
+    fn init_starts(&mut self, starts: &[Coord]) {
+        self.seen_set.clear();
+        for &start in starts {
+            self.seen_set.add_start(start);
+        }
+    }
+
+    fn point_to_point_search_core<S, E, G, P>(
+        &mut self,
+        point_to_point_search: &S,
+        starts: &[Coord],
+        goal: &G,
+        profiler: &mut P,
+    ) -> Result<(), NoPath>
+    where
+        S: CanEnter,
+        E: PrivateUnidirectionalExpand,
+        G: Goal,
+        P: Profiler,
+    {
+        self.init_starts(starts);
+        self.priority_queue.clear();
+        if let Some(&start) = starts.iter().find(|&&start| goal.is_goal(start)) {
+            self.reached_coord = start;
+            return Ok(());
+        }
+        for &start in starts {
+            for &in_direction in &UNIT_COORDS {
+                let to_coord = start + in_direction.to_coord();
+                let step = Step { to_coord, in_direction };
+                if let Some(Stop) = E::consider(self, point_to_point_search, step, 0, goal, profiler) {
+                    return Ok(());
+                }
+            }
+        }
+        while let Some(Node {
+            key: (cost_plus_heuristic, Reverse(cost)),
+            value: step,
+        }) = self.priority_queue.pop()
+        {
+            if goal.is_goal(step.to_coord) {
+                self.reached_coord = step.to_coord;
+                return Ok(());
+            }
+            if let Some(Stop) = profiler.before_expand(step.to_coord, cost, cost_plus_heuristic) {
+                return Err(NoPath);
+            }
+            profiler.expand(step.to_coord);
+            if let Some(Stop) = E::expand(self, point_to_point_search, step, cost, goal, profiler) {
+                return Ok(());
+            }
+        }
+        Err(NoPath)
+    }
+
+    pub fn point_to_point_search_path<S, E>(
+        &mut self,
+        expand: E,
+        point_to_point_search: &S,
+        start: Coord,
+        goal: Coord,
+        path: &mut Path,
+    ) -> Result<(), SearchError>
+    where
+        S: CanEnter,
+        E: Expand,
+    {
+        self.point_to_point_search_path_with_observer(expand, point_to_point_search, start, goal, path, &mut ())
+    }
+
+    pub fn point_to_point_search_path_with_observer<S, E, O>(
+        &mut self,
+        expand: E,
+        point_to_point_search: &S,
+        start: Coord,
+        goal: Coord,
+        path: &mut Path,
+        mut observer: &mut O,
+    ) -> Result<(), SearchError>
+    where
+        S: CanEnter,
+        E: Expand,
+        O: Observer,
+    {
+        let _ = expand;
+        self.seen_set.check_bounds(&[start], &[goal])?;
+        E::search(self, point_to_point_search, &[start], &goal, &mut observer)
+            .map_err(|NoPath| self.goal_unreachable(&[start], &[goal]))?;
+        E::build_path(self, path);
+        observer.path_cost(E::path_cost(self));
+        Ok(())
+    }
+
+    /// Like `point_to_point_search_path`, but stores the path as the jumps taken by the search.
+    /// With `expand::JumpPoint` this avoids expanding long straight runs into individual steps.
+    pub fn point_to_point_search_compact_path<S, E>(
+        &mut self,
+        expand: E,
+        point_to_point_search: &S,
+        start: Coord,
+        goal: Coord,
+        path: &mut CompactPath,
+    ) -> Result<(), SearchError>
+    where
+        S: CanEnter,
+        E: Expand,
+    {
+        self.point_to_point_search_compact_path_with_observer(expand, point_to_point_search, start, goal, path, &mut ())
+    }
+
+    pub fn point_to_point_search_compact_path_with_observer<S, E, O>(
+        &mut self,
+        expand: E,
+        point_to_point_search: &S,
+        start: Coord,
+        goal: Coord,
+        path: &mut CompactPath,
+        mut observer: &mut O,
+    ) -> Result<(), SearchError>
+    where
+        S: CanEnter,
+        E: Expand,
+        O: Observer,
+    {
+        let _ = expand;
+        self.seen_set.check_bounds(&[start], &[goal])?;
+        E::search(self, point_to_point_search, &[start], &goal, &mut observer)
+            .map_err(|NoPath| self.goal_unreachable(&[start], &[goal]))?;
+        E::build_compact_path(self, path);
+        observer.path_cost(E::path_cost(self));
+        Ok(())
+    }
+
+    pub fn point_to_point_search_first<S, E>(
+        &mut self,
+        expand: E,
+        point_to_point_search: &S,
+        start: Coord,
+        goal: Coord,
+    ) -> Result<Option<CardinalDirection>, SearchError>
+    where
+        S: CanEnter,
+        E: Expand,
+    {
+        self.point_to_point_search_first_with_observer(expand, point_to_point_search, start, goal, &mut ())
+    }
+
+    pub fn point_to_point_search_first_with_observer<S, E, O>(
+        &mut self,
+        expand: E,
+        point_to_point_search: &S,
+        start: Coord,
+        goal: Coord,
+        mut observer: &mut O,
+    ) -> Result<Option<CardinalDirection>, SearchError>
+    where
+        S: CanEnter,
+        E: Expand,
+        O: Observer,
+    {
+        let _ = expand;
+        self.seen_set.check_bounds(&[start], &[goal])?;
+        E::search(self, point_to_point_search, &[start], &goal, &mut observer)
+            .map_err(|NoPath| self.goal_unreachable(&[start], &[goal]))?;
+        observer.path_cost(E::path_cost(self));
+        Ok(E::first_direction(self))
+    }
+
+    #[deprecated(note = "pass a `SearchStats` to `point_to_point_search_first_with_observer` instead")]
+    #[allow(deprecated)]
+    pub fn point_to_point_search_profile<S, E>(
+        &mut self,
+        expand: E,
+        point_to_point_search: &S,
+        start: Coord,
+        goal: Coord,
+    ) -> (Profile, Result<(), SearchError>)
+    where
+        S: CanEnter,
+        E: Expand,
+    {
+        let _ = expand;
+        let mut profile = Profile::default();
+        if let Err(error) = self.seen_set.check_bounds(&[start], &[goal]) {
+            return (profile, Err(error));
+        }
+        let result = E::search(self, point_to_point_search, &[start], &goal, &mut &mut profile)
+            .map_err(|NoPath| self.goal_unreachable(&[start], &[goal]));
+        (profile, result)
+    }
+
+    /// On reaching a limit, `path` is set to the path to the expanded cell closest to the goal.
+    pub fn point_to_point_search_path_limited<S, E>(
+        &mut self,
+        expand: E,
+        point_to_point_search: &S,
+        start: Coord,
+        goal: Coord,
+        limits: &SearchLimits,
+        path: &mut Path,
+    ) -> Result<(), LimitedSearchError>
+    where
+        S: CanEnter,
+        E: Expand,
+    {
+        let _ = expand;
+        self.seen_set
+            .check_bounds(&[start], &[goal])
+            .map_err(LimitedSearchError::Search)?;
+        let mut limiter = Limiter::new(limits, start, goal, self.wrap, self.bounds());
+        let result = match limits.bounds {
+            None => E::search(self, point_to_point_search, &[start], &goal, &mut limiter),
+            Some(bounds) => {
+                let bounded = Bounded::new(point_to_point_search, bounds);
+                let result = if bounds.contains(goal) {
+                    E::search(self, &bounded, &[start], &goal, &mut limiter)
+                } else {
+                    E::search(self, &bounded, &[start], &OutOfBounds(goal), &mut limiter)
                 };
-                *grid.get_checked_mut(coord) = cell;
+                if result.is_err()
+                    && limiter.limit_reached().is_none()
+                    && (bounded.left_bounds() || !bounds.contains(goal))
+                {
+                    limiter.set_limit_reached(Limit::Bounds);
+                }
+                result
+            }
+        };
+        if let Ok(()) = result {
+            E::build_path(self, path);
+            // a path found without being popped from the queue can cost more than the limit
+            if limits.max_path_cost.map_or(true, |max| path.len() as u32 <= max) {
+                return Ok(());
+            }
+            limiter.set_limit_reached(Limit::PathCost);
+        }
+        match limiter.limit_reached() {
+            None => Err(LimitedSearchError::Search(self.goal_unreachable(&[start], &[goal]))),
+            Some(limit) => {
+                let closest = limiter.closest();
+                self.seen_set.build_path_to(closest, path);
+                Err(LimitedSearchError::LimitReached { limit, closest })
+            }
+        }
+    }
+
+    pub fn set_to_set_search_path<S, E>(
+        &mut self,
+        expand: E,
+        point_to_point_search: &S,
+        starts: &[Coord],
+        goals: &[Coord],
+        path: &mut Path,
+    ) -> Result<Endpoints, SearchError>
+    where
+        S: CanEnter,
+        E: Expand,
+    {
+        self.set_to_set_search_path_with_observer(expand, point_to_point_search, starts, goals, path, &mut ())
+    }
+
+    pub fn set_to_set_search_path_with_observer<S, E, O>(
+        &mut self,
+        expand: E,
+        point_to_point_search: &S,
+        starts: &[Coord],
+        goals: &[Coord],
+        path: &mut Path,
+        mut observer: &mut O,
+    ) -> Result<Endpoints, SearchError>
+    where
+        S: CanEnter,
+        E: Expand,
+        O: Observer,
+    {
+        let _ = expand;
+        self.seen_set.check_bounds(starts, goals)?;
+        let mut goal_set = std::mem::take(&mut self.goal_set);
+        goal_set.set(goals);
+        let result = if starts.is_empty() || goal_set.is_empty() {
+            Err(NoPath)
+        } else {
+            E::search(self, point_to_point_search, starts, &goal_set, &mut observer)
+        };
+        self.goal_set = goal_set;
+        result.map_err(|NoPath| self.goal_unreachable(starts, goals))?;
+        E::build_path(self, path);
+        observer.path_cost(E::path_cost(self));
+        Ok(Endpoints::of_path(path, self.reached_coord))
+    }
+
+    pub fn weighted_point_to_point_search_path<S>(
+        &mut self,
+        step_cost: &S,
+        start: Coord,
+        goal: Coord,
+        path: &mut Path,
+    ) -> Result<(), SearchError>
+    where
+        S: StepCost,
+    {
+        self.weighted_point_to_point_search_path_with_observer(step_cost, start, goal, path, &mut ())
+    }
+
+    pub fn weighted_point_to_point_search_path_with_observer<S, O>(
+        &mut self,
+        step_cost: &S,
+        start: Coord,
+        goal: Coord,
+        path: &mut Path,
+        mut observer: &mut O,
+    ) -> Result<(), SearchError>
+    where
+        S: StepCost,
+        O: Observer,
+    {
+        self.seen_set.check_bounds(&[start], &[goal])?;
+        self.weighted_point_to_point_search_core(step_cost, start, goal, &mut observer)
+            .map_err(|NoPath| self.goal_unreachable(&[start], &[goal]))?;
+        self.seen_set.build_path_to(goal, path);
+        observer.path_cost(self.seen_set.cost(goal).unwrap_or(0));
+        Ok(())
+    }
+
+    pub fn weighted_point_to_point_search_first<S>(
+        &mut self,
+        step_cost: &S,
+        start: Coord,
+        goal: Coord,
+    ) -> Result<Option<CardinalDirection>, SearchError>
+    where
+        S: StepCost,
+    {
+        self.weighted_point_to_point_search_first_with_observer(step_cost, start, goal, &mut ())
+    }
+
+    pub fn weighted_point_to_point_search_first_with_observer<S, O>(
+        &mut self,
+        step_cost: &S,
+        start: Coord,
+        goal: Coord,
+        mut observer: &mut O,
+    ) -> Result<Option<CardinalDirection>, SearchError>
+    where
+        S: StepCost,
+        O: Observer,
+    {
+        self.seen_set.check_bounds(&[start], &[goal])?;
+        self.weighted_point_to_point_search_core(step_cost, start, goal, &mut observer)
+            .map_err(|NoPath| self.goal_unreachable(&[start], &[goal]))?;
+        observer.path_cost(self.seen_set.cost(goal).unwrap_or(0));
+        Ok(self.seen_set.first_direction_towards(goal))
+    }
+
+    /// Searches a world with portals as well as steps. Portals break the assumptions jump point
+    /// search relies on, so cells are always expanded sequentially. The manhattan distance to the
+    /// goal is capped by the cheapest route to the goal through any portal, keeping the heuristic
+    /// admissible.
+    pub fn portal_search_path<P: Portals>(
+        &mut self,
+        portals: &P,
+        start: Coord,
+        goal: Coord,
+        path: &mut PortalPath,
+    ) -> Result<(), SearchError> {
+        self.portal_search_path_with_observer(portals, start, goal, path, &mut ())
+    }
+
+    pub fn portal_search_path_with_observer<P: Portals, O: Observer>(
+        &mut self,
+        portals: &P,
+        start: Coord,
+        goal: Coord,
+        path: &mut PortalPath,
+        mut observer: &mut O,
+    ) -> Result<(), SearchError> {
+        self.seen_set.check_bounds(&[start], &[goal])?;
+        self.portal_search_core(portals, &[start], &goal, &mut observer)
+            .map_err(|NoPath| self.goal_unreachable(&[start], &[goal]))?;
+        self.seen_set.build_portal_path_to(goal, path);
+        observer.path_cost(self.seen_set.cost(goal).unwrap_or(0));
+        Ok(())
+    }
+
+    /// Returns the first node of the path, which may be a portal rather than a step
+    pub fn portal_search_first<P: Portals>(
+        &mut self,
+        portals: &P,
+        start: Coord,
+        goal: Coord,
+    ) -> Result<Option<PortalPathNode>, SearchError> {
+        self.portal_search_first_with_observer(portals, start, goal, &mut ())
+    }
+
+    pub fn portal_search_first_with_observer<P: Portals, O: Observer>(
+        &mut self,
+        portals: &P,
+        start: Coord,
+        goal: Coord,
+        mut observer: &mut O,
+    ) -> Result<Option<PortalPathNode>, SearchError> {
+        self.seen_set.check_bounds(&[start], &[goal])?;
+        self.portal_search_core(portals, &[start], &goal, &mut observer)
+            .map_err(|NoPath| self.goal_unreachable(&[start], &[goal]))?;
+        observer.path_cost(self.seen_set.cost(goal).unwrap_or(0));
+        Ok(self.seen_set.first_portal_path_node_towards(goal))
+    }
+}
+
+fn has_forced_neighbour<P: CanEnter, G: Goal, N: Fn(Coord) -> Coord>(
+    point_to_point_search: &P,
+    step: Step,
+    goal: &G,
+    normalize: N,
+) -> bool {
+    let step_from = |from_coord: Coord, in_direction: UnitCoord| Step {
+        to_coord: normalize(from_coord + in_direction.to_coord()),
+        in_direction,
+    };
+    (!point_to_point_search.can_enter(normalize(step.to_coord + step.in_direction.left135()))
+        && (point_to_point_search.can_step(step_from(step.to_coord, step.in_direction.left90()))
+            || goal.is_goal(normalize(step.to_coord + step.in_direction.left90().to_coord()))))
+        || (!point_to_point_search.can_enter(normalize(step.to_coord + step.in_direction.right135()))
+            && (point_to_point_search.can_step(step_from(step.to_coord, step.in_direction.right90()))
+                || goal.is_goal(normalize(step.to_coord + step.in_direction.right90().to_coord()))))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use grid_2d::Grid;
+    use rand::{Rng, SeedableRng};
+    use rand_isaac::Isaac64Rng;
+
+    #[derive(Clone)]
+    enum Cell {
+        Solid,
+        Traversable,
+    }
+
+    struct Test {
+        grid: Grid<Cell>,
+        start: Coord,
+        goal: Coord,
+    }
+
+    fn print_test(test: &Test) {
+        for row in test.grid.rows() {
+            print!("\"");
+            for cell in row {
+                let ch = match cell {
+                    Cell::Solid => '#',
+                    Cell::Traversable => '.',
+                };
+                print!("{}", ch);
+            }
+            println!("\",");
+        }
+    }
+
+    fn str_slice_to_test(str_slice: &[&str]) -> Test {
+        let width = str_slice[0].len() as u32;
+        let height = str_slice.len() as u32;
+        let size = Size::new(width, height);
+        let mut grid = Grid::new_clone(size, Cell::Solid);
+        let mut start = None;
+        let mut goal = None;
+        for (y, line) in str_slice.iter().enumerate() {
+            for (x, ch) in line.chars().enumerate() {
+                let coord = Coord::new(x as i32, y as i32);
+                let cell = match ch {
+                    '.' => Cell::Traversable,
+                    '@' => {
+                        start = Some(coord);
+                        Cell::Traversable
+                    }
+                    '*' => {
+                        goal = Some(coord);
+                        Cell::Traversable
+                    }
+                    '#' => Cell::Solid,
+                    _ => Cell::Traversable,
+                };
+                *grid.get_checked_mut(coord) = cell;
+            }
+        }
+        Test {
+            grid,
+            start: start.unwrap(),
+            goal: goal.unwrap_or(start.unwrap()),
+        }
+    }
+
+    fn random_test<R: Rng>(size: Size, rng: &mut R) -> Test {
+        let mut grid = Grid::new_clone(size, Cell::Traversable);
+        let num_solid = size.count() / 4;
+        for _ in 0..num_solid {
+            let coord = Coord::random_within(size, rng);
+            *grid.get_checked_mut(coord) = Cell::Solid;
+        }
+        let start = Coord::new(0, 0);
+        let goal = size.to_coord().unwrap() - Coord::new(1, 1);
+        Test { grid, start, goal }
+    }
+
+    struct Search<'a> {
+        grid: &'a Grid<Cell>,
+    }
+
+    impl<'a> CanEnter for Search<'a> {
+        fn can_enter(&self, coord: Coord) -> bool {
+            if let Some(cell) = self.grid.get(coord) {
+                match cell {
+                    Cell::Solid => false,
+                    Cell::Traversable => true,
+                }
+            } else {
+                false
+            }
+        }
+    }
+
+    fn assert_valid_path<S: CanEnter>(search: &S, path: &Path, start: Coord, goal: Coord) {
+        let mut coord = start;
+        for node in path.iter() {
+            let step = Step {
+                to_coord: node.to_coord,
+                in_direction: UnitCoord::from_cardinal_direction(node.in_direction),
+            };
+            assert_eq!(step.from_coord(), coord);
+            assert!(step.to_coord == goal || search.can_step(step));
+            coord = step.to_coord;
+        }
+        assert_eq!(coord, goal);
+    }
+
+    fn test(grid_str_slice: &[&str], len: Option<usize>) {
+        let Test { grid, start, goal } = str_slice_to_test(grid_str_slice);
+        let mut ctx = Context::new(grid.size());
+        let mut path = Path::default();
+        match len {
+            Some(len) => {
+                ctx.point_to_point_search_path(expand::Sequential, &Search { grid: &grid }, start, goal, &mut path)
+                    .unwrap();
+                assert_eq!(path.len(), len);
+                ctx.point_to_point_search_path(expand::JumpPoint, &Search { grid: &grid }, start, goal, &mut path)
+                    .unwrap();
+                assert_eq!(path.len(), len);
+                ctx.point_to_point_search_path(expand::Bidirectional, &Search { grid: &grid }, start, goal, &mut path)
+                    .unwrap();
+                assert_eq!(path.len(), len);
+                assert_valid_path(&Search { grid: &grid }, &path, start, goal);
+            }
+            None => {
+                ctx.point_to_point_search_path(expand::Sequential, &Search { grid: &grid }, start, goal, &mut path)
+                    .unwrap_err();
+                ctx.point_to_point_search_path(expand::JumpPoint, &Search { grid: &grid }, start, goal, &mut path)
+                    .unwrap_err();
+                ctx.point_to_point_search_path(expand::Bidirectional, &Search { grid: &grid }, start, goal, &mut path)
+                    .unwrap_err();
+            }
+        }
+    }
+
+    const GRID_A: &[&str] = &[
+        "..........",
+        ".......*..",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        ".@........",
+        "..........",
+    ];
+
+    #[test]
+    fn grid_a() {
+        test(GRID_A, Some(13));
+    }
+
+    const GRID_B: &[&str] = &[
+        "..........",
+        ".......#..",
+        ".......#..",
+        "....*..#..",
+        "########..",
+        "..........",
+        "..........",
+        "..........",
+        ".@........",
+        "..........",
+    ];
+
+    #[test]
+    fn grid_b() {
+        test(GRID_B, Some(22));
+    }
+
+    const GRID_C: &[&str] = &[
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        ".@*.......",
+        "..........",
+    ];
+
+    #[test]
+    fn grid_c() {
+        test(GRID_C, Some(1));
+    }
+
+    const GRID_D: &[&str] = &[
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        ".@........",
+        "..........",
+    ];
+
+    #[test]
+    fn grid_d() {
+        test(GRID_D, Some(0));
+    }
+
+    const GRID_E: &[&str] = &[
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..*.......",
+        ".@........",
+        "..........",
+    ];
+
+    #[test]
+    fn grid_e() {
+        test(GRID_E, Some(2));
+    }
+
+    const GRID_F: &[&str] = &[
+        "..........",
+        ".......#.#",
+        ".......#.#",
+        ".......#.#",
+        ".......#*#",
+        ".......#.#",
+        ".......#.#",
+        ".......###",
+        ".@........",
+        "..........",
+    ];
+
+    #[test]
+    fn grid_f() {
+        test(GRID_F, Some(19));
+    }
+
+    const GRID_G: &[&str] = &[
+        "..........",
+        ".......#.#",
+        ".......#.#",
+        ".......#.#",
+        ".......#.#",
+        ".......#.#",
+        ".......#*#",
+        ".......###",
+        ".@........",
+        "..........",
+    ];
+
+    #[test]
+    fn grid_g() {
+        test(GRID_G, Some(21));
+    }
+
+    const GRID_H: &[&str] = &[
+        "..........",
+        "....@.....",
+        "..........",
+        "...###....",
+        "..........",
+        "..##......",
+        "..........",
+        ".....##...",
+        "....*.....",
+        "..........",
+    ];
+
+    #[test]
+    fn grid_h() {
+        test(GRID_H, Some(11));
+    }
+
+    const GRID_I: &[&str] = &[
+        "..........",
+        "....@.....",
+        "..........",
+        "..........",
+        "...###....",
+        "...#*#....",
+        "...###....",
+        "..........",
+        "..........",
+        "..........",
+    ];
+
+    #[test]
+    fn grid_i() {
+        test(GRID_I, None);
+    }
+
+    const GRID_J: &[&str] = &[
+        "..........",
+        "....*.....",
+        "..........",
+        "..........",
+        "...###....",
+        "...#@#....",
+        "...###....",
+        "..........",
+        "..........",
+        "..........",
+    ];
+
+    #[test]
+    fn grid_j() {
+        test(GRID_J, None);
+    }
+
+    const GRID_K: &[&str] = &[
+        "..........",
+        ".#####....",
+        ".....#....",
+        ".###.#....",
+        ".###.####.",
+        "....@...#.",
+        ".###.####.",
+        "...#.#....",
+        "..####....",
+        "..#.*.....",
+    ];
+
+    #[test]
+    fn grid_k() {
+        test(GRID_K, Some(32));
+    }
+
+    const GRID_L: &[&str] = &[
+        ".#........",
+        ".#...#.*#.",
+        "...#...#..",
+        ".#..#.....",
+        ".......#..",
+        ".#...#.#..",
+        "..#.......",
+        "....#..#..",
+        ".@.......#",
+        "...#..#...",
+    ];
+
+    #[test]
+    fn grid_l() {
+        test(GRID_L, Some(13));
+    }
+
+    const GRID_M: &[&str] = &[
+        "@......#..",
+        "..........",
+        ".#....##..",
+        "...###....",
+        "..#..#...#",
+        "..#.##..#.",
+        "......#...",
+        "#...#.....",
+        "...#..#...",
+        "....#....*",
+    ];
+
+    #[test]
+    fn grid_m() {
+        test(GRID_M, Some(18));
+    }
+
+    const GRID_N: &[&str] = &[
+        "@#........",
+        "....#..##.",
+        "..#.....#.",
+        "..#.#..##.",
+        "#.........",
+        "...#.#.#..",
+        "...#..#...",
+        "......#..#",
+        ".......#..",
+        ".#.#....#*",
+    ];
+
+    #[test]
+    fn grid_n() {
+        test(GRID_N, Some(18));
+    }
+
+    const GRID_O: &[&str] = &[
+        "@.........",
+        "##...##...",
+        ".....#....",
+        "#..#...#.#",
+        "..##.#....",
+        "...#......",
+        "...#...###",
+        ".......#..",
+        ".....#....",
+        "...#..#.#*",
+    ];
+
+    #[test]
+    fn grid_o() {
+        test(GRID_O, Some(18));
+    }
+
+    #[test]
+    fn grid_random() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 1000;
+        let size = Size::new(10, 10);
+        let mut ctx = Context::new(size);
+        let mut path = Path::default();
+        for _ in 0..num_tests {
+            let Test { grid, start, goal } = random_test(size, &mut rng);
+            let seq_result =
+                ctx.point_to_point_search_path(expand::Sequential, &Search { grid: &grid }, start, goal, &mut path);
+            let seq_len = path.len();
+            let jps_result =
+                ctx.point_to_point_search_path(expand::JumpPoint, &Search { grid: &grid }, start, goal, &mut path);
+            let jps_len = path.len();
+            let bidirectional_result =
+                ctx.point_to_point_search_path(expand::Bidirectional, &Search { grid: &grid }, start, goal, &mut path);
+            let bidirectional_len = path.len();
+            if bidirectional_result.is_ok() {
+                assert_valid_path(&Search { grid: &grid }, &path, start, goal);
+            }
+            let test = Test { grid, start, goal };
+            if seq_result.is_ok() != jps_result.is_ok()
+                || seq_len != jps_len
+                || seq_result.is_ok() != bidirectional_result.is_ok()
+                || seq_len != bidirectional_len
+            {
+                print_test(&test);
+            }
+            assert_eq!(seq_result.is_ok(), jps_result.is_ok());
+            assert_eq!(seq_len, jps_len);
+            assert_eq!(seq_result.is_ok(), bidirectional_result.is_ok());
+            assert_eq!(seq_len, bidirectional_len);
+        }
+    }
+
+    #[test]
+    fn bidirectional_first() {
+        let Test { grid, start, goal } = str_slice_to_test(GRID_B);
+        let mut ctx = Context::new(grid.size());
+        let search = Search { grid: &grid };
+        assert_eq!(
+            ctx.point_to_point_search_first(expand::Bidirectional, &search, start, goal),
+            Ok(Some(CardinalDirection::East))
+        );
+        let Test { grid, start, goal } = str_slice_to_test(GRID_C);
+        let search = Search { grid: &grid };
+        assert_eq!(
+            ctx.point_to_point_search_first(expand::Bidirectional, &search, start, goal),
+            Ok(Some(CardinalDirection::East))
+        );
+        let Test { grid, start, goal } = str_slice_to_test(GRID_D);
+        let search = Search { grid: &grid };
+        assert_eq!(
+            ctx.point_to_point_search_first(expand::Bidirectional, &search, start, goal),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn bidirectional_allocates_reverse_seen_set() {
+        let grid = Grid::new_clone(Size::new(10, 3), Cell::Traversable);
+        let search = Search { grid: &grid };
+        let mut ctx = Context::new(grid.size());
+        ctx.set_wrap(Wrap::X);
+        ctx.point_to_point_search_first(expand::JumpPoint, &search, Coord::new(1, 1), Coord::new(8, 1))
+            .unwrap();
+        assert!(ctx.reverse_seen_set.is_none());
+        let mut path = Path::default();
+        ctx.point_to_point_search_path(
+            expand::Bidirectional,
+            &search,
+            Coord::new(1, 1),
+            Coord::new(8, 1),
+            &mut path,
+        )
+        .unwrap();
+        assert!(ctx.reverse_seen_set.is_some());
+        // the reverse seen set shares the wrap, so the path crosses the seam
+        assert_eq!(path.len(), 3);
+    }
+
+    // also checks the deprecated profile agrees with the statistics
+    #[allow(deprecated)]
+    fn observer_test<E: expand::Expand + Copy>(expand: E) {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 200;
+        let size = Size::new(10, 10);
+        let mut ctx = Context::new(size);
+        let mut path = Path::default();
+        let mut expected_path = Path::default();
+        for _ in 0..num_tests {
+            let Test { grid, start, goal } = random_test(size, &mut rng);
+            let search = Search { grid: &grid };
+            let mut stats = SearchStats::default();
+            let result =
+                ctx.point_to_point_search_path_with_observer(expand, &search, start, goal, &mut path, &mut stats);
+            let expected = ctx.point_to_point_search_path(expand, &search, start, goal, &mut expected_path);
+            assert_eq!(result, expected);
+            assert_eq!(path.len(), expected_path.len());
+            let (profile, _) = ctx.point_to_point_search_profile(expand, &search, start, goal);
+            assert_eq!(stats.nodes_expanded, profile.expand);
+            assert!(stats.nodes_expanded <= stats.nodes_pushed);
+            assert!(stats.nodes_pushed <= stats.cells_visited);
+            assert!(stats.peak_queue_len as u64 <= stats.nodes_pushed);
+            let mut first_stats = SearchStats::default();
+            ctx.point_to_point_search_first_with_observer(expand, &search, start, goal, &mut first_stats)
+                .unwrap_or(None);
+            assert_eq!(first_stats, stats);
+            match result {
+                Ok(()) => assert_eq!(stats.path_cost, Some(path.len() as u32)),
+                Err(SearchError::GoalUnreachable { .. }) => assert_eq!(stats.path_cost, None),
+                Err(error) => panic!("unexpected error: {:?}", error),
             }
         }
-        Test {
-            grid,
-            start: start.unwrap(),
-            goal: goal.unwrap_or(start.unwrap()),
-        }
     }
 
-    fn random_test<R: Rng>(size: Size, rng: &mut R) -> Test {
-        let mut grid = Grid::new_clone(size, Cell::Traversable);
-        let num_solid = size.count() / 4;
-        for _ in 0..num_solid {
-            let coord = Coord::random_within(size, rng);
-            *grid.get_checked_mut(coord) = Cell::Solid;
+    #[test]
+    fn observer_random() {
+        observer_test(expand::Sequential);
+        observer_test(expand::JumpPoint);
+        observer_test(expand::Bidirectional);
+    }
+
+    #[test]
+    fn trace() {
+        let Test { grid, start, goal } = str_slice_to_test(GRID_A);
+        let search = Search { grid: &grid };
+        let mut ctx = Context::new(grid.size());
+        let mut path = Path::default();
+        let mut trace = Trace::new();
+        ctx.point_to_point_search_path_with_observer(expand::JumpPoint, &search, start, goal, &mut path, &mut trace)
+            .unwrap();
+        trace.set_path(start, &path);
+        assert_eq!(trace.path().len(), path.len() + 1);
+        assert!(trace
+            .events()
+            .iter()
+            .any(|event| matches!(event, TraceEvent::Jump { .. })));
+        assert_eq!(trace.events().last(), Some(&TraceEvent::PathCost(path.len() as u32)));
+        #[cfg(feature = "trace-json")]
+        {
+            let mut json_lines = Vec::new();
+            trace.write_json_lines(&mut json_lines).unwrap();
+            assert_eq!(Trace::read_json_lines(json_lines.as_slice()).unwrap(), trace);
+            let diagonal = br#"{"event":{"jump":{"from":{"x":0,"y":0},"to":{"x":1,"y":1}}}}"#;
+            assert!(Trace::read_json_lines(&diagonal[..])
+                .unwrap_err()
+                .to_string()
+                .starts_with("jump"));
         }
-        let start = Coord::new(0, 0);
-        let goal = size.to_coord().unwrap() - Coord::new(1, 1);
-        Test { grid, start, goal }
+        let ansi = trace.render_ansi(&search, grid.size());
+        assert_eq!(ansi.lines().count(), grid.height() as usize);
+        assert_eq!(ansi.matches('*').count(), path.len() + 1);
+        let svg = trace.render_svg(&search, grid.size());
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("<polyline"));
     }
 
-    struct Search<'a> {
-        grid: &'a Grid<Cell>,
+    #[test]
+    fn goal_cost_is_path_len() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let size = Size::new(10, 10);
+        let mut ctx = Context::new(size);
+        let mut path = Path::default();
+        for _ in 0..100 {
+            let Test { grid, start, goal } = random_test(size, &mut rng);
+            let search = Search { grid: &grid };
+            if ctx
+                .point_to_point_search_path(expand::Sequential, &search, start, goal, &mut path)
+                .is_ok()
+            {
+                assert_eq!(ctx.seen_set.cost(goal), Some(path.len() as u32));
+                ctx.point_to_point_search_path(expand::JumpPoint, &search, start, goal, &mut path)
+                    .unwrap();
+                assert_eq!(ctx.seen_set.cost(goal), Some(path.len() as u32));
+            }
+        }
     }
 
-    impl<'a> CanEnter for Search<'a> {
-        fn can_enter(&self, coord: Coord) -> bool {
-            if let Some(cell) = self.grid.get(coord) {
-                match cell {
-                    Cell::Solid => false,
-                    Cell::Traversable => true,
+    fn set_to_set_test(num_starts: usize, num_goals: usize) {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 200;
+        let size = Size::new(10, 10);
+        let mut ctx = Context::new(size);
+        let mut path = Path::default();
+        for _ in 0..num_tests {
+            let Test { grid, .. } = random_test(size, &mut rng);
+            let search = Search { grid: &grid };
+            let starts = (0..num_starts)
+                .map(|_| Coord::random_within(size, &mut rng))
+                .collect::<Vec<_>>();
+            let goals = (0..num_goals)
+                .map(|_| Coord::random_within(size, &mut rng))
+                .collect::<Vec<_>>();
+            let mut shortest = None;
+            for &start in &starts {
+                for &goal in &goals {
+                    if let Ok(()) = ctx.point_to_point_search_path(expand::Sequential, &search, start, goal, &mut path)
+                    {
+                        shortest = Some(shortest.map_or(path.len(), |len: usize| len.min(path.len())));
+                    }
+                }
+            }
+            fn check<E: expand::Expand>(
+                expand: E,
+                ctx: &mut Context,
+                search: &Search,
+                starts: &[Coord],
+                goals: &[Coord],
+                path: &mut Path,
+                shortest: Option<usize>,
+            ) {
+                match ctx.set_to_set_search_path(expand, search, starts, goals, path) {
+                    Ok(Endpoints { start, goal }) => {
+                        assert!(starts.contains(&start));
+                        assert!(goals.contains(&goal));
+                        assert_valid_path(search, path, start, goal);
+                        assert_eq!(Some(path.len()), shortest);
+                    }
+                    Err(SearchError::GoalUnreachable { .. }) => assert_eq!(shortest, None),
+                    Err(error) => panic!("unexpected error: {:?}", error),
                 }
-            } else {
-                false
             }
+            check(
+                expand::Sequential,
+                &mut ctx,
+                &search,
+                &starts,
+                &goals,
+                &mut path,
+                shortest,
+            );
+            check(
+                expand::JumpPoint,
+                &mut ctx,
+                &search,
+                &starts,
+                &goals,
+                &mut path,
+                shortest,
+            );
+            check(
+                expand::Bidirectional,
+                &mut ctx,
+                &search,
+                &starts,
+                &goals,
+                &mut path,
+                shortest,
+            );
         }
     }
 
-    fn test(grid_str_slice: &[&str], len: Option<usize>) {
-        let Test { grid, start, goal } = str_slice_to_test(grid_str_slice);
+    #[test]
+    fn set_to_set_random() {
+        set_to_set_test(3, 3);
+    }
+
+    #[test]
+    fn set_to_set_random_many_goals() {
+        set_to_set_test(2, 20);
+    }
+
+    #[test]
+    fn set_to_set_empty() {
+        let Test { grid, start, goal } = str_slice_to_test(GRID_A);
         let mut ctx = Context::new(grid.size());
         let mut path = Path::default();
-        match len {
-            Some(len) => {
-                ctx.point_to_point_search_path(expand::Sequential, &Search { grid: &grid }, start, goal, &mut path)
-                    .unwrap();
-                assert_eq!(path.len(), len);
-                ctx.point_to_point_search_path(expand::JumpPoint, &Search { grid: &grid }, start, goal, &mut path)
-                    .unwrap();
-                assert_eq!(path.len(), len);
+        let search = Search { grid: &grid };
+        assert_eq!(
+            ctx.set_to_set_search_path(expand::Sequential, &search, &[], &[goal], &mut path),
+            Err(SearchError::GoalUnreachable {
+                closest: Coord::new(0, 0),
+                num_reachable: 0
+            })
+        );
+        assert_eq!(
+            ctx.set_to_set_search_path(expand::Sequential, &search, &[start], &[], &mut path),
+            Err(SearchError::GoalUnreachable {
+                closest: start,
+                num_reachable: 0
+            })
+        );
+        assert_eq!(
+            ctx.set_to_set_search_path(expand::JumpPoint, &search, &[start, goal], &[goal], &mut path),
+            Ok(Endpoints { start: goal, goal })
+        );
+        assert!(path.is_empty());
+    }
+
+    const GRID_WALLED: &[&str] = &[
+        "@.#..", //
+        "..#*.", //
+        "..#..", //
+    ];
+
+    #[test]
+    fn search_errors() {
+        let Test { grid, start, goal } = str_slice_to_test(GRID_WALLED);
+        let mut ctx = Context::new(grid.size());
+        let mut path = Path::default();
+        let search = Search { grid: &grid };
+        let unreachable = SearchError::GoalUnreachable {
+            closest: Coord::new(1, 1),
+            num_reachable: 6,
+        };
+        assert_eq!(
+            ctx.point_to_point_search_path(expand::Sequential, &search, start, goal, &mut path),
+            Err(unreachable)
+        );
+        // jump point search only visits the jump points, and there are none here
+        assert_eq!(
+            ctx.point_to_point_search_first(expand::JumpPoint, &search, start, goal),
+            Err(SearchError::GoalUnreachable {
+                closest: start,
+                num_reachable: 1
+            })
+        );
+        assert_eq!(
+            ctx.point_to_point_search_path(expand::Bidirectional, &search, start, goal, &mut path),
+            Err(unreachable)
+        );
+        assert_eq!(
+            ctx.point_to_point_search_path(expand::JumpPoint, &search, Coord::new(-1, 0), goal, &mut path),
+            Err(SearchError::StartOutOfBounds(Coord::new(-1, 0)))
+        );
+        assert_eq!(
+            ctx.point_to_point_search_path(expand::JumpPoint, &search, start, Coord::new(5, 0), &mut path),
+            Err(SearchError::GoalOutOfBounds(Coord::new(5, 0)))
+        );
+        let preprocessed_grid = PreprocessedGrid::new(&search, Size::new(4, 3));
+        assert_eq!(
+            ctx.preprocessed_search_path(&preprocessed_grid, start, goal, &mut path),
+            Err(SearchError::SizeMismatch {
+                expected: grid.size(),
+                actual: Size::new(4, 3)
+            })
+        );
+    }
+
+    fn limited_test<E: expand::Expand + Copy>(expand: E) {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 200;
+        let size = Size::new(10, 10);
+        let mut ctx = Context::new(size);
+        let mut path = Path::default();
+        for _ in 0..num_tests {
+            let Test { grid, start, goal } = random_test(size, &mut rng);
+            let search = Search { grid: &grid };
+            let unlimited = ctx
+                .point_to_point_search_path(expand, &search, start, goal, &mut path)
+                .map(|()| path.len());
+            let limits = SearchLimits::default();
+            match ctx.point_to_point_search_path_limited(expand, &search, start, goal, &limits, &mut path) {
+                Ok(()) => assert_eq!(Ok(path.len()), unlimited),
+                Err(error) => assert_eq!(Err(error), unlimited.map_err(LimitedSearchError::Search)),
             }
-            None => {
-                ctx.point_to_point_search_path(expand::Sequential, &Search { grid: &grid }, start, goal, &mut path)
-                    .unwrap_err();
-                ctx.point_to_point_search_path(expand::JumpPoint, &Search { grid: &grid }, start, goal, &mut path)
-                    .unwrap_err();
+            let limits = SearchLimits {
+                max_expansions: Some(5),
+                ..Default::default()
+            };
+            match ctx.point_to_point_search_path_limited(expand, &search, start, goal, &limits, &mut path) {
+                Ok(()) => assert_eq!(Ok(path.len()), unlimited),
+                Err(LimitedSearchError::LimitReached { limit, closest }) => {
+                    assert_eq!(limit, Limit::Expansions);
+                    assert_valid_path(&search, &path, start, closest);
+                    assert!(closest.manhattan_distance(goal) <= start.manhattan_distance(goal));
+                }
+                Err(LimitedSearchError::Search(error)) => assert_eq!(unlimited, Err(error)),
+            }
+            if let Ok(len) = unlimited {
+                let limits = SearchLimits {
+                    max_path_cost: Some(len as u32),
+                    ..Default::default()
+                };
+                assert_eq!(
+                    ctx.point_to_point_search_path_limited(expand, &search, start, goal, &limits, &mut path),
+                    Ok(())
+                );
+                assert_eq!(path.len(), len);
+                let limits = SearchLimits {
+                    max_path_cost: Some(len as u32 - 1),
+                    ..Default::default()
+                };
+                match ctx.point_to_point_search_path_limited(expand, &search, start, goal, &limits, &mut path) {
+                    Err(LimitedSearchError::LimitReached {
+                        limit: Limit::PathCost,
+                        closest,
+                    }) => assert_valid_path(&search, &path, start, closest),
+                    other => panic!("unexpected result: {:?}", other),
+                }
             }
         }
     }
 
-    const GRID_A: &[&str] = &[
-        "..........",
-        ".......*..",
-        "..........",
-        "..........",
-        "..........",
-        "..........",
-        "..........",
+    #[test]
+    fn limited_random() {
+        limited_test(expand::Sequential);
+        limited_test(expand::JumpPoint);
+        limited_test(expand::Bidirectional);
+    }
+
+    const GRID_LIMITED: &[&str] = &[
         "..........",
-        ".@........",
+        ".########.",
+        ".#......#.",
+        ".#.@....#.",
+        ".#......#*",
+        ".####.###.",
         "..........",
     ];
 
     #[test]
-    fn grid_a() {
-        test(GRID_A, Some(13));
+    fn limited_bounds() {
+        let Test { grid, start, goal } = str_slice_to_test(GRID_LIMITED);
+        let search = Search { grid: &grid };
+        let mut ctx = Context::new(grid.size());
+        let mut path = Path::default();
+        let limits = SearchLimits {
+            bounds: Some(Rect::new(Coord::new(0, 0), Size::new(10, 6))),
+            ..Default::default()
+        };
+        assert_eq!(
+            ctx.point_to_point_search_path_limited(expand::Sequential, &search, start, goal, &limits, &mut path),
+            Err(LimitedSearchError::LimitReached {
+                limit: Limit::Bounds,
+                closest: Coord::new(7, 4),
+            })
+        );
+        assert_valid_path(&search, &path, start, Coord::new(7, 4));
+        // only jump points are expanded, so the closest cell may be further from the goal
+        match ctx.point_to_point_search_path_limited(expand::JumpPoint, &search, start, goal, &limits, &mut path) {
+            Err(LimitedSearchError::LimitReached {
+                limit: Limit::Bounds,
+                closest,
+            }) => assert_valid_path(&search, &path, start, closest),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let limits = SearchLimits {
+            bounds: Some(Rect::new(Coord::new(1, 1), Size::new(10, 6))),
+            ..Default::default()
+        };
+        ctx.point_to_point_search_path_limited(expand::Sequential, &search, start, goal, &limits, &mut path)
+            .unwrap();
+        assert_eq!(path.len(), 11);
+        assert_valid_path(&search, &path, start, goal);
+        let limits = SearchLimits {
+            bounds: Some(Rect::new(Coord::new(2, 2), Size::new(6, 3))),
+            ..Default::default()
+        };
+        assert_eq!(
+            ctx.point_to_point_search_path_limited(expand::Sequential, &search, start, goal, &limits, &mut path),
+            Err(LimitedSearchError::LimitReached {
+                limit: Limit::Bounds,
+                closest: Coord::new(7, 4),
+            })
+        );
     }
 
-    const GRID_B: &[&str] = &[
-        "..........",
-        ".......#..",
-        ".......#..",
-        "....*..#..",
-        "########..",
-        "..........",
+    #[test]
+    fn limited_wrap() {
+        let grid = Grid::new_clone(Size::new(10, 3), Cell::Traversable);
+        let search = Search { grid: &grid };
+        let mut ctx = Context::new(grid.size());
+        ctx.set_wrap(Wrap::X);
+        let mut path = Path::default();
+        let limits = SearchLimits {
+            max_expansions: Some(1),
+            ..Default::default()
+        };
+        // the cell expanded first is closer to the goal across the seam than the start is
+        assert_eq!(
+            ctx.point_to_point_search_path_limited(
+                expand::Sequential,
+                &search,
+                Coord::new(1, 1),
+                Coord::new(8, 1),
+                &limits,
+                &mut path
+            ),
+            Err(LimitedSearchError::LimitReached {
+                limit: Limit::Expansions,
+                closest: Coord::new(0, 1),
+            })
+        );
+        assert_eq!(path.len(), 1);
+    }
+
+    struct WeightedSearch<'a> {
+        grid: &'a Grid<Cell>,
+        costs: &'a Grid<u32>,
+        min_step_cost: u32,
+    }
+
+    impl<'a> CanEnter for WeightedSearch<'a> {
+        fn can_enter(&self, coord: Coord) -> bool {
+            Search { grid: self.grid }.can_enter(coord)
+        }
+    }
+
+    impl<'a> StepCost for WeightedSearch<'a> {
+        fn step_cost(&self, step: Step) -> u32 {
+            *self.costs.get_checked(step.to_coord)
+        }
+        fn min_step_cost(&self) -> u32 {
+            self.min_step_cost
+        }
+    }
+
+    fn str_slice_to_costs(str_slice: &[&str]) -> Grid<u32> {
+        let width = str_slice[0].len() as u32;
+        let height = str_slice.len() as u32;
+        let mut costs = Grid::new_clone(Size::new(width, height), 1);
+        for (y, line) in str_slice.iter().enumerate() {
+            for (x, ch) in line.chars().enumerate() {
+                if let Some(cost) = ch.to_digit(10) {
+                    *costs.get_checked_mut(Coord::new(x as i32, y as i32)) = cost;
+                }
+            }
+        }
+        costs
+    }
+
+    fn path_cost(path: &Path, costs: &Grid<u32>) -> u32 {
+        path.iter().map(|node| *costs.get_checked(node.to_coord)).sum()
+    }
+
+    const WEIGHTED_GRID_A: &[&str] = &[
         "..........",
         "..........",
-        ".@........",
+        "..#####...",
+        ".@99999*..",
+        "..#####...",
         "..........",
     ];
 
     #[test]
-    fn grid_b() {
-        test(GRID_B, Some(22));
+    fn weighted_grid_a() {
+        let Test { grid, start, goal } = str_slice_to_test(WEIGHTED_GRID_A);
+        let costs = str_slice_to_costs(WEIGHTED_GRID_A);
+        let mut ctx = Context::new(grid.size());
+        let mut path = Path::default();
+        let search = WeightedSearch {
+            grid: &grid,
+            costs: &costs,
+            min_step_cost: 1,
+        };
+        ctx.weighted_point_to_point_search_path(&search, start, goal, &mut path)
+            .unwrap();
+        assert_eq!(path.len(), 10);
+        assert_eq!(path_cost(&path, &costs), 10);
+        assert_eq!(
+            ctx.weighted_point_to_point_search_first(&search, start, goal),
+            Ok(Some(CardinalDirection::North))
+        );
     }
 
-    const GRID_C: &[&str] = &[
-        "..........",
-        "..........",
-        "..........",
-        "..........",
-        "..........",
-        "..........",
-        "..........",
-        "..........",
-        ".@*.......",
-        "..........",
-    ];
+    #[test]
+    fn weighted_grid_unreachable() {
+        let Test { grid, start, goal } = str_slice_to_test(GRID_I);
+        let costs = Grid::new_clone(grid.size(), 1);
+        let mut ctx = Context::new(grid.size());
+        let mut path = Path::default();
+        let search = WeightedSearch {
+            grid: &grid,
+            costs: &costs,
+            min_step_cost: 1,
+        };
+        assert!(matches!(
+            ctx.weighted_point_to_point_search_path(&search, start, goal, &mut path),
+            Err(SearchError::GoalUnreachable { .. })
+        ));
+    }
 
     #[test]
-    fn grid_c() {
-        test(GRID_C, Some(1));
+    fn weighted_grid_random_uniform() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 1000;
+        let size = Size::new(10, 10);
+        let costs = Grid::new_clone(size, 1);
+        let mut ctx = Context::new(size);
+        let mut path = Path::default();
+        for _ in 0..num_tests {
+            let Test { grid, start, goal } = random_test(size, &mut rng);
+            let seq_result =
+                ctx.point_to_point_search_path(expand::Sequential, &Search { grid: &grid }, start, goal, &mut path);
+            let seq_len = path.len();
+            let search = WeightedSearch {
+                grid: &grid,
+                costs: &costs,
+                min_step_cost: 1,
+            };
+            let weighted_result = ctx.weighted_point_to_point_search_path(&search, start, goal, &mut path);
+            assert_eq!(seq_result, weighted_result);
+            assert_eq!(seq_len, path.len());
+        }
     }
 
-    const GRID_D: &[&str] = &[
-        "..........",
-        "..........",
-        "..........",
-        "..........",
-        "..........",
-        "..........",
-        "..........",
-        "..........",
-        ".@........",
-        "..........",
-    ];
+    #[test]
+    fn weighted_grid_random() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 1000;
+        let size = Size::new(10, 10);
+        let mut ctx = Context::new(size);
+        let mut path = Path::default();
+        for _ in 0..num_tests {
+            let Test { grid, start, goal } = random_test(size, &mut rng);
+            let costs = Grid::new_fn(size, |_| rng.gen_range(2..10));
+            // a heuristic of 0 makes the search equivalent to dijkstra's algorithm
+            let dijkstra = WeightedSearch {
+                grid: &grid,
+                costs: &costs,
+                min_step_cost: 0,
+            };
+            let dijkstra_result = ctx.weighted_point_to_point_search_path(&dijkstra, start, goal, &mut path);
+            let dijkstra_cost = path_cost(&path, &costs);
+            let a_star = WeightedSearch {
+                grid: &grid,
+                costs: &costs,
+                min_step_cost: 2,
+            };
+            let a_star_result = ctx.weighted_point_to_point_search_path(&a_star, start, goal, &mut path);
+            assert_eq!(dijkstra_result, a_star_result);
+            assert_eq!(dijkstra_cost, path_cost(&path, &costs));
+        }
+    }
+
+    // breadth-first search across the seams, for checking the length of wrapping paths
+    fn wrapped_distance(search: &Search, wrap: Wrap, start: Coord, goal: Coord) -> Option<usize> {
+        let size = search.grid.size();
+        let mut distances = Grid::new_copy(size, None);
+        let mut queue = VecDeque::new();
+        *distances.get_checked_mut(start) = Some(0);
+        queue.push_back(start);
+        while let Some(coord) = queue.pop_front() {
+            let distance = distances.get_checked(coord).unwrap();
+            if coord == goal {
+                return Some(distance);
+            }
+            for &in_direction in &UNIT_COORDS {
+                let to_coord = wrap.normalize(coord + in_direction.to_coord(), size);
+                if let Some(cell @ None) = distances.get_mut(to_coord) {
+                    if to_coord == goal || search.can_enter(to_coord) {
+                        *cell = Some(distance + 1);
+                        queue.push_back(to_coord);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn wrap_test<E: expand::Expand + Copy>(expand: E, wrap: Wrap) {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 500;
+        let size = Size::new(10, 10);
+        let mut ctx = Context::new(size);
+        ctx.set_wrap(wrap);
+        let mut path = Path::default();
+        for i in 0..num_tests {
+            let Test {
+                grid,
+                mut start,
+                mut goal,
+            } = random_test(size, &mut rng);
+            if i % 2 == 0 {
+                start = Coord::random_within(size, &mut rng);
+                goal = Coord::random_within(size, &mut rng);
+            }
+            let search = Search { grid: &grid };
+            let result = ctx.point_to_point_search_path(expand, &search, start, goal, &mut path);
+            let distance = match wrapped_distance(&search, wrap, start, goal) {
+                Some(distance) => distance,
+                None => {
+                    assert!(result.is_err());
+                    continue;
+                }
+            };
+            assert_eq!(result, Ok(()));
+            assert_eq!(path.len(), distance);
+            let mut coord = start;
+            for node in path.iter() {
+                assert_eq!(wrap.normalize(coord + node.in_direction.coord(), size), node.to_coord);
+                assert!(node.to_coord == goal || search.can_enter(node.to_coord));
+                coord = node.to_coord;
+            }
+            assert_eq!(coord, goal);
+        }
+    }
 
     #[test]
-    fn grid_d() {
-        test(GRID_D, Some(0));
+    fn wrap_random() {
+        for wrap in [Wrap::None, Wrap::X, Wrap::Y, Wrap::Both] {
+            wrap_test(expand::Sequential, wrap);
+            wrap_test(expand::JumpPoint, wrap);
+            wrap_test(expand::Bidirectional, wrap);
+        }
     }
 
-    const GRID_E: &[&str] = &[
-        "..........",
-        "..........",
-        "..........",
-        "..........",
-        "..........",
-        "..........",
-        "..........",
-        "..*.......",
-        ".@........",
-        "..........",
-    ];
+    #[test]
+    fn wrap_seam() {
+        let grid = Grid::new_clone(Size::new(10, 3), Cell::Traversable);
+        let search = Search { grid: &grid };
+        let mut ctx = Context::new(grid.size());
+        ctx.set_wrap(Wrap::X);
+        let mut path = Path::default();
+        let (start, goal) = (Coord::new(1, 1), Coord::new(8, 1));
+        ctx.point_to_point_search_path(expand::JumpPoint, &search, start, goal, &mut path)
+            .unwrap();
+        assert_eq!(path.len(), 3);
+        assert!(path.iter().all(|node| node.in_direction == CardinalDirection::West));
+        let mut trace = Trace::new();
+        ctx.point_to_point_search_path_with_observer(expand::JumpPoint, &search, start, goal, &mut path, &mut trace)
+            .unwrap();
+        trace.set_path(start, &path);
+        // the jump west across the seam ends off the grid, and only its part on the grid is drawn
+        let west = TraceEvent::Jump {
+            from: start,
+            to: Coord::new(-2, 1),
+        };
+        assert!(trace.events().contains(&west));
+        let mut west_only = Trace::new();
+        west_only.jump(start, Coord::new(-2, 1));
+        let ansi = west_only.render_ansi(&search, grid.size());
+        assert_eq!(ansi.matches('-').count(), 1);
+        assert_eq!(
+            ctx.point_to_point_search_first(expand::Bidirectional, &search, start, goal),
+            Ok(Some(CardinalDirection::West))
+        );
+        let costs = Grid::new_copy(grid.size(), 1);
+        let weighted = WeightedSearch {
+            grid: &grid,
+            costs: &costs,
+            min_step_cost: 1,
+        };
+        ctx.weighted_point_to_point_search_path(&weighted, start, goal, &mut path)
+            .unwrap();
+        assert_eq!(path.len(), 3);
+    }
 
     #[test]
-    fn grid_e() {
-        test(GRID_E, Some(2));
+    fn wrap_seam_path_editing() {
+        let grid = Grid::new_clone(Size::new(10, 3), Cell::Traversable);
+        let search = Search { grid: &grid };
+        let mut ctx = Context::new(grid.size());
+        ctx.set_wrap(Wrap::X);
+        let (a, b, c) = (Coord::new(1, 1), Coord::new(8, 1), Coord::new(8, 0));
+        let mut route = Path::default();
+        let mut leg = Path::default();
+        ctx.point_to_point_search_path(expand::Sequential, &search, a, b, &mut route)
+            .unwrap();
+        ctx.point_to_point_search_path(expand::Sequential, &search, b, c, &mut leg)
+            .unwrap();
+        route.append_path(&leg).unwrap();
+        assert_eq!(route.len(), 4);
+        let (head, tail) = route.split_at(1);
+        assert_eq!(head.start(), Some(a));
+        // the first step of the tail crosses from x = 0 to x = 9
+        assert_eq!(tail.start(), Some(Coord::new(0, 1)));
+        route.reverse();
+        assert_eq!(route.start(), Some(c));
+        assert_eq!(route.last().map(|node| node.to_coord), Some(a));
+        assert!(route.iter().all(|node| grid.size().is_valid(node.to_coord)));
+        assert!(route
+            .directions()
+            .skip(1)
+            .all(|direction| direction == CardinalDirection::East));
+        assert_eq!(
+            ctx.set_to_set_search_path(expand::JumpPoint, &search, &[Coord::new(4, 1), a], &[b], &mut route),
+            Ok(Endpoints { start: a, goal: b })
+        );
+        let mut compact_path = CompactPath::default();
+        ctx.point_to_point_search_compact_path(expand::JumpPoint, &search, b, a, &mut compact_path)
+            .unwrap();
+        compact_path.to_path(&mut leg);
+        leg.reverse();
+        assert_eq!(leg.start(), Some(a));
+        assert_eq!(leg.last().map(|node| node.to_coord), Some(b));
+        // a path found on a grid which wraps the other way can't be appended
+        let mut ctx_y = Context::new(grid.size());
+        ctx_y.set_wrap(Wrap::Y);
+        let mut leg_y = Path::default();
+        ctx_y
+            .point_to_point_search_path(expand::Sequential, &search, b, c, &mut leg_y)
+            .unwrap();
+        assert_eq!(leg.append_path(&leg_y), Err(PathError::WrapMismatch));
+        assert_eq!(leg.len(), 3);
     }
 
-    const GRID_F: &[&str] = &[
-        "..........",
-        ".......#.#",
-        ".......#.#",
-        ".......#.#",
-        ".......#*#",
-        ".......#.#",
-        ".......#.#",
-        ".......###",
-        ".@........",
-        "..........",
-    ];
+    // a grid placed somewhere in a world with no bounds, surrounded by solid cells
+    struct Translated<'a> {
+        grid: &'a Grid<Cell>,
+        offset: Coord,
+    }
 
-    #[test]
-    fn grid_f() {
-        test(GRID_F, Some(19));
+    impl<'a> CanEnter for Translated<'a> {
+        fn can_enter(&self, coord: Coord) -> bool {
+            Search { grid: self.grid }.can_enter(coord - self.offset)
+        }
     }
 
-    const GRID_G: &[&str] = &[
-        "..........",
-        ".......#.#",
-        ".......#.#",
-        ".......#.#",
-        ".......#.#",
-        ".......#.#",
-        ".......#*#",
-        ".......###",
-        ".@........",
-        "..........",
-    ];
+    fn unbounded_test<E: expand::Expand + Copy>(expand: E) {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 200;
+        let size = Size::new(20, 20);
+        let offset = Coord::new(-37, -1000);
+        let mut ctx = Context::new(size);
+        let mut unbounded_ctx = Context::new_unbounded();
+        let mut expected_path = Path::default();
+        let mut path = Path::default();
+        for _ in 0..num_tests {
+            let Test { grid, .. } = random_test(size, &mut rng);
+            let start = Coord::random_within(size, &mut rng);
+            let goal = Coord::random_within(size, &mut rng);
+            let search = Translated { grid: &grid, offset };
+            let expected =
+                ctx.point_to_point_search_path(expand, &Search { grid: &grid }, start, goal, &mut expected_path);
+            let result =
+                unbounded_ctx.point_to_point_search_path(expand, &search, start + offset, goal + offset, &mut path);
+            match expected {
+                Ok(()) => {
+                    assert_eq!(result, Ok(()));
+                    assert_valid_path(&search, &path, start + offset, goal + offset);
+                    assert_eq!(path.len(), expected_path.len());
+                }
+                // the cells visited by jump point search depend on where the grid ends
+                Err(SearchError::GoalUnreachable { .. }) => {
+                    assert!(matches!(result, Err(SearchError::GoalUnreachable { .. })))
+                }
+                Err(error) => panic!("unexpected error {:?}", error),
+            }
+        }
+    }
 
     #[test]
-    fn grid_g() {
-        test(GRID_G, Some(21));
+    fn unbounded_random() {
+        unbounded_test(expand::Sequential);
+        unbounded_test(expand::JumpPoint);
+        unbounded_test(expand::Bidirectional);
     }
 
-    const GRID_H: &[&str] = &[
-        "..........",
-        "....@.....",
-        "..........",
-        "...###....",
-        "..........",
-        "..##......",
-        "..........",
-        ".....##...",
-        "....*.....",
-        "..........",
-    ];
+    // open in every direction apart from a wall between the start and goal
+    struct Wall;
+
+    impl CanEnter for Wall {
+        fn can_enter(&self, coord: Coord) -> bool {
+            coord.x != 0 || coord.y.abs() > 5
+        }
+    }
 
     #[test]
-    fn grid_h() {
-        test(GRID_H, Some(11));
+    fn unbounded_open() {
+        let mut ctx = Context::new_unbounded();
+        let mut path = Path::default();
+        let (start, goal) = (Coord::new(-30, 0), Coord::new(30, 3));
+        // around the wall's nearer end
+        let expected_len = 60 + 6 + 3;
+        ctx.point_to_point_search_path(expand::Sequential, &Wall, start, goal, &mut path)
+            .unwrap();
+        assert_valid_path(&Wall, &path, start, goal);
+        assert_eq!(path.len(), expected_len);
+        ctx.point_to_point_search_path(expand::JumpPoint, &Wall, start, goal, &mut path)
+            .unwrap();
+        assert_valid_path(&Wall, &path, start, goal);
+        assert_eq!(path.len(), expected_len);
+        ctx.point_to_point_search_path(expand::Bidirectional, &Wall, start, goal, &mut path)
+            .unwrap();
+        assert_valid_path(&Wall, &path, start, goal);
+        assert_eq!(path.len(), expected_len);
+        // jumps far from any obstacle
+        let goal = Coord::new(-500, 400);
+        ctx.point_to_point_search_path(expand::JumpPoint, &Wall, start, goal, &mut path)
+            .unwrap();
+        assert_eq!(path.len() as u32, start.manhattan_distance(goal));
     }
 
-    const GRID_I: &[&str] = &[
-        "..........",
-        "....@.....",
-        "..........",
-        "..........",
-        "...###....",
-        "...#*#....",
-        "...###....",
-        "..........",
-        "..........",
-        "..........",
-    ];
+    // checks every cell covered by the agent
+    struct Footprint<'a> {
+        grid: &'a Grid<Cell>,
+        agent_size: u32,
+    }
+
+    impl<'a> CanEnter for Footprint<'a> {
+        fn can_enter(&self, coord: Coord) -> bool {
+            let search = Search { grid: self.grid };
+            Size::new(self.agent_size, self.agent_size)
+                .coord_iter_row_major()
+                .all(|offset| search.can_enter(coord + offset))
+        }
+    }
 
     #[test]
-    fn grid_i() {
-        test(GRID_I, None);
+    fn clearance_random() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 100;
+        let size = Size::new(20, 20);
+        let mut ctx = Context::new(size);
+        let mut clearance_map = ClearanceMap::new(size);
+        let mut expected_path = Path::default();
+        let mut path = Path::default();
+        for _ in 0..num_tests {
+            let Test { mut grid, .. } = random_test(size, &mut rng);
+            clearance_map.compute(&Search { grid: &grid });
+            // the map is kept up to date as cells change
+            for _ in 0..10 {
+                let coord = Coord::random_within(size, &mut rng);
+                let cell = grid.get_checked_mut(coord);
+                *cell = match cell {
+                    Cell::Solid => Cell::Traversable,
+                    Cell::Traversable => Cell::Solid,
+                };
+                clearance_map.update(&Search { grid: &grid }, coord);
+            }
+            for agent_size in 1..=3 {
+                let footprint = Footprint {
+                    grid: &grid,
+                    agent_size,
+                };
+                let clearance = clearance_map.for_agent(agent_size);
+                assert!(size
+                    .coord_iter_row_major()
+                    .all(|coord| clearance.can_enter(coord) == footprint.can_enter(coord)));
+                let start = Coord::random_within(size, &mut rng);
+                let goal = Coord::random_within(size, &mut rng);
+                let expected =
+                    ctx.point_to_point_search_path(expand::Sequential, &footprint, start, goal, &mut expected_path);
+                let result = ctx.point_to_point_search_path(expand::JumpPoint, &clearance, start, goal, &mut path);
+                assert_eq!(result.is_ok(), expected.is_ok());
+                if result.is_ok() {
+                    assert_valid_path(&footprint, &path, start, goal);
+                    assert_eq!(path.len(), expected_path.len());
+                }
+            }
+        }
     }
 
-    const GRID_J: &[&str] = &[
-        "..........",
-        "....*.....",
-        "..........",
-        "..........",
-        "...###....",
-        "...#@#....",
-        "...###....",
-        "..........",
-        "..........",
-        "..........",
-    ];
+    // an open world whose top row can't be walked along eastwards
+    struct NoEastInTopRow;
 
-    #[test]
-    fn grid_j() {
-        test(GRID_J, None);
+    impl CanEnter for NoEastInTopRow {
+        fn can_enter(&self, _coord: Coord) -> bool {
+            true
+        }
+        fn can_step(&self, step: Step) -> bool {
+            step.to_coord.y != 0 || step.in_direction.to_cardinal_direction() != CardinalDirection::East
+        }
     }
 
-    const GRID_K: &[&str] = &[
-        "..........",
-        ".#####....",
-        ".....#....",
-        ".###.#....",
-        ".###.####.",
-        "....@...#.",
-        ".###.####.",
-        "...#.#....",
-        "..####....",
-        "..#.*.....",
-    ];
+    #[test]
+    fn with_clearance() {
+        let grid = Grid::new_clone(Size::new(5, 3), Cell::Traversable);
+        let mut clearance_map = ClearanceMap::new(grid.size());
+        clearance_map.compute(&Search { grid: &grid });
+        let mut ctx = Context::new(grid.size());
+        let mut path = Path::default();
+        let start = Coord::new(0, 0);
+        for (agent_size, goal) in [(1, Coord::new(4, 0)), (2, Coord::new(3, 0))] {
+            let clearance = clearance_map.for_agent(agent_size);
+            ctx.point_to_point_search_path(expand::Sequential, &clearance, start, goal, &mut path)
+                .unwrap();
+            assert_eq!(path.len(), goal.x as usize);
+            // the detour through the second row must leave room for the agent
+            let world = WithClearance::new(NoEastInTopRow, clearance);
+            ctx.point_to_point_search_path(expand::Sequential, &world, start, goal, &mut path)
+                .unwrap();
+            assert_valid_path(&world, &path, start, goal);
+            assert!(path.iter().all(|node| clearance.can_enter(node.to_coord)));
+            assert_eq!(path.len(), goal.x as usize + 2);
+        }
+        let world = WithClearance::new(NoEastInTopRow, clearance_map.for_agent(3));
+        assert!(ctx
+            .point_to_point_search_path(expand::Sequential, &world, start, Coord::new(2, 0), &mut path)
+            .is_err());
+    }
 
     #[test]
-    fn grid_k() {
-        test(GRID_K, Some(32));
+    fn path_editing() {
+        let grid = str_slice_to_test(&["@.........", ".####.###.", "..........", "...#.....*"]).grid;
+        let search = Search { grid: &grid };
+        let mut ctx = Context::new(grid.size());
+        let (a, b, c) = (Coord::new(0, 0), Coord::new(9, 3), Coord::new(0, 3));
+        let mut route = Path::default();
+        let mut leg = Path::default();
+        ctx.point_to_point_search_path(expand::JumpPoint, &search, a, b, &mut route)
+            .unwrap();
+        ctx.point_to_point_search_path(expand::JumpPoint, &search, b, c, &mut leg)
+            .unwrap();
+        let (a_to_b_len, b_to_c_len) = (route.len(), leg.len());
+        route.append_path(&leg).unwrap();
+        assert_valid_path(&search, &route, a, c);
+        assert_eq!(route.len(), a_to_b_len + b_to_c_len);
+        assert_eq!(
+            leg.append_path(&route),
+            Err(PathError::Discontinuous { end: c, next_start: a })
+        );
+        assert_eq!(leg.len(), b_to_c_len);
+        let (head, tail) = route.split_at(a_to_b_len);
+        assert_valid_path(&search, &head, a, b);
+        assert_valid_path(&search, &tail, b, c);
+        assert_eq!(tail.start(), Some(b));
+        route.reverse();
+        assert_valid_path(&search, &route, c, a);
+        assert_eq!(
+            route.first(),
+            tail.last().map(|node| PathNode {
+                to_coord: route.start().unwrap() + node.in_direction.opposite().coord(),
+                in_direction: node.in_direction.opposite(),
+            })
+        );
+        route.truncate(b_to_c_len);
+        assert_eq!(route.last().map(|node| node.to_coord), Some(b));
+        let directions = route.directions().collect::<Vec<_>>();
+        let rebuilt = Path::from_directions(&search, c, &directions).unwrap();
+        assert_eq!(rebuilt.iter().collect::<Vec<_>>(), route.iter().collect::<Vec<_>>());
+        use CardinalDirection::*;
+        assert_eq!(
+            Path::from_directions(&search, a, &[East, South]).map(|path| path.len()),
+            Err(PathError::Blocked {
+                index: 1,
+                coord: Coord::new(1, 1)
+            })
+        );
     }
 
-    const GRID_L: &[&str] = &[
-        ".#........",
-        ".#...#.*#.",
-        "...#...#..",
-        ".#..#.....",
-        ".......#..",
-        ".#...#.#..",
-        "..#.......",
-        "....#..#..",
-        ".@.......#",
-        "...#..#...",
-    ];
+    fn compact_path_test<E: expand::Expand + Copy>(expand: E, wrap: Wrap) {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let size = Size::new(10, 10);
+        let mut ctx = Context::new(size);
+        ctx.set_wrap(wrap);
+        let mut path = Path::default();
+        let mut compact_path = CompactPath::default();
+        let mut expanded = Path::default();
+        for _ in 0..200 {
+            let Test { grid, start, goal } = random_test(size, &mut rng);
+            let search = Search { grid: &grid };
+            let result = ctx.point_to_point_search_path(expand, &search, start, goal, &mut path);
+            let compact_result =
+                ctx.point_to_point_search_compact_path(expand, &search, start, goal, &mut compact_path);
+            assert_eq!(result, compact_result);
+            if result.is_err() {
+                continue;
+            }
+            assert_eq!(compact_path.len(), path.len());
+            assert_eq!(compact_path.iter().len(), path.len());
+            assert!(compact_path.num_jumps() <= path.len());
+            assert_eq!(compact_path.iter().collect::<Vec<_>>(), path.iter().collect::<Vec<_>>());
+            assert_eq!(compact_path.first(), path.first());
+            assert_eq!(compact_path.last(), path.last());
+            compact_path.to_path(&mut expanded);
+            assert_eq!(expanded.iter().collect::<Vec<_>>(), path.iter().collect::<Vec<_>>());
+            while let Some(node) = compact_path.pop() {
+                assert_eq!(Some(node), path.pop());
+                assert_eq!(compact_path.len(), path.len());
+            }
+            assert!(path.is_empty());
+        }
+    }
 
     #[test]
-    fn grid_l() {
-        test(GRID_L, Some(13));
+    fn compact_path_random() {
+        for wrap in [Wrap::None, Wrap::Both] {
+            compact_path_test(expand::Sequential, wrap);
+            compact_path_test(expand::JumpPoint, wrap);
+            compact_path_test(expand::Bidirectional, wrap);
+        }
     }
 
-    const GRID_M: &[&str] = &[
-        "@......#..",
-        "..........",
-        ".#....##..",
-        "...###....",
-        "..#..#...#",
-        "..#.##..#.",
-        "......#...",
-        "#...#.....",
-        "...#..#...",
-        "....#....*",
-    ];
+    #[test]
+    fn compact_path_open() {
+        let grid = Grid::new_clone(Size::new(100, 100), Cell::Traversable);
+        let search = Search { grid: &grid };
+        let mut ctx = Context::new(grid.size());
+        let mut path = CompactPath::default();
+        ctx.point_to_point_search_compact_path(
+            expand::JumpPoint,
+            &search,
+            Coord::new(0, 50),
+            Coord::new(99, 50),
+            &mut path,
+        )
+        .unwrap();
+        assert_eq!(path.len(), 99);
+        assert_eq!(path.num_jumps(), 1);
+        assert_eq!(path.last().map(|node| node.to_coord), Some(Coord::new(99, 50)));
+    }
 
+    #[cfg(feature = "serialize")]
     #[test]
-    fn grid_m() {
-        test(GRID_M, Some(18));
+    fn compact_path_rejects_empty_jumps() {
+        let mut path = CompactPath::default();
+        path.append(Jump {
+            to_coord: Coord::new(3, 0),
+            in_direction: UNIT_COORDS[0].scale(3),
+        });
+        let json = serde_json::to_string(&path).unwrap();
+        assert!(!json.contains("len"));
+        assert_eq!(serde_json::from_str::<CompactPath>(&json).unwrap().len(), 3);
+        let empty_jump = json.replace(r#""x":3,"y":0}}"#, r#""x":0,"y":0}}"#);
+        assert_ne!(empty_jump, json);
+        assert!(serde_json::from_str::<CompactPath>(&empty_jump).is_err());
     }
 
-    const GRID_N: &[&str] = &[
-        "@#........",
-        "....#..##.",
-        "..#.....#.",
-        "..#.#..##.",
-        "#.........",
-        "...#.#.#..",
-        "...#..#...",
-        "......#..#",
-        ".......#..",
-        ".#.#....#*",
-    ];
+    struct PortalWorld<'a, S> {
+        can_enter: S,
+        // (from_coord, portal)
+        portals: &'a [(Coord, Portal)],
+    }
 
-    #[test]
-    fn grid_n() {
-        test(GRID_N, Some(18));
+    impl<'a, S: CanEnter> CanEnter for PortalWorld<'a, S> {
+        fn can_enter(&self, coord: Coord) -> bool {
+            self.can_enter.can_enter(coord)
+        }
     }
 
-    const GRID_O: &[&str] = &[
-        "@.........",
-        "##...##...",
-        ".....#....",
-        "#..#...#.#",
-        "..##.#....",
-        "...#......",
-        "...#...###",
-        ".......#..",
-        ".....#....",
-        "...#..#.#*",
-    ];
+    impl<'a, S: CanEnter> Portals for PortalWorld<'a, S> {
+        fn for_each_portal<F: FnMut(Portal)>(&self, from_coord: Coord, mut f: F) {
+            for &(coord, portal) in self.portals {
+                if coord == from_coord {
+                    f(portal);
+                }
+            }
+        }
+        fn for_each_portal_in_world<F: FnMut(Coord, Portal)>(&self, mut f: F) {
+            for &(coord, portal) in self.portals {
+                f(coord, portal);
+            }
+        }
+    }
+
+    // returns the cost of the path, checking that each node follows from the previous one
+    fn portal_path_cost<S: CanEnter>(
+        world: &PortalWorld<S>,
+        normalize: impl Fn(Coord) -> Coord,
+        path: &PortalPath,
+        start: Coord,
+        goal: Coord,
+    ) -> u32 {
+        let mut coord = start;
+        let mut cost = 0;
+        for &PortalPathNode { to_coord, via } in path.iter() {
+            match via {
+                Move::Step(direction) => {
+                    assert_eq!(normalize(coord + direction.coord()), to_coord);
+                    cost += 1;
+                }
+                Move::Portal => {
+                    cost += world
+                        .portals
+                        .iter()
+                        .filter(|&&(from_coord, portal)| from_coord == coord && normalize(portal.to_coord) == to_coord)
+                        .map(|(_, portal)| portal.cost)
+                        .min()
+                        .unwrap();
+                }
+            }
+            assert!(to_coord == goal || world.can_enter(to_coord));
+            coord = to_coord;
+        }
+        assert_eq!(coord, goal);
+        cost
+    }
+
+    fn portal_dijkstra<S: CanEnter>(
+        world: &PortalWorld<S>,
+        size: Size,
+        wrap: Wrap,
+        start: Coord,
+        goal: Coord,
+    ) -> Option<u32> {
+        let mut costs = Grid::new_copy(size, None);
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse((0, start.x, start.y)));
+        while let Some(Reverse((cost, x, y))) = queue.pop() {
+            let coord = Coord::new(x, y);
+            let cell = costs.get_checked_mut(coord);
+            if cell.is_some() {
+                continue;
+            }
+            *cell = Some(cost);
+            if coord == goal {
+                return Some(cost);
+            }
+            let mut next = UNIT_COORDS
+                .iter()
+                .map(|direction| (coord + direction.to_coord(), 1))
+                .collect::<Vec<_>>();
+            world.for_each_portal(coord, |portal| next.push((portal.to_coord, portal.cost)));
+            for (to_coord, step_cost) in next {
+                let to_coord = wrap.normalize(to_coord, size);
+                if costs.get(to_coord).is_some() && (to_coord == goal || world.can_enter(to_coord)) {
+                    queue.push(Reverse((cost + step_cost, to_coord.x, to_coord.y)));
+                }
+            }
+        }
+        None
+    }
 
     #[test]
-    fn grid_o() {
-        test(GRID_O, Some(18));
+    fn portal_teleporter() {
+        // two rooms with no door between them
+        let grid = Grid::new_fn(Size::new(11, 3), |coord| {
+            if coord.x == 5 {
+                Cell::Solid
+            } else {
+                Cell::Traversable
+            }
+        });
+        let portals = [(
+            Coord::new(1, 1),
+            Portal {
+                to_coord: Coord::new(9, 1),
+                cost: 3,
+            },
+        )];
+        let world = PortalWorld {
+            can_enter: Search { grid: &grid },
+            portals: &portals,
+        };
+        let mut ctx = Context::new(grid.size());
+        let mut path = PortalPath::default();
+        let mut stats = SearchStats::default();
+        let (start, goal) = (Coord::new(0, 0), Coord::new(10, 2));
+        ctx.portal_search_path_with_observer(&world, start, goal, &mut path, &mut stats)
+            .unwrap();
+        assert_eq!(portal_path_cost(&world, |coord| coord, &path, start, goal), 2 + 3 + 2);
+        assert_eq!(stats.path_cost, Some(2 + 3 + 2));
+        assert_eq!(path.iter().filter(|node| node.via == Move::Portal).count(), 1);
+        assert_eq!(
+            ctx.portal_search_first(&world, Coord::new(1, 1), goal),
+            Ok(Some(PortalPathNode {
+                to_coord: Coord::new(9, 1),
+                via: Move::Portal
+            }))
+        );
+        // portals are one way
+        assert_eq!(
+            ctx.portal_search_path(&world, goal, start, &mut path),
+            Err(SearchError::GoalUnreachable {
+                closest: Coord::new(6, 0),
+                num_reachable: 15
+            })
+        );
+        let world = PortalWorld {
+            can_enter: Search { grid: &grid },
+            portals: &[],
+        };
+        assert!(ctx.portal_search_path(&world, start, goal, &mut path).is_err());
     }
 
     #[test]
-    fn grid_random() {
+    fn portal_random() {
         let mut rng = Isaac64Rng::seed_from_u64(0);
-        let num_tests = 1000;
-        let size = Size::new(10, 10);
-        let mut ctx = Context::new(size);
-        let mut path = Path::default();
-        for _ in 0..num_tests {
-            let Test { grid, start, goal } = random_test(size, &mut rng);
-            let seq_result =
-                ctx.point_to_point_search_path(expand::Sequential, &Search { grid: &grid }, start, goal, &mut path);
-            let seq_len = path.len();
-            let jps_result =
-                ctx.point_to_point_search_path(expand::JumpPoint, &Search { grid: &grid }, start, goal, &mut path);
-            let jps_len = path.len();
-            let test = Test { grid, start, goal };
-            if seq_result != jps_result || seq_len != jps_len {
-                print_test(&test);
+        let num_tests = 200;
+        let size = Size::new(20, 20);
+        let mut path = PortalPath::default();
+        let mut sequential_path = Path::default();
+        for wrap in [Wrap::None, Wrap::X, Wrap::Y, Wrap::Both] {
+            let mut ctx = Context::new(size);
+            ctx.set_wrap(wrap);
+            for i in 0..num_tests {
+                let Test { grid, .. } = random_test(size, &mut rng);
+                let portals = (0..i % 6)
+                    .map(|_| {
+                        let from_coord = Coord::random_within(size, &mut rng);
+                        let portal = Portal {
+                            to_coord: Coord::random_within(size, &mut rng),
+                            cost: rng.gen_range(0..10),
+                        };
+                        (from_coord, portal)
+                    })
+                    .collect::<Vec<_>>();
+                let world = PortalWorld {
+                    can_enter: Search { grid: &grid },
+                    portals: &portals,
+                };
+                let start = Coord::random_within(size, &mut rng);
+                let goal = Coord::random_within(size, &mut rng);
+                let result = ctx.portal_search_path(&world, start, goal, &mut path);
+                let expected = portal_dijkstra(&world, size, wrap, start, goal);
+                assert_eq!(result.is_ok(), expected.is_some());
+                if result.is_ok() {
+                    let normalize = |coord| wrap.normalize(coord, size);
+                    assert_eq!(Some(portal_path_cost(&world, normalize, &path, start, goal)), expected);
+                }
+                if portals.is_empty() {
+                    let sequential =
+                        ctx.point_to_point_search_path(expand::Sequential, &world, start, goal, &mut sequential_path);
+                    assert_eq!(result.is_ok(), sequential.is_ok());
+                    if result.is_ok() {
+                        assert_eq!(path.len(), sequential_path.len());
+                    }
+                }
             }
-            assert_eq!(seq_result, jps_result);
-            assert_eq!(seq_len, jps_len);
         }
     }
+
+    #[test]
+    fn portal_unbounded() {
+        let mut ctx = Context::new_unbounded();
+        let mut path = PortalPath::default();
+        let portals = [(
+            Coord::new(-1, 0),
+            Portal {
+                to_coord: Coord::new(1, 0),
+                cost: 2,
+            },
+        )];
+        let world = PortalWorld {
+            can_enter: Wall,
+            portals: &portals,
+        };
+        let (start, goal) = (Coord::new(-30, 0), Coord::new(30, 3));
+        ctx.portal_search_path(&world, start, goal, &mut path).unwrap();
+        // through the wall rather than around it
+        assert_eq!(
+            portal_path_cost(&world, |coord| coord, &path, start, goal),
+            29 + 2 + 29 + 3
+        );
+    }
 }
@@ -0,0 +1,190 @@
+use crate::{
+    goal::Goal,
+    private_expand::{Profiler, Stop},
+    CanEnter, Coord, Observer, SearchError, Size, Step, Wrap,
+};
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub top_left: Coord,
+    pub size: Size,
+}
+
+impl Rect {
+    pub fn new(top_left: Coord, size: Size) -> Self {
+        Self { top_left, size }
+    }
+
+    pub fn contains(&self, coord: Coord) -> bool {
+        self.size.is_valid(coord - self.top_left)
+    }
+}
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub max_expansions: Option<u64>,
+    pub max_path_cost: Option<u32>,
+    /// The search won't enter any cell outside this rectangle
+    pub bounds: Option<Rect>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Expansions,
+    PathCost,
+    Bounds,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimitedSearchError {
+    Search(SearchError),
+    /// The search was cut short by `limit`. `closest` is the expanded cell nearest to the goal.
+    LimitReached {
+        limit: Limit,
+        closest: Coord,
+    },
+}
+
+pub(crate) struct Limiter<'a> {
+    limits: &'a SearchLimits,
+    goal: Coord,
+    // the grid the search runs on, if it's bounded, so distances can be measured across its edges
+    grid: Option<(Wrap, Size)>,
+    expansions: u64,
+    closest: Coord,
+    closest_distance: u32,
+    limit_reached: Option<Limit>,
+}
+
+impl<'a> Limiter<'a> {
+    pub(crate) fn new(limits: &'a SearchLimits, start: Coord, goal: Coord, wrap: Wrap, bounds: Option<Size>) -> Self {
+        let mut limiter = Self {
+            limits,
+            goal,
+            grid: bounds.map(|size| (wrap, size)),
+            expansions: 0,
+            closest: start,
+            closest_distance: 0,
+            limit_reached: None,
+        };
+        limiter.closest_distance = limiter.distance_to_goal(start);
+        limiter
+    }
+
+    fn distance_to_goal(&self, coord: Coord) -> u32 {
+        match self.grid {
+            Some((wrap, size)) => wrap.manhattan_distance(coord, self.goal, size),
+            None => coord.manhattan_distance(self.goal),
+        }
+    }
+
+    pub(crate) fn closest(&self) -> Coord {
+        self.closest
+    }
+
+    pub(crate) fn limit_reached(&self) -> Option<Limit> {
+        self.limit_reached
+    }
+
+    pub(crate) fn set_limit_reached(&mut self, limit: Limit) {
+        self.limit_reached = Some(limit);
+    }
+
+    fn check(&mut self, min_path_cost: u32) -> Option<Stop> {
+        if self.limits.max_path_cost.is_some_and(|max| min_path_cost > max) {
+            self.limit_reached = Some(Limit::PathCost);
+            return Some(Stop);
+        }
+        if self.limits.max_expansions.is_some_and(|max| self.expansions >= max) {
+            self.limit_reached = Some(Limit::Expansions);
+            return Some(Stop);
+        }
+        self.expansions += 1;
+        None
+    }
+}
+
+impl<'a> Observer for Limiter<'a> {}
+
+impl<'a> Profiler for Limiter<'a> {
+    fn before_expand(&mut self, coord: Coord, _cost: u32, min_path_cost: u32) -> Option<Stop> {
+        if let Some(Stop) = self.check(min_path_cost) {
+            return Some(Stop);
+        }
+        let distance = self.distance_to_goal(coord);
+        if distance < self.closest_distance {
+            self.closest = coord;
+            self.closest_distance = distance;
+        }
+        None
+    }
+
+    fn before_expand_reverse(&mut self, min_path_cost: u32) -> Option<Stop> {
+        self.check(min_path_cost)
+    }
+}
+
+// Treats every cell outside `bounds` as a wall, remembering whether this hid an enterable cell
+pub(crate) struct Bounded<'a, S> {
+    can_enter: &'a S,
+    bounds: Rect,
+    left_bounds: Cell<bool>,
+}
+
+impl<'a, S: CanEnter> Bounded<'a, S> {
+    pub(crate) fn new(can_enter: &'a S, bounds: Rect) -> Self {
+        Self {
+            can_enter,
+            bounds,
+            left_bounds: Cell::new(false),
+        }
+    }
+
+    pub(crate) fn left_bounds(&self) -> bool {
+        self.left_bounds.get()
+    }
+
+    fn check(&self, coord: Coord, can_enter: bool) -> bool {
+        if !can_enter {
+            return false;
+        }
+        if self.bounds.contains(coord) {
+            true
+        } else {
+            self.left_bounds.set(true);
+            false
+        }
+    }
+}
+
+impl<'a, S: CanEnter> CanEnter for Bounded<'a, S> {
+    fn can_enter(&self, coord: Coord) -> bool {
+        self.check(coord, self.can_enter.can_enter(coord))
+    }
+    fn can_step(&self, step: Step) -> bool {
+        self.check(step.to_coord, self.can_enter.can_step(step))
+    }
+}
+
+// A goal outside the bounds can't be reached, but still guides the search towards it
+pub(crate) struct OutOfBounds(pub(crate) Coord);
+
+impl Goal for OutOfBounds {
+    fn is_goal(&self, _coord: Coord) -> bool {
+        false
+    }
+    fn heuristic(&self, coord: Coord) -> u32 {
+        self.0.heuristic(coord)
+    }
+    fn coords(&self) -> &[Coord] {
+        self.0.coords()
+    }
+    fn stop_when_visited(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,411 @@
+use crate::{
+    goal::NoGoal,
+    has_forced_neighbour,
+    private_expand::{Profiler, Stop},
+    CanEnter, CardinalDirection, Context, Coord, NoPath, Node, Observer, Path, SearchError, Size, Step, UnitCoord,
+    Wrap,
+};
+use grid_2d::Grid;
+use grid_search_cardinal_common::{coord::UNIT_COORDS, seen_set::Visit, step::Jump};
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stopped {
+    // the cell at `distance` can't be entered
+    Wall,
+    JumpPoint,
+    // the scan to the left of the cell at `distance` reaches a jump point
+    SideJumpPoint,
+}
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug)]
+struct Scan {
+    distance: u32,
+    stopped: Stopped,
+}
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug)]
+struct Cell {
+    can_enter: bool,
+    // indexed by the direction of a step into this cell
+    can_step: [bool; 4],
+    // indexed by the direction of the scan. `straight` scans only look ahead, while `jump` scans
+    // also look to the left of each cell as `Context::consider_jps` does.
+    straight: [Scan; 4],
+    jump: [Scan; 4],
+}
+
+const NO_SCAN: Scan = Scan {
+    distance: 0,
+    stopped: Stopped::Wall,
+};
+
+fn index(direction: UnitCoord) -> usize {
+    direction.to_cardinal_direction() as usize
+}
+
+fn dot(a: Coord, b: Coord) -> i32 {
+    a.x * b.x + a.y * b.y
+}
+
+/// Jump distances from each cell in each direction, precomputed for a grid that doesn't change.
+/// Searches using this find paths of the same length as `expand::JumpPoint`.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug)]
+pub struct PreprocessedGrid {
+    grid: Grid<Cell>,
+}
+
+impl PreprocessedGrid {
+    pub fn new<S: CanEnter>(can_enter: &S, size: Size) -> Self {
+        let mut grid = Grid::new_fn(size, |coord| {
+            let mut can_step = [false; 4];
+            for &in_direction in &UNIT_COORDS {
+                can_step[index(in_direction)] = can_enter.can_step(Step {
+                    to_coord: coord,
+                    in_direction,
+                });
+            }
+            Cell {
+                can_enter: can_enter.can_enter(coord),
+                can_step,
+                straight: [NO_SCAN; 4],
+                jump: [NO_SCAN; 4],
+            }
+        });
+        for &direction in &UNIT_COORDS {
+            for coord in coords_against(size, direction) {
+                let step = Step {
+                    to_coord: coord + direction.to_coord(),
+                    in_direction: direction,
+                };
+                let scan = if !can_enter.can_step(step) || !size.is_valid(step.to_coord) {
+                    Scan {
+                        distance: 1,
+                        stopped: Stopped::Wall,
+                    }
+                } else if has_forced_neighbour(can_enter, step, &NoGoal, |coord| coord) {
+                    Scan {
+                        distance: 1,
+                        stopped: Stopped::JumpPoint,
+                    }
+                } else {
+                    let next = grid.get_checked(step.to_coord).straight[index(direction)];
+                    Scan {
+                        distance: next.distance + 1,
+                        ..next
+                    }
+                };
+                grid.get_checked_mut(coord).straight[index(direction)] = scan;
+            }
+        }
+        for &direction in &UNIT_COORDS {
+            for coord in coords_against(size, direction) {
+                let to_coord = coord + direction.to_coord();
+                let straight = grid.get_checked(coord).straight[index(direction)];
+                let scan = if straight.distance == 1 {
+                    straight
+                } else if grid.get_checked(to_coord).straight[index(direction.left90())].stopped == Stopped::JumpPoint {
+                    Scan {
+                        distance: 1,
+                        stopped: Stopped::SideJumpPoint,
+                    }
+                } else {
+                    let next = grid.get_checked(to_coord).jump[index(direction)];
+                    Scan {
+                        distance: next.distance + 1,
+                        ..next
+                    }
+                };
+                grid.get_checked_mut(coord).jump[index(direction)] = scan;
+            }
+        }
+        Self { grid }
+    }
+
+    pub fn size(&self) -> Size {
+        self.grid.size()
+    }
+
+    fn scan_covers(&self, from_coord: Coord, direction: UnitCoord, jump: Scan, coord: Coord) -> bool {
+        let delta = coord - from_coord;
+        let forward = dot(delta, direction.to_coord());
+        let left = dot(delta, direction.left90().to_coord());
+        if forward < 1 || forward as u32 > jump.distance || left < 0 {
+            return false;
+        }
+        if left == 0 {
+            return true;
+        }
+        // the scan to the left of the last cell only happens if the jump didn't stop there
+        let last_side_scan = match jump.stopped {
+            Stopped::SideJumpPoint => jump.distance,
+            Stopped::Wall | Stopped::JumpPoint => jump.distance - 1,
+        };
+        if forward as u32 > last_side_scan {
+            return false;
+        }
+        let side_coord = from_coord + direction.to_coord() * forward;
+        let side = self.grid.get_checked(side_coord).straight[index(direction.left90())];
+        left as u32 <= side.distance
+    }
+
+    // The precomputed jumps ignore the goal, so jumps that could pass the goal or any cell
+    // which only has a forced neighbour because of the goal are scanned as normal
+    fn goal_affects_jump(&self, from_coord: Coord, direction: UnitCoord, jump: Scan, goal: Coord) -> bool {
+        let delta = goal - from_coord;
+        let forward = dot(delta, direction.to_coord());
+        let left = dot(delta, direction.left90().to_coord());
+        // quickly rule out goals which aren't next to the strip of cells that could be scanned
+        if forward < 0 || forward > jump.distance as i32 + 1 || left < -1 {
+            return false;
+        }
+        self.scan_covers(from_coord, direction, jump, goal)
+            || UNIT_COORDS
+                .iter()
+                .any(|&neighbour| self.scan_covers(from_coord, direction, jump, goal + neighbour.to_coord()))
+    }
+}
+
+impl CanEnter for PreprocessedGrid {
+    fn can_enter(&self, coord: Coord) -> bool {
+        self.grid.get(coord).is_some_and(|cell| cell.can_enter)
+    }
+    fn can_step(&self, step: Step) -> bool {
+        self.grid
+            .get(step.to_coord)
+            .is_some_and(|cell| cell.can_step[index(step.in_direction)])
+    }
+}
+
+// Visits coords such that each is visited after its neighbour in `direction`
+fn coords_against(size: Size, direction: UnitCoord) -> impl Iterator<Item = Coord> {
+    let mut coords = size.coord_iter_row_major().collect::<Vec<_>>();
+    coords.sort_by_key(|&coord| -dot(coord, direction.to_coord()));
+    coords.into_iter()
+}
+
+impl Context {
+    fn consider_jps_preprocessed<R: Profiler>(
+        &mut self,
+        preprocessed_grid: &PreprocessedGrid,
+        step: Step,
+        cost: u32,
+        goal: Coord,
+        profiler: &mut R,
+    ) -> Option<Stop> {
+        let from_coord = step.from_coord();
+        let direction = step.in_direction;
+        let jump = preprocessed_grid.grid.get_checked(from_coord).jump[index(direction)];
+        if preprocessed_grid.goal_affects_jump(from_coord, direction, jump, goal) {
+            return self.consider_jps(preprocessed_grid, step, cost, &goal, profiler);
+        }
+        let jump_cost = jump.distance;
+        let step = Step {
+            to_coord: from_coord + direction.to_coord() * jump_cost as i32,
+            in_direction: direction,
+        };
+        match jump.stopped {
+            Stopped::Wall => return None,
+            Stopped::JumpPoint => (),
+            Stopped::SideJumpPoint => {
+                let side_direction = direction.left90();
+                let side_jump_cost =
+                    preprocessed_grid.grid.get_checked(step.to_coord).straight[index(side_direction)].distance;
+                let side_step = Step {
+                    to_coord: step.to_coord + side_direction.to_coord() * side_jump_cost as i32,
+                    in_direction: side_direction,
+                };
+                let jump_to_side_jump_point = Jump {
+                    in_direction: side_direction.scale(side_jump_cost),
+                    to_coord: side_step.to_coord,
+                };
+                let cost = cost + jump_cost + side_jump_cost;
+                if let Some(Visit) = self.seen_set.try_visit_jump(jump_to_side_jump_point, cost) {
+                    profiler.jump(from_coord, step.to_coord);
+                    profiler.jump(step.to_coord, side_step.to_coord);
+                    profiler.visit(side_step.to_coord);
+                    let node = Node {
+                        key: (cost + side_step.to_coord.manhattan_distance(goal), Reverse(cost)),
+                        value: side_step,
+                    };
+                    self.push(node, profiler);
+                }
+            }
+        }
+        let cost = cost + jump_cost;
+        if let Some(Visit) = self.seen_set.try_visit_jump(step.scale_back(jump_cost), cost) {
+            profiler.jump(from_coord, step.to_coord);
+            profiler.visit(step.to_coord);
+            let node = Node {
+                key: (cost + step.to_coord.manhattan_distance(goal), Reverse(cost)),
+                value: step,
+            };
+            self.push(node, profiler);
+        }
+        None
+    }
+
+    fn preprocessed_search_core<R: Profiler>(
+        &mut self,
+        preprocessed_grid: &PreprocessedGrid,
+        start: Coord,
+        goal: Coord,
+        profiler: &mut R,
+    ) -> Result<(), NoPath> {
+        self.init_starts(&[start]);
+        self.priority_queue.clear();
+        self.reached_coord = goal;
+        if start == goal {
+            return Ok(());
+        }
+        for &in_direction in &UNIT_COORDS {
+            let to_coord = start + in_direction.to_coord();
+            let step = Step { to_coord, in_direction };
+            if let Some(Stop) = self.consider_jps_preprocessed(preprocessed_grid, step, 0, goal, profiler) {
+                return Ok(());
+            }
+        }
+        while let Some(Node {
+            key: (_, Reverse(cost)),
+            value: step,
+        }) = self.priority_queue.pop()
+        {
+            profiler.expand(step.to_coord);
+            for step in [step.forward(), step.left(), step.right()] {
+                if let Some(Stop) = self.consider_jps_preprocessed(preprocessed_grid, step, cost, goal, profiler) {
+                    return Ok(());
+                }
+            }
+        }
+        Err(NoPath)
+    }
+
+    fn preprocessed_search_checked<O: Observer>(
+        &mut self,
+        preprocessed_grid: &PreprocessedGrid,
+        start: Coord,
+        goal: Coord,
+        mut observer: &mut O,
+    ) -> Result<(), SearchError> {
+        SearchError::check_size(self.seen_set.size(), preprocessed_grid.size())?;
+        SearchError::check_bounds(self.seen_set.size(), &[start], &[goal])?;
+        // the jump distances stop at the edges of the grid
+        if self.wrap != Wrap::None {
+            return Err(SearchError::WrapUnsupported);
+        }
+        self.preprocessed_search_core(preprocessed_grid, start, goal, &mut observer)
+            .map_err(|NoPath| self.goal_unreachable(&[start], &[goal]))?;
+        observer.path_cost(self.seen_set.cost(goal).unwrap_or(0));
+        Ok(())
+    }
+
+    pub fn preprocessed_search_path(
+        &mut self,
+        preprocessed_grid: &PreprocessedGrid,
+        start: Coord,
+        goal: Coord,
+        path: &mut Path,
+    ) -> Result<(), SearchError> {
+        self.preprocessed_search_path_with_observer(preprocessed_grid, start, goal, path, &mut ())
+    }
+
+    pub fn preprocessed_search_path_with_observer<O: Observer>(
+        &mut self,
+        preprocessed_grid: &PreprocessedGrid,
+        start: Coord,
+        goal: Coord,
+        path: &mut Path,
+        observer: &mut O,
+    ) -> Result<(), SearchError> {
+        self.preprocessed_search_checked(preprocessed_grid, start, goal, observer)?;
+        self.seen_set.build_path_to(goal, path);
+        Ok(())
+    }
+
+    pub fn preprocessed_search_first(
+        &mut self,
+        preprocessed_grid: &PreprocessedGrid,
+        start: Coord,
+        goal: Coord,
+    ) -> Result<Option<CardinalDirection>, SearchError> {
+        self.preprocessed_search_first_with_observer(preprocessed_grid, start, goal, &mut ())
+    }
+
+    pub fn preprocessed_search_first_with_observer<O: Observer>(
+        &mut self,
+        preprocessed_grid: &PreprocessedGrid,
+        start: Coord,
+        goal: Coord,
+        observer: &mut O,
+    ) -> Result<Option<CardinalDirection>, SearchError> {
+        self.preprocessed_search_checked(preprocessed_grid, start, goal, observer)?;
+        Ok(self.seen_set.first_direction_towards(goal))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_world::{random_grid, Search};
+    use crate::{expand, SearchStats};
+    use rand::SeedableRng;
+    use rand_isaac::Isaac64Rng;
+
+    #[test]
+    fn random_grids() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_grids = 100;
+        let num_tests = 20;
+        let size = Size::new(20, 20);
+        let mut ctx = Context::new(size);
+        let mut path = Path::default();
+        let mut expected_path = Path::default();
+        for _ in 0..num_grids {
+            let grid = random_grid(size, 4, &mut rng);
+            let search = Search { grid: &grid };
+            let preprocessed_grid = PreprocessedGrid::new(&search, size);
+            for _ in 0..num_tests {
+                let start = Coord::random_within(size, &mut rng);
+                let goal = Coord::random_within(size, &mut rng);
+                let expected =
+                    ctx.point_to_point_search_path(expand::JumpPoint, &search, start, goal, &mut expected_path);
+                let mut stats = SearchStats::default();
+                let result =
+                    ctx.preprocessed_search_path_with_observer(&preprocessed_grid, start, goal, &mut path, &mut stats);
+                assert_eq!(result, expected);
+                assert_eq!(path.len(), expected_path.len());
+                assert_eq!(stats.path_cost, result.ok().map(|()| path.len() as u32));
+                assert!(stats.nodes_pushed >= stats.nodes_expanded);
+                if result.is_ok() {
+                    let mut coord = start;
+                    for node in path.iter() {
+                        assert_eq!(node.to_coord - node.in_direction.coord(), coord);
+                        assert!(node.to_coord == goal || search.can_enter(node.to_coord));
+                        coord = node.to_coord;
+                    }
+                    assert_eq!(coord, goal);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn wrapped_context() {
+        let grid = Grid::new_copy(Size::new(10, 3), true);
+        let search = Search { grid: &grid };
+        let preprocessed_grid = PreprocessedGrid::new(&search, grid.size());
+        let mut ctx = Context::new(grid.size());
+        ctx.set_wrap(Wrap::X);
+        let mut path = Path::default();
+        assert_eq!(
+            ctx.preprocessed_search_path(&preprocessed_grid, Coord::new(1, 1), Coord::new(8, 1), &mut path),
+            Err(SearchError::WrapUnsupported)
+        );
+    }
+}
@@ -0,0 +1,26 @@
+use crate::{CanEnter, CanEnterAt, Coord, Size};
+use grid_2d::Grid;
+use rand::Rng;
+
+// cells which are `true` can be entered
+pub struct Search<'a> {
+    pub grid: &'a Grid<bool>,
+}
+
+impl<'a> CanEnter for Search<'a> {
+    fn can_enter(&self, coord: Coord) -> bool {
+        self.grid.get(coord).cloned().unwrap_or(false)
+    }
+}
+
+// the cells never change
+impl<'a> CanEnterAt for Search<'a> {
+    fn can_enter_at(&self, coord: Coord, _time: u32) -> bool {
+        self.can_enter(coord)
+    }
+}
+
+// each cell is solid with probability 1 / `solid_one_in`
+pub fn random_grid<R: Rng>(size: Size, solid_one_in: u32, rng: &mut R) -> Grid<bool> {
+    Grid::new_fn(size, |_| rng.gen_range(0..solid_one_in) != 0)
+}
@@ -0,0 +1,310 @@
+use crate::{CanEnterAt, Coord, Observer, PathNode, SearchError, Size, Step, TimedPath, TimedPathNode, UNIT_COORDS};
+use grid_search_cardinal_common::min_heap::MinHeapNode;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+// earliest arrival time plus heuristic first, breaking ties in favour of the node closest to the
+// goal
+type Node = MinHeapNode<(u32, u32), (Coord, u32)>;
+
+/// Searches over (coord, time) pairs, so that obstacles can follow a schedule. Each step or wait
+/// takes one tick, and the path arriving at the goal earliest is found.
+pub struct TimedContext {
+    size: Size,
+    // maps each (coord, time) reached to the coord at the previous tick
+    seen: HashMap<(Coord, u32), Coord>,
+    reached: HashSet<Coord>,
+    priority_queue: BinaryHeap<Node>,
+}
+
+impl TimedContext {
+    pub fn new(size: Size) -> Self {
+        Self {
+            size,
+            seen: HashMap::new(),
+            reached: HashSet::new(),
+            priority_queue: BinaryHeap::new(),
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    fn visit<O: Observer>(&mut self, coord: Coord, time: u32, parent: Coord, goal: Coord, observer: &mut O) {
+        if self.seen.contains_key(&(coord, time)) {
+            return;
+        }
+        self.seen.insert((coord, time), parent);
+        self.reached.insert(coord);
+        observer.visit(coord);
+        let heuristic = coord.manhattan_distance(goal);
+        observer.push(coord);
+        self.priority_queue.push(Node {
+            key: (time + heuristic, heuristic),
+            value: (coord, time),
+        });
+        observer.queue_len(self.priority_queue.len());
+    }
+
+    fn search_core<S: CanEnterAt, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        start_time: u32,
+        goal: Coord,
+        max_time: u32,
+        observer: &mut O,
+    ) -> Result<u32, SearchError> {
+        self.seen.clear();
+        self.reached.clear();
+        self.priority_queue.clear();
+        self.visit(start, start_time, start, goal, observer);
+        let mut closest = start;
+        let mut horizon_reached = false;
+        while let Some(Node {
+            value: (coord, time), ..
+        }) = self.priority_queue.pop()
+        {
+            if coord == goal {
+                return Ok(time);
+            }
+            observer.expand(coord);
+            if coord.manhattan_distance(goal) < closest.manhattan_distance(goal) {
+                closest = coord;
+            }
+            if time == max_time {
+                horizon_reached = true;
+                continue;
+            }
+            if can_enter.can_enter_at(coord, time + 1) {
+                self.visit(coord, time + 1, coord, goal, observer);
+            }
+            for &in_direction in &UNIT_COORDS {
+                let step = Step {
+                    to_coord: coord + in_direction.to_coord(),
+                    in_direction,
+                };
+                if self.size.is_valid(step.to_coord) && can_enter.can_step_at(step, time + 1) {
+                    self.visit(step.to_coord, time + 1, coord, goal, observer);
+                }
+            }
+        }
+        if horizon_reached {
+            return Err(SearchError::HorizonExceeded { closest });
+        }
+        Err(SearchError::GoalUnreachable {
+            closest,
+            num_reachable: self.reached.len() as u64,
+        })
+    }
+
+    /// Finds the path from `start` at tick `start_time` which arrives at `goal` earliest, taking
+    /// at most `horizon` ticks. Unlike searches through static worlds, the goal must be
+    /// enterable at the arrival tick. If there is no such path, the error is
+    /// `HorizonExceeded` if a longer horizon might have found one, and `GoalUnreachable` otherwise.
+    pub fn timed_search_path<S: CanEnterAt>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        start_time: u32,
+        goal: Coord,
+        horizon: u32,
+        path: &mut TimedPath,
+    ) -> Result<(), SearchError> {
+        self.timed_search_path_with_observer(can_enter, start, start_time, goal, horizon, path, &mut ())
+    }
+
+    /// The path cost given to the observer is the number of ticks taken to reach the goal,
+    /// including waits
+    #[allow(clippy::too_many_arguments)]
+    pub fn timed_search_path_with_observer<S: CanEnterAt, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        start: Coord,
+        start_time: u32,
+        goal: Coord,
+        horizon: u32,
+        path: &mut TimedPath,
+        observer: &mut O,
+    ) -> Result<(), SearchError> {
+        SearchError::check_bounds(self.size, &[start], &[goal])?;
+        let max_time = start_time.saturating_add(horizon);
+        let mut time = self.search_core(can_enter, start, start_time, goal, max_time, observer)?;
+        observer.path_cost(time - start_time);
+        path.clear();
+        let mut coord = goal;
+        while time > start_time {
+            let parent = self.seen[&(coord, time)];
+            if parent != coord {
+                let in_direction = UNIT_COORDS
+                    .iter()
+                    .find(|unit_coord| parent + unit_coord.to_coord() == coord)
+                    .unwrap()
+                    .to_cardinal_direction();
+                path.prepend(TimedPathNode {
+                    node: PathNode {
+                        to_coord: coord,
+                        in_direction,
+                    },
+                    time,
+                });
+            }
+            coord = parent;
+            time -= 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_world::{random_grid, Search};
+    use crate::{expand, CanEnter, Context, Path, SearchStats};
+    use grid_2d::Grid;
+    use rand::{Rng, SeedableRng};
+    use rand_isaac::Isaac64Rng;
+
+    struct Gate {
+        grid: Grid<bool>,
+        gate: Coord,
+        opens_at: u32,
+    }
+
+    impl CanEnterAt for Gate {
+        fn can_enter_at(&self, coord: Coord, time: u32) -> bool {
+            Search { grid: &self.grid }.can_enter(coord) && (coord != self.gate || time >= self.opens_at)
+        }
+    }
+
+    // a guard walking back and forth along a row
+    struct Patrol {
+        grid: Grid<bool>,
+        row: i32,
+        width: i32,
+    }
+
+    impl Patrol {
+        fn guard(&self, time: u32) -> Coord {
+            let period = 2 * (self.width - 1);
+            let offset = time as i32 % period;
+            let x = if offset < self.width { offset } else { period - offset };
+            Coord::new(x, self.row)
+        }
+    }
+
+    impl CanEnterAt for Patrol {
+        fn can_enter_at(&self, coord: Coord, time: u32) -> bool {
+            Search { grid: &self.grid }.can_enter(coord) && coord != self.guard(time)
+        }
+        fn can_step_at(&self, step: Step, time: u32) -> bool {
+            // don't walk through the guard coming the other way
+            let from_coord = step.to_coord - step.in_direction.to_coord();
+            let swapped = time > 0 && step.to_coord == self.guard(time - 1) && from_coord == self.guard(time);
+            self.can_enter_at(step.to_coord, time) && !swapped
+        }
+    }
+
+    fn assert_path<S: CanEnterAt>(can_enter: &S, path: &TimedPath, start: Coord, start_time: u32, goal: Coord) {
+        let (mut coord, mut time) = (start, start_time);
+        for &TimedPathNode { node, time: arrival } in path.iter() {
+            assert_eq!(node.to_coord - node.in_direction.coord(), coord);
+            assert!(arrival > time);
+            for wait in time + 1..arrival {
+                assert!(can_enter.can_enter_at(coord, wait));
+            }
+            let step = Step {
+                to_coord: node.to_coord,
+                in_direction: crate::UnitCoord::from_cardinal_direction(node.in_direction),
+            };
+            assert!(can_enter.can_step_at(step, arrival));
+            coord = node.to_coord;
+            time = arrival;
+        }
+        assert_eq!(coord, goal);
+    }
+
+    #[test]
+    fn gate() {
+        let gate = Gate {
+            grid: Grid::new_copy(Size::new(5, 1), true),
+            gate: Coord::new(2, 0),
+            opens_at: 6,
+        };
+        let mut ctx = TimedContext::new(gate.grid.size());
+        let mut path = TimedPath::default();
+        let (start, goal) = (Coord::new(0, 0), Coord::new(4, 0));
+        let mut stats = SearchStats::default();
+        ctx.timed_search_path_with_observer(&gate, start, 0, goal, 20, &mut path, &mut stats)
+            .unwrap();
+        assert_path(&gate, &path, start, 0, goal);
+        assert_eq!(path.len(), 4);
+        assert_eq!(path.arrival_time(), Some(8));
+        assert_eq!(stats.path_cost, Some(8));
+        assert!(stats.nodes_expanded > 0);
+        ctx.timed_search_path(&gate, start, 10, goal, 20, &mut path).unwrap();
+        assert_eq!(path.arrival_time(), Some(14));
+        assert_eq!(
+            ctx.timed_search_path(&gate, start, 0, goal, 7, &mut path),
+            Err(SearchError::HorizonExceeded {
+                closest: Coord::new(3, 0)
+            })
+        );
+    }
+
+    #[test]
+    fn patrol() {
+        // the guard patrols the only corridor connecting the top and bottom rows, which has an
+        // alcove to wait in while the guard passes
+        let grid = Grid::new_fn(Size::new(6, 3), |coord| {
+            coord.y == 1 || coord.x == 0 || coord.x == 5 || coord == Coord::new(3, 0)
+        });
+        let patrol = Patrol { grid, row: 1, width: 6 };
+        let mut ctx = TimedContext::new(patrol.grid.size());
+        let mut path = TimedPath::default();
+        for start_time in 0..10 {
+            let (start, goal) = (Coord::new(0, 0), Coord::new(5, 2));
+            ctx.timed_search_path(&patrol, start, start_time, goal, 50, &mut path)
+                .unwrap();
+            assert_path(&patrol, &path, start, start_time, goal);
+        }
+    }
+
+    #[test]
+    fn random_grids() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 200;
+        let size = Size::new(20, 20);
+        let mut ctx = Context::new(size);
+        let mut timed_ctx = TimedContext::new(size);
+        let mut expected_path = Path::default();
+        let mut path = TimedPath::default();
+        for _ in 0..num_tests {
+            let grid = random_grid(size, 4, &mut rng);
+            let search = Search { grid: &grid };
+            let start = Coord::random_within(size, &mut rng);
+            let goal = Coord::random_within(size, &mut rng);
+            // unlike the static search, the timed search doesn't allow entering a solid goal
+            if !search.can_enter(goal) {
+                continue;
+            }
+            let start_time = rng.gen_range(0..100);
+            let expected = ctx.point_to_point_search_path(expand::JumpPoint, &search, start, goal, &mut expected_path);
+            let result = timed_ctx.timed_search_path(&search, start, start_time, goal, 400, &mut path);
+            assert_eq!(result.is_ok(), expected.is_ok());
+            if result.is_err() {
+                continue;
+            }
+            assert_path(&search, &path, start, start_time, goal);
+            assert_eq!(path.len(), expected_path.len());
+            let length = path.len() as u32;
+            assert_eq!(path.arrival_time().unwrap_or(start_time), start_time + length);
+            if length > 0 {
+                assert!(matches!(
+                    timed_ctx.timed_search_path(&search, start, start_time, goal, length - 1, &mut path),
+                    Err(SearchError::HorizonExceeded { .. })
+                ));
+            }
+        }
+    }
+}
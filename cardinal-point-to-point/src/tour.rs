@@ -0,0 +1,530 @@
+use crate::{expand::Expand, CanEnter, Context, Coord, Observer, Path, SearchError, Size, UNIT_COORDS};
+use grid_search_cardinal_distance_map::{Distance, DistanceMap, PopulateContext};
+
+/// Tours of at most this many waypoints are put in the shortest order exactly. Longer tours are
+/// ordered approximately.
+pub const MAX_EXACT_TOUR_WAYPOINTS: usize = 13;
+
+const UNREACHABLE: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TourKind {
+    /// Visit the waypoints in the order given
+    Fixed,
+    /// Visit every waypoint in the shortest order, starting at the first
+    Open,
+    /// Visit every waypoint in the shortest order, starting at and returning to the first
+    Closed,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct Tour {
+    /// Indices of waypoints in the order they are visited. The first waypoint is always first,
+    /// and isn't repeated at the end of closed tours.
+    pub order: Vec<usize>,
+    /// Every leg of the tour in turn
+    pub path: Path,
+}
+
+// Forwards the events of each search to the tour's observer, except for the cost of each leg
+struct Legs<'a, O>(&'a mut O);
+
+impl<O: Observer> Observer for Legs<'_, O> {
+    fn push(&mut self, coord: Coord) {
+        self.0.push(coord);
+    }
+    fn expand(&mut self, coord: Coord) {
+        self.0.expand(coord);
+    }
+    fn visit(&mut self, coord: Coord) {
+        self.0.visit(coord);
+    }
+    fn rescan(&mut self, coord: Coord) {
+        self.0.rescan(coord);
+    }
+    fn jump(&mut self, from: Coord, to: Coord) {
+        self.0.jump(from, to);
+    }
+    fn queue_len(&mut self, len: usize) {
+        self.0.queue_len(len);
+    }
+}
+
+/// Plans routes which visit a list of waypoints. The costs of legs between each pair of
+/// waypoints are found with one distance map per waypoint, and are assumed to be the same in
+/// both directions. Only the legs in the chosen order are searched for.
+pub struct TourContext {
+    context: Context,
+    populate_context: PopulateContext,
+    distance_map: DistanceMap,
+    // costs[from * num_waypoints + to]
+    costs: Vec<u32>,
+    leg: Path,
+}
+
+impl TourContext {
+    pub fn new(size: Size) -> Self {
+        Self {
+            context: Context::new(size),
+            populate_context: PopulateContext::default(),
+            distance_map: DistanceMap::new(size),
+            costs: Vec::new(),
+            leg: Path::default(),
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.context.size()
+    }
+
+    /// Plans a tour starting at the first waypoint. On failure, `tour` is left empty.
+    pub fn plan<S, E>(
+        &mut self,
+        expand: E,
+        can_enter: &S,
+        kind: TourKind,
+        waypoints: &[Coord],
+        tour: &mut Tour,
+    ) -> Result<(), SearchError>
+    where
+        S: CanEnter,
+        E: Expand + Copy,
+    {
+        self.plan_with_observer(expand, can_enter, kind, waypoints, tour, &mut ())
+    }
+
+    /// The observer is notified of every search made while planning, and is given the cost of
+    /// the whole tour rather than of each leg
+    pub fn plan_with_observer<S, E, O>(
+        &mut self,
+        expand: E,
+        can_enter: &S,
+        kind: TourKind,
+        waypoints: &[Coord],
+        tour: &mut Tour,
+        observer: &mut O,
+    ) -> Result<(), SearchError>
+    where
+        S: CanEnter,
+        E: Expand + Copy,
+        O: Observer,
+    {
+        tour.order.clear();
+        tour.path.clear();
+        let result = self.plan_inner(expand, can_enter, kind, waypoints, tour, &mut Legs(observer));
+        match result {
+            Ok(()) => observer.path_cost(tour.path.len() as u32),
+            Err(_) => {
+                tour.order.clear();
+                tour.path.clear();
+            }
+        }
+        result
+    }
+
+    fn plan_inner<S, E, O>(
+        &mut self,
+        expand: E,
+        can_enter: &S,
+        kind: TourKind,
+        waypoints: &[Coord],
+        tour: &mut Tour,
+        observer: &mut O,
+    ) -> Result<(), SearchError>
+    where
+        S: CanEnter,
+        E: Expand + Copy,
+        O: Observer,
+    {
+        let (&start, rest) = match waypoints.split_first() {
+            Some(split) => split,
+            None => return Ok(()),
+        };
+        SearchError::check_bounds(self.size(), &[start], rest)?;
+        let num_waypoints = waypoints.len();
+        match kind {
+            TourKind::Fixed => tour.order.extend(0..num_waypoints),
+            TourKind::Open | TourKind::Closed => {
+                let closed = kind == TourKind::Closed;
+                if let Some(unreachable) = self.compute_costs(can_enter, waypoints, observer) {
+                    // the failed search describes the part of the grid reachable from the start
+                    self.context.point_to_point_search_path_with_observer(
+                        expand,
+                        can_enter,
+                        start,
+                        unreachable,
+                        &mut self.leg,
+                        observer,
+                    )?;
+                }
+                if num_waypoints <= MAX_EXACT_TOUR_WAYPOINTS {
+                    held_karp(&self.costs, num_waypoints, closed, &mut tour.order);
+                } else {
+                    two_opt(&self.costs, num_waypoints, closed, &mut tour.order);
+                }
+            }
+        }
+        let mut from = start;
+        let back_to_start = (kind == TourKind::Closed).then_some(0);
+        for index in tour.order.iter().skip(1).cloned().chain(back_to_start) {
+            let to = waypoints[index];
+            self.context.point_to_point_search_path_with_observer(
+                expand,
+                can_enter,
+                from,
+                to,
+                &mut self.leg,
+                observer,
+            )?;
+            tour.path.append_path(&self.leg).expect("legs of a tour are contiguous");
+            from = to;
+        }
+        Ok(())
+    }
+
+    // Returns a waypoint which can't be reached from the start, if there is one
+    fn compute_costs<S: CanEnter, O: Observer>(
+        &mut self,
+        can_enter: &S,
+        waypoints: &[Coord],
+        observer: &mut O,
+    ) -> Option<Coord> {
+        self.costs.clear();
+        for &from in waypoints {
+            self.populate_context.add(from);
+            self.populate_context.populate_approach_with_observer(
+                can_enter,
+                Distance::MAX,
+                &mut self.distance_map,
+                observer,
+            );
+            let distance_map = &self.distance_map;
+            self.costs
+                .extend(waypoints.iter().map(|&to| leg_cost(distance_map, to)));
+        }
+        // costs are symmetric, so it's enough to check that every waypoint can be reached from
+        // the start
+        waypoints
+            .iter()
+            .zip(self.costs.iter())
+            .find(|&(_, &cost)| cost == UNREACHABLE)
+            .map(|(&unreachable, _)| unreachable)
+    }
+}
+
+// Like the point-to-point search used for each leg, a waypoint which can't be entered is still
+// reached by stepping into it from a neighbour
+fn leg_cost(distance_map: &DistanceMap, to: Coord) -> u32 {
+    distance_map
+        .distance(to)
+        .or_else(|| {
+            UNIT_COORDS
+                .iter()
+                .filter_map(|direction| distance_map.distance(to + direction.to_coord()))
+                .min()
+                .map(|distance| distance + 1)
+        })
+        .unwrap_or(UNREACHABLE)
+}
+
+// Finds the shortest order by dynamic programming over the sets of visited waypoints
+// (Held-Karp), taking O(2^n * n^2) time
+fn held_karp(costs: &[u32], num_waypoints: usize, closed: bool, order: &mut Vec<usize>) {
+    order.push(0);
+    // waypoint `i + 1` is bit `i` of a set, since the start is always visited first
+    let num_others = num_waypoints - 1;
+    if num_others == 0 {
+        return;
+    }
+    let cost = |from: usize, to: usize| costs[from * num_waypoints + to];
+    let num_sets = 1 << num_others;
+    // best[set * num_others + last] is the cost of visiting every waypoint in `set`, ending at
+    // `last`, and parent is the waypoint visited before `last`
+    let mut best = vec![UNREACHABLE; num_sets * num_others];
+    let mut parent = vec![0; num_sets * num_others];
+    for last in 0..num_others {
+        best[(1 << last) * num_others + last] = cost(0, last + 1);
+    }
+    for set in 1..num_sets {
+        for last in (0..num_others).filter(|&last| set & (1 << last) != 0) {
+            let set_cost = best[set * num_others + last];
+            if set_cost == UNREACHABLE {
+                continue;
+            }
+            for next in (0..num_others).filter(|&next| set & (1 << next) == 0) {
+                let leg_cost = cost(last + 1, next + 1);
+                if leg_cost == UNREACHABLE {
+                    continue;
+                }
+                let index = (set | (1 << next)) * num_others + next;
+                if set_cost + leg_cost < best[index] {
+                    best[index] = set_cost + leg_cost;
+                    parent[index] = last;
+                }
+            }
+        }
+    }
+    let mut set = num_sets - 1;
+    let tour_cost = |last: usize| {
+        let set_cost = best[set * num_others + last];
+        if closed {
+            set_cost.saturating_add(cost(last + 1, 0))
+        } else {
+            set_cost
+        }
+    };
+    let mut last = (0..num_others).min_by_key(|&last| tour_cost(last)).unwrap();
+    loop {
+        order.push(last + 1);
+        let before_last = parent[set * num_others + last];
+        set &= !(1 << last);
+        if set == 0 {
+            break;
+        }
+        last = before_last;
+    }
+    order[1..].reverse();
+}
+
+// Visits the nearest unvisited waypoint next, then reverses sections of that order while it
+// makes the tour shorter (2-opt)
+fn two_opt(costs: &[u32], num_waypoints: usize, closed: bool, order: &mut Vec<usize>) {
+    let cost = |from: usize, to: usize| costs[from * num_waypoints + to] as u64;
+    let mut visited = vec![false; num_waypoints];
+    visited[0] = true;
+    order.push(0);
+    for _ in 1..num_waypoints {
+        let last = *order.last().unwrap();
+        let next = (0..num_waypoints)
+            .filter(|&index| !visited[index])
+            .min_by_key(|&index| cost(last, index))
+            .unwrap();
+        visited[next] = true;
+        order.push(next);
+    }
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 1..num_waypoints {
+            for k in (i + 1)..num_waypoints {
+                // reversing order[i..=k] replaces the legs into order[i] and out of order[k]
+                let (before_i, at_i, at_k) = (order[i - 1], order[i], order[k]);
+                let after_k = match order.get(k + 1) {
+                    Some(&after_k) => Some(after_k),
+                    None if closed => Some(0),
+                    None => None,
+                };
+                let current = cost(before_i, at_i) + after_k.map_or(0, |after_k| cost(at_k, after_k));
+                let reversed = cost(before_i, at_k) + after_k.map_or(0, |after_k| cost(at_i, after_k));
+                if reversed < current {
+                    order[i..=k].reverse();
+                    improved = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_world::{random_grid, Search};
+    use crate::{expand, SearchStats};
+    use grid_2d::Grid;
+    use rand::{Rng, SeedableRng};
+    use rand_isaac::Isaac64Rng;
+
+    fn random_waypoints<R: Rng>(world: &Search, num_waypoints: usize, rng: &mut R) -> Vec<Coord> {
+        let size = world.grid.size();
+        let mut waypoints = Vec::new();
+        while waypoints.len() < num_waypoints {
+            let coord = Coord::new(
+                rng.gen_range(0..size.width() as i32),
+                rng.gen_range(0..size.height() as i32),
+            );
+            if world.can_enter(coord) {
+                waypoints.push(coord);
+            }
+        }
+        waypoints
+    }
+
+    // checks that the path visits the waypoints in order and returns its length
+    fn check_tour(world: &Search, kind: TourKind, waypoints: &[Coord], tour: &Tour) -> usize {
+        let mut sorted_order = tour.order.clone();
+        sorted_order.sort_unstable();
+        assert_eq!(sorted_order, (0..waypoints.len()).collect::<Vec<_>>());
+        assert_eq!(tour.order.first(), Some(&0));
+        let mut coord = waypoints[0];
+        let mut to_visit = tour.order.iter().skip(1).map(|&index| waypoints[index]).peekable();
+        while to_visit.next_if_eq(&coord).is_some() {}
+        for node in tour.path.iter() {
+            assert_eq!(coord + node.in_direction.coord(), node.to_coord);
+            assert!(world.can_enter(node.to_coord) || waypoints.contains(&node.to_coord));
+            coord = node.to_coord;
+            while to_visit.next_if_eq(&coord).is_some() {}
+        }
+        assert_eq!(to_visit.next(), None);
+        if kind == TourKind::Closed {
+            assert_eq!(coord, waypoints[0]);
+        }
+        tour.path.len()
+    }
+
+    fn permutations(items: &mut Vec<usize>, len: usize, f: &mut impl FnMut(&[usize])) {
+        if len <= 1 {
+            f(items);
+            return;
+        }
+        for i in 0..len {
+            items.swap(i, len - 1);
+            permutations(items, len - 1, f);
+            items.swap(i, len - 1);
+        }
+    }
+
+    #[test]
+    fn exact_random() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let size = Size::new(12, 12);
+        let mut ctx = TourContext::new(size);
+        let mut search_ctx = Context::new(size);
+        let mut tour = Tour::default();
+        let mut leg = Path::default();
+        for _ in 0..30 {
+            let grid = random_grid(size, 4, &mut rng);
+            let world = Search { grid: &grid };
+            let waypoints = random_waypoints(&world, 6, &mut rng);
+            let mut stats = SearchStats::default();
+            let result = ctx.plan_with_observer(
+                expand::JumpPoint,
+                &world,
+                TourKind::Open,
+                &waypoints,
+                &mut tour,
+                &mut stats,
+            );
+            let mut legs = vec![None; waypoints.len() * waypoints.len()];
+            for (i, &from) in waypoints.iter().enumerate() {
+                for (j, &to) in waypoints.iter().enumerate() {
+                    if search_ctx
+                        .point_to_point_search_path(expand::JumpPoint, &world, from, to, &mut leg)
+                        .is_ok()
+                    {
+                        legs[i * waypoints.len() + j] = Some(leg.len());
+                    }
+                }
+            }
+            if legs.iter().any(Option::is_none) {
+                assert!(result.is_err());
+                assert!(tour.order.is_empty());
+                assert_eq!(stats.path_cost, None);
+                continue;
+            }
+            let leg = |i: usize, j: usize| legs[i * waypoints.len() + j].unwrap();
+            for kind in [TourKind::Open, TourKind::Closed] {
+                let mut shortest = usize::MAX;
+                let mut rest = (1..waypoints.len()).collect::<Vec<_>>();
+                let num_rest = rest.len();
+                permutations(&mut rest, num_rest, &mut |rest| {
+                    let mut len = leg(0, rest[0]) + rest.windows(2).map(|w| leg(w[0], w[1])).sum::<usize>();
+                    if kind == TourKind::Closed {
+                        len += leg(*rest.last().unwrap(), 0);
+                    }
+                    shortest = shortest.min(len);
+                });
+                let mut stats = SearchStats::default();
+                ctx.plan_with_observer(expand::JumpPoint, &world, kind, &waypoints, &mut tour, &mut stats)
+                    .unwrap();
+                assert_eq!(check_tour(&world, kind, &waypoints, &tour), shortest);
+                assert_eq!(stats.path_cost, Some(shortest as u32));
+                assert!(stats.nodes_expanded > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_and_approximate() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let size = Size::new(30, 30);
+        let grid = Grid::new_copy(size, true);
+        let world = Search { grid: &grid };
+        let mut ctx = TourContext::new(size);
+        let mut tour = Tour::default();
+        let waypoints = random_waypoints(&world, MAX_EXACT_TOUR_WAYPOINTS + 7, &mut rng);
+        ctx.plan(expand::JumpPoint, &world, TourKind::Fixed, &waypoints, &mut tour)
+            .unwrap();
+        assert_eq!(tour.order, (0..waypoints.len()).collect::<Vec<_>>());
+        let fixed_len = check_tour(&world, TourKind::Fixed, &waypoints, &tour);
+        ctx.plan(expand::Sequential, &world, TourKind::Open, &waypoints, &mut tour)
+            .unwrap();
+        let open_len = check_tour(&world, TourKind::Open, &waypoints, &tour);
+        assert!(open_len <= fixed_len);
+        // on an open grid, reversing any section of a 2-opt tour doesn't make it shorter
+        let order = &tour.order;
+        let manhattan = |a: usize, b: usize| waypoints[a].manhattan_distance(waypoints[b]);
+        for i in 1..order.len() {
+            for k in (i + 1)..order.len() {
+                let after_k = order.get(k + 1).map_or(0, |&after_k| {
+                    manhattan(order[k], after_k) as i64 - manhattan(order[i], after_k) as i64
+                });
+                assert!(manhattan(order[i - 1], order[i]) as i64 + after_k <= manhattan(order[i - 1], order[k]) as i64);
+            }
+        }
+        ctx.plan(expand::Bidirectional, &world, TourKind::Closed, &waypoints, &mut tour)
+            .unwrap();
+        check_tour(&world, TourKind::Closed, &waypoints, &tour);
+    }
+
+    #[test]
+    fn unreachable() {
+        let mut grid = Grid::new_copy(Size::new(5, 5), true);
+        for y in 0..5 {
+            *grid.get_checked_mut(Coord::new(2, y)) = false;
+        }
+        let world = Search { grid: &grid };
+        let mut ctx = TourContext::new(grid.size());
+        let mut tour = Tour::default();
+        let waypoints = [Coord::new(0, 0), Coord::new(1, 4), Coord::new(4, 4)];
+        assert_eq!(
+            ctx.plan(expand::Sequential, &world, TourKind::Open, &waypoints, &mut tour),
+            Err(SearchError::GoalUnreachable {
+                closest: Coord::new(1, 4),
+                num_reachable: 10,
+            })
+        );
+        assert!(tour.order.is_empty() && tour.path.is_empty());
+        ctx.plan(expand::JumpPoint, &world, TourKind::Closed, &waypoints[..2], &mut tour)
+            .unwrap();
+        assert_eq!(tour.order, [0, 1]);
+        assert_eq!(tour.path.len(), 10);
+        ctx.plan(expand::JumpPoint, &world, TourKind::Open, &[], &mut tour)
+            .unwrap();
+        assert!(tour.order.is_empty());
+        ctx.plan(expand::JumpPoint, &world, TourKind::Closed, &waypoints[..1], &mut tour)
+            .unwrap();
+        assert_eq!(tour.order, [0]);
+        assert!(tour.path.is_empty());
+    }
+
+    #[test]
+    fn solid_waypoint() {
+        let mut grid = Grid::new_copy(Size::new(5, 5), true);
+        let solid = Coord::new(2, 2);
+        *grid.get_checked_mut(solid) = false;
+        let world = Search { grid: &grid };
+        let mut ctx = TourContext::new(grid.size());
+        let mut tour = Tour::default();
+        let waypoints = [Coord::new(0, 0), Coord::new(4, 4), solid];
+        for kind in [TourKind::Fixed, TourKind::Open, TourKind::Closed] {
+            ctx.plan(expand::JumpPoint, &world, kind, &waypoints, &mut tour)
+                .unwrap();
+            check_tour(&world, kind, &waypoints, &tour);
+        }
+        // the solid waypoint is visited on the way to the far corner
+        ctx.plan(expand::JumpPoint, &world, TourKind::Open, &waypoints, &mut tour)
+            .unwrap();
+        assert_eq!(tour.order, [0, 2, 1]);
+        assert_eq!(tour.path.len(), 8);
+    }
+}
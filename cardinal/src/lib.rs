@@ -1,4 +1,7 @@
 pub use grid_search_cardinal_best as best;
 pub use grid_search_cardinal_distance_map as distance_map;
 pub use grid_search_cardinal_point_to_point as point_to_point;
-pub use point_to_point::{CanEnter, CardinalDirection, Coord, Path, Size, Step};
+pub use point_to_point::{
+    CanEnter, CardinalDirection, ClearanceMap, CompactPath, Coord, Dense, Observer, Path, Portal, Portals, SearchError,
+    SearchStats, Size, Sparse, Step, StepCost, Storage, Wrap,
+};
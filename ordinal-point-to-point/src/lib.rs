@@ -0,0 +1,735 @@
+pub use coord_2d::{Coord, Size};
+pub use direction::Direction;
+pub use grid_search_cardinal_common::error::SearchError;
+pub use path::{Path, PathIter, PathNode};
+use seen_set::{SeenSet, Visit};
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+pub use step::Step;
+
+mod path;
+mod seen_set;
+mod step;
+
+pub type Cost = u32;
+
+pub const CARDINAL_STEP_COST: Cost = 10;
+pub const ORDINAL_STEP_COST: Cost = 14;
+
+pub trait CanEnter {
+    fn can_enter(&self, coord: Coord) -> bool;
+}
+
+/// Which diagonal steps are allowed, based on the two cells the step passes between
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CornerCutting {
+    Never,
+    WhenOneSideOpen,
+    Always,
+}
+
+impl CornerCutting {
+    fn allows(self, left_open: bool, right_open: bool) -> bool {
+        match self {
+            Self::Never => left_open && right_open,
+            Self::WhenOneSideOpen => left_open || right_open,
+            Self::Always => true,
+        }
+    }
+}
+
+pub fn step_cost(direction: Direction) -> Cost {
+    if direction.is_cardinal() {
+        CARDINAL_STEP_COST
+    } else {
+        ORDINAL_STEP_COST
+    }
+}
+
+pub fn octile_distance(a: Coord, b: Coord) -> Cost {
+    let delta = a - b;
+    let dx = delta.x.unsigned_abs();
+    let dy = delta.y.unsigned_abs();
+    let (min, max) = if dx < dy { (dx, dy) } else { (dy, dx) };
+    (max - min) * CARDINAL_STEP_COST + min * ORDINAL_STEP_COST
+}
+
+#[derive(Debug)]
+struct Node {
+    cost: Cost,
+    cost_plus_heuristic: Cost,
+    coord: Coord,
+    in_direction: Option<Direction>,
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost_plus_heuristic.eq(&other.cost_plus_heuristic)
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Eq for Node {}
+
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match other.cost_plus_heuristic.cmp(&self.cost_plus_heuristic) {
+            Ordering::Equal => self.cost.cmp(&other.cost),
+            other => other,
+        }
+    }
+}
+
+type NeighbourMask = u8;
+
+const ALL_NEIGHBOURS: NeighbourMask = 0xff;
+
+fn direction_bit(direction: Direction) -> NeighbourMask {
+    1 << direction as u8
+}
+
+fn neighbour_direction(offset: Coord) -> Option<Direction> {
+    if offset == Coord::new(0, 0) {
+        None
+    } else {
+        Some(Direction::from_unit_coord(offset))
+    }
+}
+
+// For each direction of arrival and each combination of open neighbours, the set of neighbours
+// which can't be reached at least as cheaply from the previous cell without passing through the
+// current cell. This is the neighbour pruning rule of jump point search, evaluated once per
+// corner-cutting policy rather than hard-coded for each policy.
+#[derive(Debug, Clone)]
+struct SuccessorTable {
+    successors: Vec<NeighbourMask>,
+}
+
+impl SuccessorTable {
+    fn new(corner_cutting: CornerCutting) -> Self {
+        let mut successors = Vec::with_capacity(Direction::all().count() * 256);
+        for in_direction in Direction::all() {
+            for open in 0..=ALL_NEIGHBOURS {
+                successors.push(Self::compute(corner_cutting, in_direction, open));
+            }
+        }
+        Self { successors }
+    }
+
+    fn compute(corner_cutting: CornerCutting, in_direction: Direction, open: NeighbourMask) -> NeighbourMask {
+        let parent = in_direction.opposite();
+        let open = open | direction_bit(parent);
+        let is_open = |offset: Coord| match neighbour_direction(offset) {
+            None => true,
+            Some(direction) => open & direction_bit(direction) != 0,
+        };
+        let can_move = |from: Coord, to: Coord| {
+            let delta = to - from;
+            if delta.x.abs() > 1 || delta.y.abs() > 1 || delta == Coord::new(0, 0) || !is_open(to) {
+                return false;
+            }
+            if delta.x == 0 || delta.y == 0 {
+                return true;
+            }
+            corner_cutting.allows(is_open(Coord::new(to.x, from.y)), is_open(Coord::new(from.x, to.y)))
+        };
+        // shortest distances from the parent to each neighbour, without passing through the
+        // current cell
+        let mut distances = [Cost::MAX; 8];
+        distances[parent as usize] = 0;
+        for _ in 0..distances.len() {
+            for from in Direction::all() {
+                let from_distance = distances[from as usize];
+                if from_distance == Cost::MAX {
+                    continue;
+                }
+                for to in Direction::all() {
+                    if can_move(from.coord(), to.coord()) {
+                        let delta = to.coord() - from.coord();
+                        let cost = if delta.x == 0 || delta.y == 0 {
+                            CARDINAL_STEP_COST
+                        } else {
+                            ORDINAL_STEP_COST
+                        };
+                        let distance = &mut distances[to as usize];
+                        *distance = (*distance).min(from_distance + cost);
+                    }
+                }
+            }
+        }
+        let mut successors = 0;
+        for direction in Direction::all() {
+            if !can_move(Coord::new(0, 0), direction.coord()) {
+                continue;
+            }
+            let via_current = step_cost(in_direction) + step_cost(direction);
+            let distance = distances[direction as usize];
+            let pruned = if in_direction.is_cardinal() {
+                distance <= via_current
+            } else {
+                distance < via_current
+            };
+            if !pruned {
+                successors |= direction_bit(direction);
+            }
+        }
+        successors
+    }
+
+    fn get(&self, in_direction: Direction, open: NeighbourMask) -> NeighbourMask {
+        self.successors[in_direction as usize * 256 + open as usize]
+    }
+}
+
+pub struct Context {
+    seen_set: SeenSet,
+    priority_queue: BinaryHeap<Node>,
+    corner_cutting: CornerCutting,
+    successor_table: SuccessorTable,
+}
+
+#[cfg(feature = "serialize")]
+impl Serialize for Context {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        (self.size(), self.corner_cutting).serialize(s)
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl<'a> Deserialize<'a> for Context {
+    fn deserialize<D: serde::Deserializer<'a>>(d: D) -> Result<Self, D::Error> {
+        Deserialize::deserialize(d).map(|(size, corner_cutting)| Self::new(size, corner_cutting))
+    }
+}
+
+pub mod expand {
+    use super::private_expand::PrivateExpand;
+    pub trait Expand: PrivateExpand {}
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct JumpPoint;
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct Sequential;
+
+    impl Expand for JumpPoint {}
+    impl Expand for Sequential {}
+}
+
+struct NoPath;
+
+mod private_expand {
+    use super::{expand, CanEnter, Context, Coord, Direction};
+    pub trait PrivateExpand {
+        fn expand<C: CanEnter>(
+            context: &mut Context,
+            can_enter: &C,
+            coord: Coord,
+            in_direction: Option<Direction>,
+            cost: u32,
+            goal: Coord,
+        );
+    }
+
+    impl PrivateExpand for expand::JumpPoint {
+        fn expand<C: CanEnter>(
+            context: &mut Context,
+            can_enter: &C,
+            coord: Coord,
+            in_direction: Option<Direction>,
+            cost: u32,
+            goal: Coord,
+        ) {
+            let successors = match in_direction {
+                None => super::ALL_NEIGHBOURS,
+                Some(in_direction) => {
+                    let open = context.open_neighbours(can_enter, coord, goal);
+                    context.successor_table.get(in_direction, open)
+                }
+            };
+            for direction in Direction::all() {
+                if successors & super::direction_bit(direction) != 0 {
+                    context.consider_jps(can_enter, coord, direction, cost, goal);
+                }
+            }
+        }
+    }
+
+    impl PrivateExpand for expand::Sequential {
+        fn expand<C: CanEnter>(
+            context: &mut Context,
+            can_enter: &C,
+            coord: Coord,
+            _in_direction: Option<Direction>,
+            cost: u32,
+            goal: Coord,
+        ) {
+            for direction in Direction::all() {
+                context.consider(can_enter, coord, direction, cost, goal);
+            }
+        }
+    }
+}
+
+use expand::Expand;
+
+fn is_open<C: CanEnter>(can_enter: &C, coord: Coord, goal: Coord) -> bool {
+    coord == goal || can_enter.can_enter(coord)
+}
+
+impl Context {
+    pub fn new(size: Size, corner_cutting: CornerCutting) -> Self {
+        Self {
+            seen_set: SeenSet::new(size),
+            priority_queue: BinaryHeap::new(),
+            corner_cutting,
+            successor_table: SuccessorTable::new(corner_cutting),
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.seen_set.size()
+    }
+
+    pub fn corner_cutting(&self) -> CornerCutting {
+        self.corner_cutting
+    }
+
+    fn can_move<C: CanEnter>(&self, can_enter: &C, from: Coord, direction: Direction, goal: Coord) -> bool {
+        if !is_open(can_enter, from + direction.coord(), goal) {
+            return false;
+        }
+        if direction.is_cardinal() {
+            return true;
+        }
+        self.corner_cutting.allows(
+            is_open(can_enter, from + direction.left45().coord(), goal),
+            is_open(can_enter, from + direction.right45().coord(), goal),
+        )
+    }
+
+    fn open_neighbours<C: CanEnter>(&self, can_enter: &C, coord: Coord, goal: Coord) -> NeighbourMask {
+        let mut open = 0;
+        for direction in Direction::all() {
+            if is_open(can_enter, coord + direction.coord(), goal) {
+                open |= direction_bit(direction);
+            }
+        }
+        open
+    }
+
+    fn has_forced_neighbour<C: CanEnter>(
+        &self,
+        can_enter: &C,
+        coord: Coord,
+        in_direction: Direction,
+        goal: Coord,
+    ) -> bool {
+        let open = self.open_neighbours(can_enter, coord, goal);
+        let natural = self.successor_table.get(in_direction, ALL_NEIGHBOURS);
+        self.successor_table.get(in_direction, open) & !natural != 0
+    }
+
+    fn push(&mut self, coord: Coord, in_direction: Direction, length: u32, cost: Cost, goal: Coord) {
+        if let Some(Visit) = self.seen_set.try_visit(coord, in_direction, length, cost) {
+            let node = Node {
+                cost,
+                cost_plus_heuristic: cost + octile_distance(coord, goal),
+                coord,
+                in_direction: Some(in_direction),
+            };
+            self.priority_queue.push(node);
+        }
+    }
+
+    fn consider<C: CanEnter>(&mut self, can_enter: &C, from: Coord, direction: Direction, cost: Cost, goal: Coord) {
+        if self.can_move(can_enter, from, direction, goal) {
+            self.push(
+                from + direction.coord(),
+                direction,
+                1,
+                cost + step_cost(direction),
+                goal,
+            );
+        }
+    }
+
+    fn jump<C: CanEnter>(&self, can_enter: &C, from: Coord, direction: Direction, goal: Coord) -> Option<(Coord, u32)> {
+        let mut coord = from;
+        let mut length = 0;
+        loop {
+            if !self.can_move(can_enter, coord, direction, goal) {
+                return None;
+            }
+            coord += direction.coord();
+            length += 1;
+            if coord == goal || self.has_forced_neighbour(can_enter, coord, direction, goal) {
+                return Some((coord, length));
+            }
+            if direction.is_ordinal()
+                && (self.jump(can_enter, coord, direction.left45(), goal).is_some()
+                    || self.jump(can_enter, coord, direction.right45(), goal).is_some())
+            {
+                return Some((coord, length));
+            }
+        }
+    }
+
+    fn consider_jps<C: CanEnter>(&mut self, can_enter: &C, from: Coord, direction: Direction, cost: Cost, goal: Coord) {
+        if let Some((coord, length)) = self.jump(can_enter, from, direction, goal) {
+            self.push(coord, direction, length, cost + length * step_cost(direction), goal);
+        }
+    }
+
+    fn point_to_point_search_core<C, E>(&mut self, can_enter: &C, start: Coord, goal: Coord) -> Result<(), NoPath>
+    where
+        C: CanEnter,
+        E: Expand,
+    {
+        self.seen_set.init(start);
+        self.priority_queue.clear();
+        self.priority_queue.push(Node {
+            cost: 0,
+            cost_plus_heuristic: octile_distance(start, goal),
+            coord: start,
+            in_direction: None,
+        });
+        while let Some(Node {
+            cost,
+            coord,
+            in_direction,
+            ..
+        }) = self.priority_queue.pop()
+        {
+            if coord == goal {
+                return Ok(());
+            }
+            if self.seen_set.cost(coord) != Some(cost) {
+                continue;
+            }
+            E::expand(self, can_enter, coord, in_direction, cost, goal);
+        }
+        Err(NoPath)
+    }
+
+    pub fn point_to_point_search_path<C, E>(
+        &mut self,
+        expand: E,
+        can_enter: &C,
+        start: Coord,
+        goal: Coord,
+        path: &mut Path,
+    ) -> Result<(), SearchError>
+    where
+        C: CanEnter,
+        E: Expand,
+    {
+        let _ = expand;
+        SearchError::check_bounds(self.size(), &[start], &[goal])?;
+        self.point_to_point_search_core::<_, E>(can_enter, start, goal)
+            .map_err(|NoPath| self.seen_set.goal_unreachable(start, goal))?;
+        self.seen_set.build_path_to(goal, path);
+        Ok(())
+    }
+
+    pub fn point_to_point_search_first<C, E>(
+        &mut self,
+        expand: E,
+        can_enter: &C,
+        start: Coord,
+        goal: Coord,
+    ) -> Result<Option<Direction>, SearchError>
+    where
+        C: CanEnter,
+        E: Expand,
+    {
+        let _ = expand;
+        SearchError::check_bounds(self.size(), &[start], &[goal])?;
+        self.point_to_point_search_core::<_, E>(can_enter, start, goal)
+            .map_err(|NoPath| self.seen_set.goal_unreachable(start, goal))?;
+        Ok(self.seen_set.first_direction_towards(goal))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use grid_2d::Grid;
+    use rand::{Rng, SeedableRng};
+    use rand_isaac::Isaac64Rng;
+
+    #[derive(Clone)]
+    enum Cell {
+        Solid,
+        Traversable,
+    }
+
+    struct Test {
+        grid: Grid<Cell>,
+        start: Coord,
+        goal: Coord,
+    }
+
+    fn print_test(test: &Test) {
+        for row in test.grid.rows() {
+            print!("\"");
+            for cell in row {
+                let ch = match cell {
+                    Cell::Solid => '#',
+                    Cell::Traversable => '.',
+                };
+                print!("{}", ch);
+            }
+            println!("\",");
+        }
+    }
+
+    fn str_slice_to_test(str_slice: &[&str]) -> Test {
+        let width = str_slice[0].len() as u32;
+        let height = str_slice.len() as u32;
+        let size = Size::new(width, height);
+        let mut grid = Grid::new_clone(size, Cell::Solid);
+        let mut start = None;
+        let mut goal = None;
+        for (y, line) in str_slice.iter().enumerate() {
+            for (x, ch) in line.chars().enumerate() {
+                let coord = Coord::new(x as i32, y as i32);
+                let cell = match ch {
+                    '.' => Cell::Traversable,
+                    '@' => {
+                        start = Some(coord);
+                        Cell::Traversable
+                    }
+                    '*' => {
+                        goal = Some(coord);
+                        Cell::Traversable
+                    }
+                    '#' => Cell::Solid,
+                    _ => panic!(),
+                };
+                *grid.get_checked_mut(coord) = cell;
+            }
+        }
+        Test {
+            grid,
+            start: start.unwrap(),
+            goal: goal.unwrap_or(start.unwrap()),
+        }
+    }
+
+    fn random_test<R: Rng>(size: Size, rng: &mut R) -> Test {
+        let mut grid = Grid::new_clone(size, Cell::Traversable);
+        let num_solid = size.count() / 3;
+        for _ in 0..num_solid {
+            let coord = Coord::random_within(size, rng);
+            *grid.get_checked_mut(coord) = Cell::Solid;
+        }
+        let start = Coord::random_within(size, rng);
+        let goal = Coord::random_within(size, rng);
+        Test { grid, start, goal }
+    }
+
+    struct Search<'a> {
+        grid: &'a Grid<Cell>,
+    }
+
+    impl<'a> CanEnter for Search<'a> {
+        fn can_enter(&self, coord: Coord) -> bool {
+            matches!(self.grid.get(coord), Some(Cell::Traversable))
+        }
+    }
+
+    // checks that the path is made of legal moves from start to goal, and returns its cost
+    fn validate_path(ctx: &Context, search: &Search, path: &Path, start: Coord, goal: Coord) -> Cost {
+        let mut coord = start;
+        let mut cost = 0;
+        for node in path.iter() {
+            assert_eq!(node.from_coord(), coord);
+            assert!(ctx.can_move(search, coord, node.in_direction, goal));
+            cost += step_cost(node.in_direction);
+            coord = node.to_coord;
+        }
+        assert_eq!(coord, goal);
+        cost
+    }
+
+    fn test(grid_str_slice: &[&str], corner_cutting: CornerCutting, cost: Option<Cost>) {
+        let Test { grid, start, goal } = str_slice_to_test(grid_str_slice);
+        let search = Search { grid: &grid };
+        let mut ctx = Context::new(grid.size(), corner_cutting);
+        let mut path = Path::default();
+        match cost {
+            Some(cost) => {
+                ctx.point_to_point_search_path(expand::Sequential, &search, start, goal, &mut path)
+                    .unwrap();
+                assert_eq!(validate_path(&ctx, &search, &path, start, goal), cost);
+                ctx.point_to_point_search_path(expand::JumpPoint, &search, start, goal, &mut path)
+                    .unwrap();
+                assert_eq!(validate_path(&ctx, &search, &path, start, goal), cost);
+            }
+            None => {
+                ctx.point_to_point_search_path(expand::Sequential, &search, start, goal, &mut path)
+                    .unwrap_err();
+                ctx.point_to_point_search_path(expand::JumpPoint, &search, start, goal, &mut path)
+                    .unwrap_err();
+            }
+        }
+    }
+
+    const GRID_A: &[&str] = &[
+        "..........",
+        ".......*..",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        "..........",
+        ".@........",
+        "..........",
+    ];
+
+    #[test]
+    fn grid_a() {
+        for corner_cutting in [
+            CornerCutting::Never,
+            CornerCutting::WhenOneSideOpen,
+            CornerCutting::Always,
+        ] {
+            test(GRID_A, corner_cutting, Some(6 * ORDINAL_STEP_COST + CARDINAL_STEP_COST));
+        }
+    }
+
+    const GRID_B: &[&str] = &[
+        "@.", //
+        "#*",
+    ];
+
+    #[test]
+    fn grid_b() {
+        test(GRID_B, CornerCutting::Never, Some(2 * CARDINAL_STEP_COST));
+        test(GRID_B, CornerCutting::WhenOneSideOpen, Some(ORDINAL_STEP_COST));
+        test(GRID_B, CornerCutting::Always, Some(ORDINAL_STEP_COST));
+    }
+
+    const GRID_C: &[&str] = &[
+        "@#", //
+        "#*",
+    ];
+
+    #[test]
+    fn grid_c() {
+        test(GRID_C, CornerCutting::Never, None);
+        test(GRID_C, CornerCutting::WhenOneSideOpen, None);
+        test(GRID_C, CornerCutting::Always, Some(ORDINAL_STEP_COST));
+    }
+
+    const GRID_D: &[&str] = &[
+        "..........",
+        "....@.....",
+        "..........",
+        "..........",
+        "...###....",
+        "...#*#....",
+        "...###....",
+        "..........",
+        "..........",
+        "..........",
+    ];
+
+    #[test]
+    fn grid_d() {
+        test(GRID_D, CornerCutting::Never, None);
+        test(GRID_D, CornerCutting::WhenOneSideOpen, None);
+        test(GRID_D, CornerCutting::Always, None);
+    }
+
+    const GRID_E: &[&str] = &[
+        "..........",
+        ".######...",
+        "......#...",
+        ".####.#...",
+        ".#*...#...",
+        ".######...",
+        "........@.",
+    ];
+
+    #[test]
+    fn search_errors() {
+        let Test { grid, start, goal } = str_slice_to_test(GRID_D);
+        let search = Search { grid: &grid };
+        let mut ctx = Context::new(grid.size(), CornerCutting::Always);
+        let mut path = Path::default();
+        let unreachable = SearchError::GoalUnreachable {
+            closest: Coord::new(4, 3),
+            num_reachable: 91,
+        };
+        assert_eq!(
+            ctx.point_to_point_search_path(expand::Sequential, &search, start, goal, &mut path),
+            Err(unreachable)
+        );
+        assert_eq!(
+            ctx.point_to_point_search_first(expand::Sequential, &search, start, goal),
+            Err(unreachable)
+        );
+        assert_eq!(
+            ctx.point_to_point_search_path(expand::JumpPoint, &search, Coord::new(10, 0), goal, &mut path),
+            Err(SearchError::StartOutOfBounds(Coord::new(10, 0)))
+        );
+        assert_eq!(
+            ctx.point_to_point_search_first(expand::JumpPoint, &search, start, Coord::new(0, -1)),
+            Err(SearchError::GoalOutOfBounds(Coord::new(0, -1)))
+        );
+    }
+
+    #[test]
+    fn grid_e() {
+        test(GRID_E, CornerCutting::Never, Some(22 * CARDINAL_STEP_COST));
+        test(
+            GRID_E,
+            CornerCutting::WhenOneSideOpen,
+            Some(14 * CARDINAL_STEP_COST + 4 * ORDINAL_STEP_COST),
+        );
+    }
+
+    #[test]
+    fn grid_random() {
+        let mut rng = Isaac64Rng::seed_from_u64(0);
+        let num_tests = 1000;
+        let size = Size::new(12, 12);
+        for corner_cutting in [
+            CornerCutting::Never,
+            CornerCutting::WhenOneSideOpen,
+            CornerCutting::Always,
+        ] {
+            let mut ctx = Context::new(size, corner_cutting);
+            let mut path = Path::default();
+            for _ in 0..num_tests {
+                let Test { grid, start, goal } = random_test(size, &mut rng);
+                let search = Search { grid: &grid };
+                let seq_result = ctx.point_to_point_search_path(expand::Sequential, &search, start, goal, &mut path);
+                let seq_cost = seq_result
+                    .ok()
+                    .map(|()| validate_path(&ctx, &search, &path, start, goal));
+                let jps_result = ctx.point_to_point_search_path(expand::JumpPoint, &search, start, goal, &mut path);
+                // jump point search visits fewer cells, so failures are described differently
+                let jps_cost = jps_result
+                    .ok()
+                    .map(|()| validate_path(&ctx, &search, &path, start, goal));
+                if seq_cost != jps_cost {
+                    println!("{:?} {:?} -> {:?}", corner_cutting, start, goal);
+                    print_test(&Test { grid, start, goal });
+                }
+                assert_eq!(seq_cost, jps_cost);
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use crate::step::Step;
+use std::collections::{vec_deque, VecDeque};
+
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+
+pub type PathNode = Step;
+
+pub struct PathIter<'a> {
+    iter: vec_deque::Iter<'a, Step>,
+}
+
+impl<'a> Iterator for PathIter<'a> {
+    type Item = PathNode;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().copied()
+    }
+}
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Default, Debug)]
+pub struct Path {
+    steps: VecDeque<Step>,
+}
+
+impl Path {
+    pub fn iter(&self) -> PathIter<'_> {
+        PathIter {
+            iter: self.steps.iter(),
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+    pub fn pop(&mut self) -> Option<PathNode> {
+        self.steps.pop_front()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.steps.clear();
+    }
+    pub(crate) fn prepend(&mut self, step: Step) {
+        self.steps.push_front(step);
+    }
+}
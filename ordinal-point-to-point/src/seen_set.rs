@@ -0,0 +1,123 @@
+use crate::path::Path;
+use crate::step::Step;
+use crate::{octile_distance, Cost};
+use coord_2d::{Coord, Size};
+use direction::Direction;
+use grid_2d::Grid;
+use grid_search_cardinal_common::error::SearchError;
+
+#[derive(Debug, Clone, Copy)]
+struct Jump {
+    in_direction: Direction,
+    length: u32,
+}
+
+#[derive(Debug, Clone)]
+struct SeenCell {
+    count: u64,
+    cost: Cost,
+    jump: Option<Jump>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SeenSet {
+    count: u64,
+    grid: Grid<SeenCell>,
+}
+
+pub struct Visit;
+
+impl SeenSet {
+    pub fn new(size: Size) -> Self {
+        Self {
+            count: 1,
+            grid: Grid::new_fn(size, |_| SeenCell {
+                count: 0,
+                cost: 0,
+                jump: None,
+            }),
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.grid.size()
+    }
+
+    pub fn init(&mut self, start: Coord) {
+        self.count += 1;
+        let cell = self.grid.get_checked_mut(start);
+        cell.count = self.count;
+        cell.cost = 0;
+        cell.jump = None;
+    }
+
+    pub fn cost(&self, coord: Coord) -> Option<Cost> {
+        self.grid
+            .get(coord)
+            .filter(|cell| cell.count == self.count)
+            .map(|cell| cell.cost)
+    }
+
+    pub fn try_visit(&mut self, to_coord: Coord, in_direction: Direction, length: u32, cost: Cost) -> Option<Visit> {
+        if let Some(cell) = self.grid.get_mut(to_coord) {
+            if cell.count != self.count || cost < cell.cost {
+                cell.count = self.count;
+                cell.cost = cost;
+                cell.jump = Some(Jump { in_direction, length });
+                return Some(Visit);
+            }
+        }
+        None
+    }
+
+    /// Describes why the latest search failed, from the cells it visited. Closeness is measured
+    /// by octile distance to `goal`.
+    pub fn goal_unreachable(&self, start: Coord, goal: Coord) -> SearchError {
+        let visited = self
+            .grid
+            .enumerate()
+            .filter(|(_, cell)| cell.count == self.count)
+            .map(|(coord, _)| coord);
+        SearchError::goal_unreachable(start, visited, |coord| octile_distance(coord, goal))
+    }
+
+    pub fn build_path_to(&self, end: Coord, path: &mut Path) {
+        let mut cell = self.grid.get(end).expect("path end out of bounds");
+        debug_assert_eq!(cell.count, self.count, "path end not visited in latest search");
+        let mut coord = end;
+        path.clear();
+        while let Some(Jump { in_direction, length }) = cell.jump {
+            let mut step = Step {
+                to_coord: coord,
+                in_direction,
+            };
+            for _ in 0..length {
+                path.prepend(step);
+                step.to_coord = step.from_coord();
+            }
+            coord = step.to_coord;
+            cell = self.grid.get_checked(coord);
+            debug_assert_eq!(
+                cell.count, self.count,
+                "path includes cell not visited in latest search"
+            );
+        }
+    }
+
+    pub fn first_direction_towards(&self, end: Coord) -> Option<Direction> {
+        let mut cell = self.grid.get(end).expect("path end out of bounds");
+        debug_assert_eq!(cell.count, self.count, "path end not visited in latest search");
+        let mut coord = end;
+        let mut ret = None;
+        while let Some(Jump { in_direction, length }) = cell.jump {
+            coord -= in_direction.coord() * length as i32;
+            cell = self.grid.get_checked(coord);
+            debug_assert_eq!(
+                cell.count, self.count,
+                "path includes cell not visited in latest search"
+            );
+            ret = Some(in_direction);
+        }
+        ret
+    }
+}
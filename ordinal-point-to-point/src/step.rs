@@ -0,0 +1,23 @@
+use coord_2d::Coord;
+use direction::Direction;
+#[cfg(feature = "serialize")]
+use serde::{Deserialize, Serialize};
+
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub to_coord: Coord,
+    pub in_direction: Direction,
+}
+
+impl Step {
+    pub fn forward(&self) -> Self {
+        Self {
+            to_coord: self.to_coord + self.in_direction.coord(),
+            in_direction: self.in_direction,
+        }
+    }
+    pub fn from_coord(&self) -> Coord {
+        self.to_coord - self.in_direction.coord()
+    }
+}